@@ -13,6 +13,9 @@ pub struct KernelLaunchInfo {
     pub kernel_region_phys_end: u64,
     pub heap_area_phys_start: u64, // Start of trailing heap area within the physical memory region.
     pub kernel_region_virt_start: u64,
+    /// Offset of the kernel's virtual load address relative to its link
+    /// address. Zero if the kernel was not relocated.
+    pub kernel_slide: u64,
     pub heap_area_virt_start: u64, // Start of virtual heap area mapping.
     pub kernel_elf_stage2_virt_start: u64, // Virtual address of kernel ELF in Stage2 mapping.
     pub kernel_elf_stage2_virt_end: u64,
@@ -16,7 +16,11 @@ fn main() {
     println!("cargo:rustc-link-arg-bin=svsm=--build-id=none");
     println!("cargo:rustc-link-arg-bin=svsm=--no-relax");
     println!("cargo:rustc-link-arg-bin=svsm=-Tkernel/src/svsm.lds");
-    println!("cargo:rustc-link-arg-bin=svsm=-no-pie");
+    // Link as PIE so that stage2 can relocate the kernel to a random base.
+    // The exception table in .text needs run-time relocations as well.
+    println!("cargo:rustc-link-arg-bin=svsm=-pie");
+    println!("cargo:rustc-link-arg-bin=svsm=--no-dynamic-linker");
+    println!("cargo:rustc-link-arg-bin=svsm=-znotext");
 
     // Extra linker args for tests.
     println!("cargo:rerun-if-env-changed=LINK_TEST");
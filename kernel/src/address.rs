@@ -188,6 +188,11 @@ impl VirtAddr {
     pub const fn const_add(&self, offset: usize) -> Self {
         VirtAddr::new(self.0 + offset)
     }
+
+    // Same as Address::bits(), but usable in const contexts.
+    pub const fn const_bits(&self) -> InnerAddr {
+        self.0
+    }
 }
 
 impl fmt::Display for VirtAddr {
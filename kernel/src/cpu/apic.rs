@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::cpu::ghcb::current_ghcb;
+use crate::cpu::idt::common::{SPURIOUS_VECTOR, TIMER_VECTOR};
+use crate::error::SvsmError;
+use crate::time::{delay, tsc_khz, Duration, Instant, TIMER_TICK_NS};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// APIC base MSR
+pub const MSR_APIC_BASE: u32 = 0x1b;
+/// xAPIC global enable bit in the APIC base MSR
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// x2APIC mode enable bit in the APIC base MSR
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+
+// x2APIC register MSRs
+pub const X2APIC_ID: u32 = 0x802;
+pub const X2APIC_VERSION: u32 = 0x803;
+pub const X2APIC_TPR: u32 = 0x808;
+pub const X2APIC_PPR: u32 = 0x80a;
+pub const X2APIC_EOI: u32 = 0x80b;
+pub const X2APIC_LDR: u32 = 0x80d;
+pub const X2APIC_SPIV: u32 = 0x80f;
+pub const X2APIC_ISR: u32 = 0x810;
+pub const X2APIC_TMR: u32 = 0x818;
+pub const X2APIC_IRR: u32 = 0x820;
+pub const X2APIC_ESR: u32 = 0x828;
+pub const X2APIC_ICR: u32 = 0x830;
+pub const X2APIC_LVT_TIMER: u32 = 0x832;
+pub const X2APIC_TMICT: u32 = 0x838;
+pub const X2APIC_TMCCT: u32 = 0x839;
+pub const X2APIC_TDCR: u32 = 0x83e;
+pub const X2APIC_SELF_IPI: u32 = 0x83f;
+
+/// APIC software-enable bit in the spurious interrupt vector register
+const APIC_SPIV_SW_ENABLE: u64 = 1 << 8;
+/// Periodic mode for the LVT timer entry
+const APIC_LVT_TIMER_PERIODIC: u64 = 1 << 17;
+/// Mask bit of LVT entries
+const APIC_LVT_MASKED: u64 = 1 << 16;
+/// Timer divide configuration value for a divisor of 1
+const APIC_TDCR_DIV_1: u64 = 0xb;
+/// Level bit of the ICR, must be set for fixed interrupts
+const APIC_ICR_LEVEL_ASSERT: u64 = 1 << 14;
+/// Shift of the destination field in the x2APIC ICR
+const APIC_ICR_DEST_SHIFT: u64 = 32;
+
+/// Time the APIC timer is measured against the clock during calibration
+const APIC_TIMER_CALIBRATE: Duration = Duration::from_millis(10);
+
+/// Number of APIC timer counts per timer tick used when the APIC timer can
+/// not be calibrated. This assumes the 1GHz APIC bus frequency used by KVM.
+const APIC_TIMER_FALLBACK_COUNT: u32 = 1_000_000;
+
+/// Number of APIC timer counts in a timer tick, zero until the APIC timer
+/// is calibrated
+static APIC_TIMER_TICK_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// Accesses to the x2APIC MSRs are intercepted by the hypervisor. Use the
+// GHCB MSR exit directly instead of taking a #VC exception for every access.
+
+fn apic_read(reg: u32) -> Result<u64, SvsmError> {
+    current_ghcb().rdmsr(reg)
+}
+
+fn apic_write(reg: u32, value: u64) -> Result<(), SvsmError> {
+    current_ghcb().wrmsr(reg, value)
+}
+
+/// Switch the local APIC of the current CPU into x2APIC mode and software
+/// enable it.
+pub fn x2apic_enable() -> Result<(), SvsmError> {
+    let base = apic_read(MSR_APIC_BASE)?;
+    if (base & APIC_BASE_X2APIC) == 0 {
+        // Enabling x2APIC mode requires the xAPIC to be globally enabled
+        // first.
+        apic_write(MSR_APIC_BASE, base | APIC_BASE_ENABLE)?;
+        apic_write(MSR_APIC_BASE, base | APIC_BASE_ENABLE | APIC_BASE_X2APIC)?;
+    }
+
+    apic_write(X2APIC_SPIV, APIC_SPIV_SW_ENABLE | SPURIOUS_VECTOR as u64)
+}
+
+/// Signal end-of-interrupt to the local APIC of the current CPU.
+pub fn apic_eoi() {
+    apic_write(X2APIC_EOI, 0).expect("Failed to signal APIC EOI");
+}
+
+/// Send a fixed, edge-triggered interrupt to a single CPU.
+///
+/// # Arguments
+///
+/// * `apic_id` - Physical x2APIC ID of the target CPU
+/// * `vector` - Interrupt vector to raise on the target CPU
+pub fn apic_send_ipi(apic_id: u32, vector: u8) -> Result<(), SvsmError> {
+    let icr =
+        (u64::from(apic_id) << APIC_ICR_DEST_SHIFT) | APIC_ICR_LEVEL_ASSERT | u64::from(vector);
+    apic_write(X2APIC_ICR, icr)
+}
+
+/// Start the local APIC timer of the current CPU in periodic mode.
+///
+/// # Arguments
+///
+/// * `count` - Number of APIC timer counts between two timer interrupts.
+pub fn apic_timer_start(count: u32) -> Result<(), SvsmError> {
+    apic_write(X2APIC_TDCR, APIC_TDCR_DIV_1)?;
+    apic_write(
+        X2APIC_LVT_TIMER,
+        APIC_LVT_TIMER_PERIODIC | TIMER_VECTOR as u64,
+    )?;
+    apic_write(X2APIC_TMICT, count as u64)
+}
+
+/// Stop the local APIC timer of the current CPU.
+pub fn apic_timer_stop() -> Result<(), SvsmError> {
+    apic_write(X2APIC_LVT_TIMER, APIC_LVT_MASKED | TIMER_VECTOR as u64)?;
+    apic_write(X2APIC_TMICT, 0)
+}
+
+/// Returns the current count of the local APIC timer.
+pub fn apic_timer_current_count() -> Result<u32, SvsmError> {
+    Ok(apic_read(X2APIC_TMCCT)? as u32)
+}
+
+/// Measures the number of APIC timer counts in a timer tick of
+/// [`TIMER_TICK_NS`] against the monotonic clock, using a masked one-shot
+/// countdown of the local APIC timer of the current CPU.
+fn apic_timer_calibrate() -> Result<u32, SvsmError> {
+    if tsc_khz() == 0 {
+        return Err(SvsmError::Clock);
+    }
+
+    apic_write(X2APIC_TDCR, APIC_TDCR_DIV_1)?;
+    apic_write(X2APIC_LVT_TIMER, APIC_LVT_MASKED | TIMER_VECTOR as u64)?;
+    apic_write(X2APIC_TMICT, u64::from(u32::MAX))?;
+
+    let start_count = apic_timer_current_count()?;
+    let start = Instant::now();
+    delay(APIC_TIMER_CALIBRATE);
+    let end_count = apic_timer_current_count()?;
+    let elapsed = start.elapsed();
+
+    apic_write(X2APIC_TMICT, 0)?;
+
+    let counts = u128::from(start_count.saturating_sub(end_count));
+    let per_tick = counts * u128::from(TIMER_TICK_NS) / elapsed.as_nanos().max(1);
+    match u32::try_from(per_tick) {
+        Ok(count) if count != 0 => Ok(count),
+        _ => Err(SvsmError::Clock),
+    }
+}
+
+/// Returns the number of APIC timer counts between two timer interrupts, so
+/// that the timer fires every [`TIMER_TICK_NS`]. The APIC timer is calibrated
+/// on the first call. When calibration fails, a 1GHz APIC bus frequency is
+/// assumed.
+pub fn apic_timer_tick_count() -> u32 {
+    let count = APIC_TIMER_TICK_COUNT.load(Ordering::Relaxed);
+    if count != 0 {
+        return count;
+    }
+
+    let count = apic_timer_calibrate().unwrap_or_else(|e| {
+        log::warn!(
+            "APIC timer calibration failed ({:?}), assuming a 1GHz APIC bus",
+            e
+        );
+        APIC_TIMER_FALLBACK_COUNT
+    });
+    log::info!("APIC timer: {} counts per timer tick", count);
+
+    APIC_TIMER_TICK_COUNT.store(count, Ordering::Relaxed);
+    count
+}
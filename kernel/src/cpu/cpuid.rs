@@ -73,6 +73,23 @@ pub fn cpuid_table(eax: u32) -> Option<CpuidResult> {
     cpuid_table_raw(eax, 0, 0, 0)
 }
 
+/// Looks up a CPUID sub-leaf regardless of the XCR0 and XSS input values of
+/// the table entry. Used for leaves whose output is computed from other
+/// entries, like the XSAVE leaf 0xd.
+pub fn cpuid_table_subleaf(eax: u32, ecx: u32) -> Option<CpuidResult> {
+    let count: usize = CPUID_PAGE.count as usize;
+
+    CPUID_PAGE.func[..count]
+        .iter()
+        .find(|func| func.eax_in == eax && func.ecx_in == ecx)
+        .map(|func| CpuidResult {
+            eax: func.eax_out,
+            ebx: func.ebx_out,
+            ecx: func.ecx_out,
+            edx: func.edx_out,
+        })
+}
+
 pub fn dump_cpuid_table() {
     let count = CPUID_PAGE.count as usize;
 
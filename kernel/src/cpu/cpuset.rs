@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::types::MAX_CPUS;
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+const CPUSET_WORDS: usize = MAX_CPUS.div_ceil(BITS_PER_WORD);
+
+/// A set of CPUs, identified by their APIC IDs. APIC IDs of
+/// [`MAX_CPUS`] and above can not be part of a set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuSet {
+    bits: [u64; CPUSET_WORDS],
+}
+
+impl CpuSet {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; CPUSET_WORDS],
+        }
+    }
+
+    /// Creates a set which contains every CPU.
+    pub const fn all() -> Self {
+        Self {
+            bits: [u64::MAX; CPUSET_WORDS],
+        }
+    }
+
+    /// Creates a set which only contains the CPU with APIC ID `apic_id`.
+    pub fn single(apic_id: u32) -> Self {
+        let mut set = Self::new();
+        set.add(apic_id);
+        set
+    }
+
+    fn position(apic_id: u32) -> Option<(usize, u64)> {
+        let idx = usize::try_from(apic_id).ok()?;
+        if idx >= MAX_CPUS {
+            return None;
+        }
+        Some((idx / BITS_PER_WORD, 1u64 << (idx % BITS_PER_WORD)))
+    }
+
+    /// Adds the CPU with APIC ID `apic_id` to the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `apic_id` is not below [`MAX_CPUS`].
+    pub fn add(&mut self, apic_id: u32) {
+        let (word, mask) = Self::position(apic_id).expect("APIC ID out of range for CpuSet");
+        self.bits[word] |= mask;
+    }
+
+    /// Removes the CPU with APIC ID `apic_id` from the set.
+    pub fn remove(&mut self, apic_id: u32) {
+        if let Some((word, mask)) = Self::position(apic_id) {
+            self.bits[word] &= !mask;
+        }
+    }
+
+    /// Returns whether the CPU with APIC ID `apic_id` is part of the set.
+    pub fn contains(&self, apic_id: u32) -> bool {
+        Self::position(apic_id).is_some_and(|(word, mask)| (self.bits[word] & mask) != 0)
+    }
+
+    /// Returns whether the set contains no CPU.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|word| *word == 0)
+    }
+}
+
+impl Default for CpuSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpuset() {
+        let mut set = CpuSet::new();
+        assert!(set.is_empty());
+
+        set.add(0);
+        set.add(65);
+        assert!(set.contains(0));
+        assert!(set.contains(65));
+        assert!(!set.contains(1));
+        assert!(!set.contains(MAX_CPUS as u32));
+
+        set.remove(0);
+        assert!(!set.contains(0));
+        assert_eq!(set, CpuSet::single(65));
+
+        set.remove(65);
+        assert!(set.is_empty());
+
+        assert!(CpuSet::all().contains(MAX_CPUS as u32 - 1));
+    }
+}
@@ -40,5 +40,8 @@ pub fn efer_init() {
         efer.insert(EFERFlags::NXE);
     }
 
+    // Needed for system calls from user-space
+    efer.insert(EFERFlags::SCE);
+
     write_efer(efer);
 }
@@ -8,6 +8,8 @@ use super::cpuid::cpuid_table;
 
 const X86_FEATURE_NX: u32 = 20;
 const X86_FEATURE_PGE: u32 = 13;
+const X86_FEATURE_PAGE1GB: u32 = 26;
+const X86_FEATURE_RDRAND: u32 = 30;
 
 pub fn cpu_has_nx() -> bool {
     let ret = cpuid_table(0x80000001);
@@ -26,3 +28,21 @@ pub fn cpu_has_pge() -> bool {
         Some(c) => (c.edx >> X86_FEATURE_PGE) & 1 == 1,
     }
 }
+
+pub fn cpu_has_1g_pages() -> bool {
+    let ret = cpuid_table(0x80000001);
+
+    match ret {
+        None => false,
+        Some(c) => (c.edx >> X86_FEATURE_PAGE1GB) & 1 == 1,
+    }
+}
+
+pub fn cpu_has_rdrand() -> bool {
+    let ret = cpuid_table(0x00000001);
+
+    match ret {
+        None => false,
+        Some(c) => (c.ecx >> X86_FEATURE_RDRAND) & 1 == 1,
+    }
+}
@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Extended (FPU/SSE/AVX) register state of tasks.
+//!
+//! The kernel itself is built without SIMD instructions, so the extended
+//! state only needs to be switched for tasks which use it. CR0.TS is set
+//! while a task without extended state runs. Its first SIMD instruction
+//! raises #NM, which allocates an [`XSaveArea`] for the task if it is
+//! allowed to use SIMD. From then on the state is saved and restored with
+//! XSAVE/XRSTOR on every task switch.
+
+use super::control_regs::{read_cr0, read_cr4, write_cr0, write_cr4, CR0Flags, CR4Flags};
+use super::cpuid::{cpuid_table, cpuid_table_subleaf};
+use crate::address::VirtAddr;
+use crate::error::SvsmError;
+use crate::mm::alloc::{allocate_pages, free_page, get_order};
+use crate::utils::immut_after_init::ImmutAfterInitCell;
+use crate::utils::zero_mem_region;
+use core::arch::asm;
+
+const X86_FEATURE_XSAVE: u32 = 26;
+
+/// CPUID leaf describing the XSAVE state components
+const CPUID_XSAVE_LEAF: u32 = 0xd;
+
+// XSAVE state components
+const XFEATURE_X87: u64 = 1 << 0;
+const XFEATURE_SSE: u64 = 1 << 1;
+const XFEATURE_AVX: u64 = 1 << 2;
+const XFEATURE_OPMASK: u64 = 1 << 5;
+const XFEATURE_ZMM_HI256: u64 = 1 << 6;
+const XFEATURE_HI16_ZMM: u64 = 1 << 7;
+
+/// State components which are managed for tasks
+const XFEATURES_SUPPORTED: u64 = XFEATURE_X87
+    | XFEATURE_SSE
+    | XFEATURE_AVX
+    | XFEATURE_OPMASK
+    | XFEATURE_ZMM_HI256
+    | XFEATURE_HI16_ZMM;
+
+/// Size of the legacy region and the XSAVE header, which come before the
+/// extended state components
+const XSAVE_LEGACY_AND_HEADER_SIZE: u32 = 512 + 64;
+
+#[derive(Clone, Copy, Debug)]
+struct XSaveInfo {
+    /// State components enabled in XCR0, zero if XSAVE is not supported
+    features: u64,
+    /// Size of the XSAVE area in standard format for `features`
+    size: usize,
+}
+
+static XSAVE_INFO: ImmutAfterInitCell<XSaveInfo> = ImmutAfterInitCell::new(XSaveInfo {
+    features: 0,
+    size: 0,
+});
+
+fn cpu_has_xsave() -> bool {
+    cpuid_table(0x00000001).is_some_and(|c| (c.ecx >> X86_FEATURE_XSAVE) & 1 == 1)
+}
+
+/// Returns the size of the standard format XSAVE area for `features`, based
+/// on the offsets and sizes of the state components in the CPUID table.
+fn xsave_size(features: u64) -> Option<u32> {
+    let mut size = XSAVE_LEGACY_AND_HEADER_SIZE;
+    for component in 2..u64::BITS {
+        if (features & (1 << component)) == 0 {
+            continue;
+        }
+        let leaf = cpuid_table_subleaf(CPUID_XSAVE_LEAF, component)?;
+        size = size.max(leaf.ebx + leaf.eax);
+    }
+    Some(size)
+}
+
+/// Determines the extended state components to manage for tasks and the
+/// size of their XSAVE areas. Must be called once on the BSP after the
+/// CPUID table is registered and before [`fpu_cpu_init()`].
+pub fn fpu_init() {
+    if !cpu_has_xsave() {
+        log::warn!("XSAVE not supported - SIMD instructions not available to tasks");
+        return;
+    }
+
+    let Some(leaf) = cpuid_table_subleaf(CPUID_XSAVE_LEAF, 0) else {
+        log::warn!("XSAVE CPUID leaf missing - SIMD instructions not available to tasks");
+        return;
+    };
+
+    let features = ((u64::from(leaf.edx) << 32) | u64::from(leaf.eax)) & XFEATURES_SUPPORTED;
+    let Some(size) = xsave_size(features) else {
+        log::warn!("XSAVE component missing in CPUID table - SIMD instructions not available");
+        return;
+    };
+
+    XSAVE_INFO.reinit(&XSaveInfo {
+        features,
+        size: size as usize,
+    });
+
+    log::info!("XSAVE features: {:#x}, area size: {} bytes", features, size);
+}
+
+/// Returns whether tasks can use SIMD instructions.
+pub fn fpu_supported() -> bool {
+    XSAVE_INFO.features != 0
+}
+
+fn xsetbv(xcr: u32, value: u64) {
+    // SAFETY: Only valid state components are enabled in XCR0.
+    unsafe {
+        asm!("xsetbv",
+             in("ecx") xcr,
+             in("eax") value as u32,
+             in("edx") (value >> 32) as u32,
+             options(att_syntax, nostack));
+    }
+}
+
+/// Enables the extended state components on the current CPU. SIMD
+/// instructions trap with #NM until [`fpu_enable_access()`] is called.
+pub fn fpu_cpu_init() {
+    let mut cr0 = read_cr0();
+    cr0.remove(CR0Flags::EM);
+    cr0.insert(CR0Flags::MP | CR0Flags::TS);
+    write_cr0(cr0);
+
+    if !fpu_supported() {
+        return;
+    }
+
+    let mut cr4 = read_cr4();
+    cr4.insert(CR4Flags::OSFXSR | CR4Flags::OSXMMEXCPT | CR4Flags::OSXSAVE);
+    write_cr4(cr4);
+
+    xsetbv(0, XSAVE_INFO.features);
+}
+
+/// Allows SIMD instructions on the current CPU by clearing CR0.TS.
+pub fn fpu_enable_access() {
+    // SAFETY: CLTS only modifies CR0.TS.
+    unsafe {
+        asm!("clts", options(att_syntax, nostack));
+    }
+}
+
+/// Makes SIMD instructions on the current CPU trap with #NM by setting
+/// CR0.TS.
+pub fn fpu_disable_access() {
+    let cr0 = read_cr0();
+    if !cr0.contains(CR0Flags::TS) {
+        write_cr0(cr0 | CR0Flags::TS);
+    }
+}
+
+/// Memory area holding the extended register state of a task in the
+/// standard XSAVE format.
+#[derive(Debug)]
+pub struct XSaveArea {
+    vaddr: VirtAddr,
+}
+
+impl XSaveArea {
+    /// Allocates an area in which all state components are in their initial
+    /// configuration.
+    ///
+    /// # Returns
+    ///
+    /// The new area, or an [`SvsmError`] if XSAVE is not supported or the
+    /// allocation failed.
+    pub fn new() -> Result<Self, SvsmError> {
+        if !fpu_supported() {
+            return Err(SvsmError::Fpu);
+        }
+
+        let size = XSAVE_INFO.size;
+        let vaddr = allocate_pages(get_order(size))?;
+        // A zeroed XSAVE header marks all components as being in their
+        // initial configuration.
+        zero_mem_region(vaddr, vaddr + size);
+        Ok(Self { vaddr })
+    }
+
+    /// Saves the extended state of the current CPU into the area. SIMD
+    /// instructions must be allowed on the CPU.
+    pub fn save(&mut self) {
+        let features = XSAVE_INFO.features;
+        // SAFETY: The area is page aligned and large enough for all enabled
+        // state components.
+        unsafe {
+            asm!("xsave64 (%rdi)",
+                 in("rdi") self.vaddr.bits(),
+                 in("eax") features as u32,
+                 in("edx") (features >> 32) as u32,
+                 options(att_syntax, nostack));
+        }
+    }
+
+    /// Loads the extended state of the current CPU from the area. SIMD
+    /// instructions must be allowed on the CPU.
+    pub fn restore(&self) {
+        let features = XSAVE_INFO.features;
+        // SAFETY: The area is page aligned and contains either a zeroed or
+        // an XSAVE-written header, so XRSTOR can not fault.
+        unsafe {
+            asm!("xrstor64 (%rdi)",
+                 in("rdi") self.vaddr.bits(),
+                 in("eax") features as u32,
+                 in("edx") (features >> 32) as u32,
+                 options(att_syntax, nostack));
+        }
+    }
+}
+
+impl Drop for XSaveArea {
+    fn drop(&mut self) {
+        free_page(self.vaddr);
+    }
+}
@@ -4,14 +4,19 @@
 //
 // Author: Jon Lange (jlange@microsoft.com)
 
+use crate::cpu::irq_state::IrqGuard;
 use crate::cpu::percpu::this_cpu_unsafe;
 use crate::sev::ghcb::GHCB;
 
 use core::ops::{Deref, DerefMut};
 
+/// Reference to the GHCB of the current CPU. Interrupts stay disabled while
+/// the reference is alive, because an interrupt handler might need the GHCB
+/// itself (e.g. to signal an APIC EOI) and would clobber an in-flight request.
 #[derive(Debug)]
 pub struct GHCBRef {
     ghcb: *mut GHCB,
+    _irq_guard: IrqGuard,
 }
 
 impl Deref for GHCBRef {
@@ -29,10 +34,14 @@ impl DerefMut for GHCBRef {
 
 pub fn current_ghcb() -> GHCBRef {
     // FIXME - Add borrow checking to GHCB references.
+    let irq_guard = IrqGuard::new();
     unsafe {
         let cpu_ptr = this_cpu_unsafe();
         let cpu = &mut *cpu_ptr;
         let ghcb = cpu.ghcb_unsafe();
-        GHCBRef { ghcb }
+        GHCBRef {
+            ghcb,
+            _irq_guard: irq_guard,
+        }
     }
 }
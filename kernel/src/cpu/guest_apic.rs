@@ -0,0 +1,650 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Emulation of the local APIC of guest vCPUs.
+//!
+//! With Alternate Injection enabled in the guest VMSA the hypervisor can not
+//! inject interrupts into the guest VMPL. The SVSM owns interrupt delivery
+//! instead: every CPU keeps the interrupt state of its guest vCPU in a
+//! [`GuestApic`] and injects the highest-priority pending interrupt into the
+//! guest VMSA before running the guest VMPL. The guest accesses its APIC
+//! registers through the APIC protocol.
+//!
+//! Interrupts sent by other CPUs are posted into the [`GuestApic`] of the
+//! target, which is then kicked with an IPI so that it leaves the guest VMPL
+//! and presents the new interrupt.
+
+use super::apic::{
+    X2APIC_EOI, X2APIC_ESR, X2APIC_ICR, X2APIC_ID, X2APIC_IRR, X2APIC_ISR, X2APIC_LDR, X2APIC_PPR,
+    X2APIC_SELF_IPI, X2APIC_SPIV, X2APIC_TMR, X2APIC_TPR, X2APIC_VERSION,
+};
+use super::ipi::smp_send_kick;
+use super::percpu::{this_cpu_shared, PERCPU_AREAS};
+use crate::error::SvsmError;
+use crate::locking::{LockGuard, SpinLock};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use cpuarch::vmsa::VMSA;
+
+/// Value of the version register: an integrated APIC with six LVT entries
+const APIC_VERSION: u64 = 0x14 | (5 << 16);
+/// Writable bits of the spurious interrupt vector register
+const APIC_SPIV_MASK: u64 = 0x3ff;
+/// APIC software-enable bit in the spurious interrupt vector register
+const APIC_SPIV_SW_ENABLE: u64 = 1 << 8;
+
+// Fields of the x2APIC interrupt command register
+const APIC_ICR_VECTOR_MASK: u64 = 0xff;
+const APIC_ICR_DELIVERY_MODE_MASK: u64 = 7 << 8;
+const APIC_ICR_DEST_LOGICAL: u64 = 1 << 11;
+const APIC_ICR_SHORTHAND_SHIFT: u64 = 18;
+const APIC_ICR_SHORTHAND_MASK: u64 = 3;
+const APIC_ICR_DEST_SHIFT: u64 = 32;
+
+/// Physical destination which addresses all CPUs
+const APIC_DEST_BROADCAST: u32 = 0xffff_ffff;
+
+// Fields of VMSA.vintr_ctrl
+const VINTR_V_TPR_MASK: u64 = 0xf;
+const VINTR_V_IRQ: u64 = 1 << 8;
+const VINTR_INT_SHADOW: u64 = 1 << 10;
+const VINTR_V_INTR_PRIO_SHIFT: u64 = 16;
+const VINTR_V_INTR_PRIO_MASK: u64 = 0xf << VINTR_V_INTR_PRIO_SHIFT;
+const VINTR_V_IGN_TPR: u64 = 1 << 20;
+const VINTR_V_INTR_VECTOR_SHIFT: u64 = 32;
+const VINTR_V_INTR_VECTOR_MASK: u64 = 0xff << VINTR_V_INTR_VECTOR_SHIFT;
+
+/// Valid bit of VMSA.event_inj and VMSA.guest_exitintinfo. A zero type field
+/// denotes an external interrupt.
+const EVENT_INJ_VALID: u64 = 1 << 31;
+const EVENT_INJ_VECTOR_MASK: u64 = 0xff;
+
+/// Interrupt flag in RFLAGS
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// Vectors below this one are reserved for exceptions
+const APIC_FIRST_VECTOR: u8 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApicError {
+    /// APIC emulation is not enabled for the guest vCPU
+    Disabled,
+    /// The register does not exist or can not be accessed this way
+    Register,
+    /// The interrupt command can not be delivered
+    Icr,
+}
+
+impl From<ApicError> for SvsmError {
+    fn from(e: ApicError) -> Self {
+        Self::Apic(e)
+    }
+}
+
+/// Returns the priority class of a vector or priority.
+const fn priority_class(prio: u8) -> u8 {
+    prio >> 4
+}
+
+/// Returns the logical x2APIC ID of the CPU with the given x2APIC ID.
+fn x2apic_ldr(apic_id: u32) -> u32 {
+    ((apic_id >> 4) << 16) | (1 << (apic_id & 0xf))
+}
+
+/// Checks whether the interrupt command `icr` sent by the CPU with x2APIC ID
+/// `sender` targets the CPU with x2APIC ID `target`.
+fn icr_targets(icr: u64, sender: u32, target: u32) -> bool {
+    match (icr >> APIC_ICR_SHORTHAND_SHIFT) & APIC_ICR_SHORTHAND_MASK {
+        // Self
+        1 => target == sender,
+        // All including self
+        2 => true,
+        // All excluding self
+        3 => target != sender,
+        // No shorthand, use the destination field
+        _ => {
+            let dest = (icr >> APIC_ICR_DEST_SHIFT) as u32;
+            if (icr & APIC_ICR_DEST_LOGICAL) != 0 {
+                let ldr = x2apic_ldr(target);
+                (dest >> 16) == (ldr >> 16) && (dest & ldr & 0xffff) != 0
+            } else {
+                dest == APIC_DEST_BROADCAST || dest == target
+            }
+        }
+    }
+}
+
+/// A set of interrupt vectors, laid out like the IRR and ISR registers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct VectorSet([u64; 4]);
+
+impl VectorSet {
+    const fn new() -> Self {
+        Self([0; 4])
+    }
+
+    fn set(&mut self, vector: u8) {
+        self.0[usize::from(vector) / 64] |= 1 << (vector % 64);
+    }
+
+    fn clear(&mut self, vector: u8) {
+        self.0[usize::from(vector) / 64] &= !(1 << (vector % 64));
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (word, bits) in self.0.iter_mut().zip(other.0) {
+            *word |= bits;
+        }
+    }
+
+    /// Returns the highest vector in the set.
+    fn highest(&self) -> Option<u8> {
+        self.0
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, word)| **word != 0)
+            .map(|(i, word)| (i * 64 + 63 - word.leading_zeros() as usize) as u8)
+    }
+
+    /// Returns the 32-bit register with the given index, as read from the
+    /// x2APIC register range of the set.
+    fn register(&self, index: usize) -> u64 {
+        (self.0[index / 2] >> ((index % 2) * 32)) & 0xffff_ffff
+    }
+}
+
+/// How a vector was handed to the guest VMSA
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Injection {
+    /// Injected through VMSA.event_inj, the vector is in service
+    Event(u8),
+    /// Offered through the virtual interrupt in VMSA.vintr_ctrl, which the
+    /// CPU delivers once the guest can take interrupts. The vector is still
+    /// pending.
+    Virtual(u8),
+}
+
+/// State of an emulated local APIC which is only changed by the CPU that
+/// runs the guest vCPU.
+#[derive(Debug)]
+struct LocalApic {
+    apic_id: u32,
+    irr: VectorSet,
+    isr: VectorSet,
+    tpr: u8,
+    spiv: u64,
+    icr: u64,
+    /// Vector handed to the guest VMSA for the current guest entry
+    injected: Option<Injection>,
+}
+
+impl LocalApic {
+    const fn new(apic_id: u32) -> Self {
+        Self {
+            apic_id,
+            irr: VectorSet::new(),
+            isr: VectorSet::new(),
+            tpr: 0,
+            spiv: 0xff,
+            icr: 0,
+            injected: None,
+        }
+    }
+
+    fn sw_enabled(&self) -> bool {
+        (self.spiv & APIC_SPIV_SW_ENABLE) != 0
+    }
+
+    /// Returns the processor priority, which is the higher one of the task
+    /// priority and the priority class of the highest vector in service.
+    fn ppr(&self) -> u8 {
+        let isrv = self.isr.highest().unwrap_or(0);
+        if priority_class(self.tpr) >= priority_class(isrv) {
+            self.tpr
+        } else {
+            isrv & 0xf0
+        }
+    }
+
+    /// Updates the task priority from VMSA.vintr_ctrl, where the guest
+    /// changes it with writes to CR8.
+    fn sync_tpr(&mut self, vmsa: &VMSA) {
+        let v_tpr = (vmsa.vintr_ctrl & VINTR_V_TPR_MASK) as u8;
+        if v_tpr != priority_class(self.tpr) {
+            self.tpr = v_tpr << 4;
+        }
+    }
+
+    fn set_tpr(&mut self, tpr: u8, vmsa: &mut VMSA) {
+        self.tpr = tpr;
+        vmsa.vintr_ctrl = (vmsa.vintr_ctrl & !VINTR_V_TPR_MASK) | u64::from(priority_class(tpr));
+    }
+
+    fn accept(&mut self, vector: u8) {
+        self.irr.set(vector);
+    }
+
+    fn eoi(&mut self) {
+        if let Some(vector) = self.isr.highest() {
+            self.isr.clear(vector);
+        }
+    }
+
+    /// Hands the highest pending vector to the guest VMSA if its priority
+    /// is above the processor priority.
+    fn present(&mut self, vmsa: &mut VMSA) {
+        if !self.sw_enabled() || self.injected.is_some() {
+            return;
+        }
+
+        let Some(vector) = self.irr.highest() else {
+            return;
+        };
+        if priority_class(vector) <= priority_class(self.ppr()) {
+            return;
+        }
+
+        if (vmsa.rflags & RFLAGS_IF) != 0 && (vmsa.vintr_ctrl & VINTR_INT_SHADOW) == 0 {
+            vmsa.event_inj = EVENT_INJ_VALID | u64::from(vector);
+            self.irr.clear(vector);
+            self.isr.set(vector);
+            self.injected = Some(Injection::Event(vector));
+        } else {
+            // The guest can not take the interrupt right now. Let the CPU
+            // deliver it once the guest enables interrupts, honoring the
+            // task priority the guest sets in the meantime.
+            vmsa.vintr_ctrl = (vmsa.vintr_ctrl
+                & !(VINTR_V_INTR_PRIO_MASK | VINTR_V_INTR_VECTOR_MASK | VINTR_V_IGN_TPR))
+                | VINTR_V_IRQ
+                | (u64::from(priority_class(vector)) << VINTR_V_INTR_PRIO_SHIFT)
+                | (u64::from(vector) << VINTR_V_INTR_VECTOR_SHIFT);
+            self.injected = Some(Injection::Virtual(vector));
+        }
+    }
+
+    /// Checks whether the vector handed to the guest VMSA was delivered
+    /// while the guest VMPL ran, and updates IRR and ISR accordingly.
+    fn check_delivered(&mut self, vmsa: &mut VMSA) {
+        match self.injected.take() {
+            None => {}
+            Some(Injection::Event(vector)) => {
+                // The event is still pending when the guest did not run or
+                // the delivery was interrupted by an exit.
+                let exitintinfo = vmsa.guest_exitintinfo;
+                let pending = (vmsa.event_inj & EVENT_INJ_VALID) != 0
+                    || ((exitintinfo & EVENT_INJ_VALID) != 0
+                        && (exitintinfo & EVENT_INJ_VECTOR_MASK) == u64::from(vector));
+                vmsa.event_inj = 0;
+                if pending {
+                    self.isr.clear(vector);
+                    self.irr.set(vector);
+                }
+            }
+            Some(Injection::Virtual(vector)) => {
+                if (vmsa.vintr_ctrl & VINTR_V_IRQ) == 0 {
+                    // The CPU cleared V_IRQ when delivering the interrupt
+                    self.irr.clear(vector);
+                    self.isr.set(vector);
+                } else {
+                    // Withdraw the offer, so that a higher-priority vector
+                    // can be presented with the next guest entry.
+                    vmsa.vintr_ctrl &= !VINTR_V_IRQ;
+                }
+            }
+        }
+    }
+
+    fn read_register(&mut self, reg: u32, vmsa: &VMSA) -> Result<u64, ApicError> {
+        self.sync_tpr(vmsa);
+        let value = match reg {
+            X2APIC_ID => u64::from(self.apic_id),
+            X2APIC_VERSION => APIC_VERSION,
+            X2APIC_TPR => u64::from(self.tpr),
+            X2APIC_PPR => u64::from(self.ppr()),
+            X2APIC_LDR => u64::from(x2apic_ldr(self.apic_id)),
+            X2APIC_SPIV => self.spiv,
+            X2APIC_ISR..=0x817 => self.isr.register((reg - X2APIC_ISR) as usize),
+            // All interrupts are edge-triggered
+            X2APIC_TMR..=0x81f => 0,
+            X2APIC_IRR..=0x827 => self.irr.register((reg - X2APIC_IRR) as usize),
+            X2APIC_ESR => 0,
+            X2APIC_ICR => self.icr,
+            _ => return Err(ApicError::Register),
+        };
+        Ok(value)
+    }
+
+    /// Emulates a register write. Returns the interrupt command to send when
+    /// the ICR was written.
+    fn write_register(
+        &mut self,
+        reg: u32,
+        value: u64,
+        vmsa: &mut VMSA,
+    ) -> Result<Option<u64>, ApicError> {
+        match reg {
+            X2APIC_TPR => self.set_tpr(value as u8, vmsa),
+            X2APIC_EOI if value == 0 => self.eoi(),
+            X2APIC_SPIV => self.spiv = value & APIC_SPIV_MASK,
+            X2APIC_ESR => {}
+            X2APIC_ICR => {
+                self.icr = value;
+                return Ok(Some(value));
+            }
+            X2APIC_SELF_IPI => {
+                let vector = (value & APIC_ICR_VECTOR_MASK) as u8;
+                if vector < APIC_FIRST_VECTOR {
+                    return Err(ApicError::Icr);
+                }
+                self.accept(vector);
+            }
+            _ => return Err(ApicError::Register),
+        }
+        Ok(None)
+    }
+}
+
+/// Emulated local APIC of the guest vCPU of a CPU, located in the shared
+/// per-CPU area.
+#[derive(Debug)]
+pub struct GuestApic {
+    /// Set while the guest uses APIC emulation
+    enabled: AtomicBool,
+    /// Vectors sent by other CPUs, moved to the IRR by the owning CPU
+    posted: [AtomicU64; 4],
+    /// State only changed on the owning CPU
+    local: SpinLock<LocalApic>,
+}
+
+impl GuestApic {
+    pub const fn new(apic_id: u32) -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            posted: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+            local: SpinLock::new(LocalApic::new(apic_id)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Resets the emulated APIC and starts emulation. Called on the owning
+    /// CPU.
+    pub fn enable(&self) {
+        let mut local = self.local.lock();
+        let apic_id = local.apic_id;
+        *local = LocalApic::new(apic_id);
+        for word in self.posted.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Stops emulation. Called on the owning CPU.
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Release);
+    }
+
+    /// Posts an interrupt the hypervisor signalled for the guest. Ignored
+    /// while emulation is off and for vectors reserved for exceptions.
+    pub fn post_external(&self, vector: u8) {
+        if self.is_enabled() && vector >= APIC_FIRST_VECTOR {
+            self.post(vector);
+        }
+    }
+
+    fn post(&self, vector: u8) {
+        self.posted[usize::from(vector) / 64].fetch_or(1 << (vector % 64), Ordering::Release);
+    }
+
+    fn take_posted(&self) -> VectorSet {
+        let mut set = VectorSet::new();
+        for (word, posted) in set.0.iter_mut().zip(self.posted.iter()) {
+            *word = posted.swap(0, Ordering::Acquire);
+        }
+        set
+    }
+
+    fn local_locked(&self) -> Result<LockGuard<'_, LocalApic>, SvsmError> {
+        if !self.is_enabled() {
+            return Err(ApicError::Disabled.into());
+        }
+        let mut local = self.local.lock();
+        local.irr.merge(&self.take_posted());
+        Ok(local)
+    }
+
+    /// Injects the highest-priority pending interrupt into the guest VMSA.
+    /// Called on the owning CPU before the guest VMPL runs.
+    pub fn present_interrupts(&self, vmsa: &mut VMSA) {
+        if let Ok(mut local) = self.local_locked() {
+            local.sync_tpr(vmsa);
+            local.present(vmsa);
+        }
+    }
+
+    /// Updates the interrupt state after the guest VMPL ran. Called on the
+    /// owning CPU.
+    pub fn check_delivered(&self, vmsa: &mut VMSA) {
+        self.local.lock().check_delivered(vmsa);
+    }
+
+    /// Emulates a read from the x2APIC register MSR `reg`.
+    pub fn read_register(&self, reg: u32, vmsa: &VMSA) -> Result<u64, SvsmError> {
+        Ok(self.local_locked()?.read_register(reg, vmsa)?)
+    }
+
+    /// Emulates a write to the x2APIC register MSR `reg`. Writes to the ICR
+    /// send the interrupt to the guest vCPUs of the target CPUs.
+    pub fn write_register(&self, reg: u32, value: u64, vmsa: &mut VMSA) -> Result<(), SvsmError> {
+        let icr = self.local_locked()?.write_register(reg, value, vmsa)?;
+        match icr {
+            Some(icr) => guest_apic_send_ipi(icr),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Sends the interrupt command `icr` from the guest vCPU of the current CPU.
+/// Only fixed interrupts are supported. Target CPUs without APIC emulation
+/// are skipped.
+fn guest_apic_send_ipi(icr: u64) -> Result<(), SvsmError> {
+    let vector = (icr & APIC_ICR_VECTOR_MASK) as u8;
+    if (icr & APIC_ICR_DELIVERY_MODE_MASK) != 0 || vector < APIC_FIRST_VECTOR {
+        return Err(ApicError::Icr.into());
+    }
+
+    let sender = this_cpu_shared().apic_id();
+    for cpu in PERCPU_AREAS
+        .iter()
+        .filter(|cpu| icr_targets(icr, sender, cpu.apic_id()))
+    {
+        let apic = cpu.guest_apic();
+        if !apic.is_enabled() {
+            continue;
+        }
+        apic.post(vector);
+        if cpu.apic_id() != sender {
+            smp_send_kick(cpu.apic_id());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_apic() -> LocalApic {
+        let mut apic = LocalApic::new(1);
+        apic.spiv = APIC_SPIV_SW_ENABLE | 0xff;
+        apic
+    }
+
+    #[test]
+    fn test_vector_set() {
+        let mut set = VectorSet::new();
+        assert_eq!(set.highest(), None);
+
+        set.set(0x31);
+        set.set(0xa0);
+        assert_eq!(set.highest(), Some(0xa0));
+        assert_eq!(set.register(1), 1 << 0x11);
+        assert_eq!(set.register(5), 1);
+
+        set.clear(0xa0);
+        assert_eq!(set.highest(), Some(0x31));
+    }
+
+    #[test]
+    fn test_ppr() {
+        let mut apic = enabled_apic();
+        assert_eq!(apic.ppr(), 0);
+
+        apic.tpr = 0x45;
+        assert_eq!(apic.ppr(), 0x45);
+
+        apic.isr.set(0x61);
+        assert_eq!(apic.ppr(), 0x60);
+
+        apic.eoi();
+        assert_eq!(apic.ppr(), 0x45);
+    }
+
+    #[test]
+    fn test_inject_event() {
+        let mut apic = enabled_apic();
+        let mut vmsa = VMSA {
+            rflags: RFLAGS_IF,
+            ..Default::default()
+        };
+
+        apic.accept(0x30);
+        apic.accept(0x50);
+        apic.present(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, EVENT_INJ_VALID | 0x50);
+        assert_eq!(apic.isr.highest(), Some(0x50));
+
+        // The event was delivered when the guest ran
+        vmsa.event_inj = 0;
+        apic.check_delivered(&mut vmsa);
+        assert_eq!(apic.injected, None);
+
+        // The lower vector is masked by the vector in service
+        apic.present(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, 0);
+
+        apic.eoi();
+        apic.present(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, EVENT_INJ_VALID | 0x30);
+
+        // The guest did not run, the vector is pending again
+        apic.check_delivered(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, 0);
+        assert_eq!(apic.isr.highest(), None);
+        assert_eq!(apic.irr.highest(), Some(0x30));
+    }
+
+    #[test]
+    fn test_inject_virtual() {
+        let mut apic = enabled_apic();
+        let mut vmsa = VMSA::default();
+
+        // Interrupts are disabled in the guest
+        apic.accept(0x41);
+        apic.present(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, 0);
+        assert_eq!(
+            { vmsa.vintr_ctrl },
+            VINTR_V_IRQ | (4 << VINTR_V_INTR_PRIO_SHIFT) | (0x41 << VINTR_V_INTR_VECTOR_SHIFT)
+        );
+
+        // Not taken by the guest, the offer is withdrawn
+        apic.check_delivered(&mut vmsa);
+        assert_eq!(vmsa.vintr_ctrl & VINTR_V_IRQ, 0);
+        assert_eq!(apic.irr.highest(), Some(0x41));
+
+        // Taken by the guest
+        apic.present(&mut vmsa);
+        vmsa.vintr_ctrl &= !VINTR_V_IRQ;
+        apic.check_delivered(&mut vmsa);
+        assert_eq!(apic.irr.highest(), None);
+        assert_eq!(apic.isr.highest(), Some(0x41));
+    }
+
+    #[test]
+    fn test_tpr() {
+        let mut apic = enabled_apic();
+        let mut vmsa = VMSA {
+            rflags: RFLAGS_IF,
+            ..Default::default()
+        };
+
+        apic.write_register(X2APIC_TPR, 0x52, &mut vmsa).unwrap();
+        assert_eq!(vmsa.vintr_ctrl & VINTR_V_TPR_MASK, 5);
+        assert_eq!(apic.read_register(X2APIC_TPR, &vmsa), Ok(0x52));
+
+        apic.accept(0x55);
+        apic.present(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, 0);
+
+        // The guest lowered the priority with a write to CR8
+        vmsa.vintr_ctrl &= !VINTR_V_TPR_MASK;
+        apic.sync_tpr(&vmsa);
+        apic.present(&mut vmsa);
+        assert_eq!({ vmsa.event_inj }, EVENT_INJ_VALID | 0x55);
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut apic = enabled_apic();
+        let mut vmsa = VMSA::default();
+
+        assert_eq!(apic.read_register(X2APIC_ID, &vmsa), Ok(1));
+        assert_eq!(apic.read_register(X2APIC_LDR, &vmsa), Ok(2));
+        assert_eq!(apic.read_register(0x800, &vmsa), Err(ApicError::Register));
+
+        apic.write_register(X2APIC_SELF_IPI, 0x80, &mut vmsa)
+            .unwrap();
+        assert_eq!(apic.read_register(X2APIC_IRR + 4, &vmsa), Ok(1));
+        assert_eq!(
+            apic.write_register(X2APIC_SELF_IPI, 0x2, &mut vmsa),
+            Err(ApicError::Icr)
+        );
+        assert_eq!(
+            apic.write_register(X2APIC_ICR, 0x3_0000_0040, &mut vmsa),
+            Ok(Some(0x3_0000_0040))
+        );
+        assert_eq!(apic.read_register(X2APIC_ICR, &vmsa), Ok(0x3_0000_0040));
+    }
+
+    #[test]
+    fn test_icr_targets() {
+        let physical = |dest: u64| (dest << APIC_ICR_DEST_SHIFT) | 0x40;
+        assert!(icr_targets(physical(3), 0, 3));
+        assert!(!icr_targets(physical(3), 0, 2));
+        assert!(icr_targets(physical(0xffff_ffff), 0, 2));
+
+        // Cluster 1, CPUs 0x11 and 0x13
+        let logical = physical(0x1_000a) | APIC_ICR_DEST_LOGICAL;
+        assert!(icr_targets(logical, 0, 0x11));
+        assert!(icr_targets(logical, 0, 0x13));
+        assert!(!icr_targets(logical, 0, 0x12));
+        assert!(!icr_targets(logical, 0, 0x01));
+
+        let shorthand = |sh: u64| (sh << APIC_ICR_SHORTHAND_SHIFT) | 0x40;
+        assert!(icr_targets(shorthand(1), 2, 2));
+        assert!(!icr_targets(shorthand(1), 2, 3));
+        assert!(icr_targets(shorthand(2), 2, 2));
+        assert!(!icr_targets(shorthand(3), 2, 2));
+        assert!(icr_targets(shorthand(3), 2, 3));
+    }
+}
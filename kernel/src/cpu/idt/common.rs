@@ -36,6 +36,11 @@ pub const HV_VECTOR: usize = 28;
 pub const VC_VECTOR: usize = 29;
 pub const SX_VECTOR: usize = 30;
 
+// External interrupt vectors
+pub const TIMER_VECTOR: usize = 32;
+pub const IPI_VECTOR: usize = 33;
+pub const SPURIOUS_VECTOR: usize = 255;
+
 pub const PF_ERROR_WRITE: usize = 2;
 
 #[repr(C, packed)]
@@ -4,20 +4,24 @@
 //
 // Authors: Joerg Roedel <jroedel@suse.de>
 
+use super::super::apic::apic_eoi;
 use super::super::control_regs::read_cr2;
-use super::super::extable::handle_exception_table;
-use super::super::percpu::this_cpu;
+use super::super::extable::{handle_exception_table, in_exception_table};
+use super::super::ipi::handle_ipi;
+use super::super::percpu::{current_task, this_cpu};
 use super::super::tss::IST_DF;
-use super::super::vc::handle_vc_exception;
+use super::super::vc::{handle_user_vc_exception, handle_vc_exception};
 use super::common::PF_ERROR_WRITE;
 use super::common::{
     idt_mut, IdtEntry, AC_VECTOR, BP_VECTOR, BR_VECTOR, CP_VECTOR, DB_VECTOR, DE_VECTOR, DF_VECTOR,
-    GP_VECTOR, HV_VECTOR, MCE_VECTOR, MF_VECTOR, NMI_VECTOR, NM_VECTOR, NP_VECTOR, OF_VECTOR,
-    PF_VECTOR, SS_VECTOR, SX_VECTOR, TS_VECTOR, UD_VECTOR, VC_VECTOR, XF_VECTOR,
+    GP_VECTOR, HV_VECTOR, IPI_VECTOR, MCE_VECTOR, MF_VECTOR, NMI_VECTOR, NM_VECTOR, NP_VECTOR,
+    OF_VECTOR, PF_VECTOR, SPURIOUS_VECTOR, SS_VECTOR, SX_VECTOR, TIMER_VECTOR, TS_VECTOR,
+    UD_VECTOR, VC_VECTOR, XF_VECTOR,
 };
 use crate::address::VirtAddr;
 use crate::cpu::X86ExceptionContext;
 use crate::debug::gdbstub::svsm_gdbstub::handle_debug_exception;
+use crate::task::{exit_current_task, is_user_addr, schedule_tick, TASK_EXIT_KILLED};
 use core::arch::global_asm;
 
 extern "C" {
@@ -43,6 +47,9 @@ extern "C" {
     fn asm_entry_hv();
     fn asm_entry_vc();
     fn asm_entry_sx();
+    fn asm_entry_timer();
+    fn asm_entry_ipi();
+    fn asm_entry_spurious();
 }
 
 fn init_ist_vectors() {
@@ -76,6 +83,9 @@ pub fn early_idt_init() {
     idt.set_entry(HV_VECTOR, IdtEntry::entry(asm_entry_hv));
     idt.set_entry(VC_VECTOR, IdtEntry::entry(asm_entry_vc));
     idt.set_entry(SX_VECTOR, IdtEntry::entry(asm_entry_sx));
+    idt.set_entry(TIMER_VECTOR, IdtEntry::entry(asm_entry_timer));
+    idt.set_entry(IPI_VECTOR, IdtEntry::entry(asm_entry_ipi));
+    idt.set_entry(SPURIOUS_VECTOR, IdtEntry::entry(asm_entry_spurious));
     idt.load();
 }
 
@@ -84,6 +94,27 @@ pub fn idt_init() {
     init_ist_vectors();
 }
 
+/// Returns whether the exception was raised while executing at CPL 3
+fn from_user_mode(ctx: &X86ExceptionContext) -> bool {
+    (ctx.frame.cs & 3) == 3
+}
+
+/// Terminate the current user task after an exception it can not recover
+/// from.
+fn kill_user_task(ctx: &X86ExceptionContext) -> ! {
+    let vec = ctx.vector;
+    let rip = ctx.frame.rip;
+    let err = ctx.error_code;
+    log::error!(
+        "Terminating user task {} - exception {} at RIP {:#018x} error code: {:#018x}",
+        current_task().get_task_id(),
+        vec,
+        rip,
+        err
+    );
+    exit_current_task(TASK_EXIT_KILLED);
+}
+
 // Debug handler
 #[no_mangle]
 extern "C" fn ex_handler_debug(ctx: &mut X86ExceptionContext) {
@@ -96,6 +127,22 @@ extern "C" fn ex_handler_breakpoint(ctx: &mut X86ExceptionContext) {
     handle_debug_exception(ctx, BP_VECTOR);
 }
 
+// Device-Not-Available handler
+#[no_mangle]
+extern "C" fn ex_handler_device_not_available(ctx: &mut X86ExceptionContext) {
+    if let Err(e) = current_task().handle_fpu_trap() {
+        if from_user_mode(ctx) {
+            kill_user_task(ctx);
+        }
+        panic!(
+            "SIMD instruction in kernel task {} at RIP {:#018x}: {:?}",
+            current_task().get_task_id(),
+            ctx.frame.rip,
+            e
+        );
+    }
+}
+
 // Doube-Fault handler
 #[no_mangle]
 extern "C" fn ex_handler_double_fault(ctx: &mut X86ExceptionContext) {
@@ -114,6 +161,10 @@ extern "C" fn ex_handler_general_protection(ctx: &mut X86ExceptionContext) {
     let rip = ctx.frame.rip;
     let err = ctx.error_code;
 
+    if from_user_mode(ctx) {
+        kill_user_task(ctx);
+    }
+
     if !handle_exception_table(ctx) {
         panic!(
             "Unhandled General-Protection-Fault at RIP {:#018x} error code: {:#018x}",
@@ -128,12 +179,25 @@ extern "C" fn ex_handler_page_fault(ctx: &mut X86ExceptionContext) {
     let cr2 = read_cr2();
     let rip = ctx.frame.rip;
     let err = ctx.error_code;
+    let vaddr = VirtAddr::from(cr2);
+    let write = (err & PF_ERROR_WRITE) != 0;
 
-    if this_cpu()
-        .handle_pf(VirtAddr::from(cr2), (err & PF_ERROR_WRITE) != 0)
-        .is_err()
-        && !handle_exception_table(ctx)
-    {
+    if from_user_mode(ctx) {
+        if current_task().handle_user_pf(vaddr, write).is_err() {
+            kill_user_task(ctx);
+        }
+        return;
+    }
+
+    // The kernel only accesses user memory from code which is covered by the
+    // exception table, like copy_from_user().
+    let result = if is_user_addr(vaddr) && in_exception_table(VirtAddr::from(rip)) {
+        current_task().handle_user_pf(vaddr, write)
+    } else {
+        this_cpu().handle_pf(vaddr, write)
+    };
+
+    if result.is_err() && !handle_exception_table(ctx) {
         handle_debug_exception(ctx, ctx.vector);
         panic!(
             "Unhandled Page-Fault at RIP {:#018x} CR2: {:#018x} error code: {:#018x}",
@@ -145,20 +209,53 @@ extern "C" fn ex_handler_page_fault(ctx: &mut X86ExceptionContext) {
 // Hypervisor Injection handler
 #[no_mangle]
 extern "C" fn ex_handler_hypervisor_injection(_ctx: &mut X86ExceptionContext) {
-    // #HV processing is not required in the SVSM.  If a maskable
-    // interrupt occurs, it will be processed prior to the next exit.
-    // There are no NMI sources, and #MC cannot be handled anyway
-    // and can safely be ignored.
+    // #HV processing is not required in the SVSM.  Events in the #HV
+    // doorbell page are processed by the request loop prior to the next
+    // exit, where the GHCB can be used to signal EOIs.  There are no NMI
+    // sources, and #MC cannot be handled anyway and can safely be ignored.
 }
 
 // VMM Communication handler
 #[no_mangle]
 extern "C" fn ex_handler_vmm_communication(ctx: &mut X86ExceptionContext) {
+    if from_user_mode(ctx) {
+        if handle_user_vc_exception(ctx).is_err() {
+            kill_user_task(ctx);
+        }
+        return;
+    }
+
     handle_vc_exception(ctx);
 }
 
+// APIC Timer handler
+#[no_mangle]
+extern "C" fn ex_handler_timer(_ctx: &mut X86ExceptionContext) {
+    // Signal EOI first, as the timer tick might switch to another task.
+    apic_eoi();
+    schedule_tick();
+}
+
+// Inter-Processor Interrupt handler
+#[no_mangle]
+extern "C" fn ex_handler_ipi(_ctx: &mut X86ExceptionContext) {
+    // Signal EOI first, as handling the IPI might switch to another task.
+    apic_eoi();
+    handle_ipi();
+}
+
+// APIC Spurious Interrupt handler
+#[no_mangle]
+extern "C" fn ex_handler_spurious(_ctx: &mut X86ExceptionContext) {
+    // Spurious interrupts must not be acknowledged with an EOI.
+}
+
 #[no_mangle]
 pub extern "C" fn ex_handler_panic(ctx: &mut X86ExceptionContext) {
+    if from_user_mode(ctx) {
+        kill_user_task(ctx);
+    }
+
     let vec = ctx.vector;
     let rip = ctx.frame.rip;
     let err = ctx.error_code;
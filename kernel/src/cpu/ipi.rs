@@ -0,0 +1,349 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Inter-Processor Interrupts.
+//!
+//! Every CPU has an [`IpiState`] in its shared per-CPU area, which holds a
+//! queue of function calls requested by other CPUs and a reschedule flag.
+//! Senders fill the state of the target CPU and raise [`IPI_VECTOR`] on it
+//! through the x2APIC ICR. The IPI handler runs the queued functions with
+//! interrupts disabled and asks the scheduler to check for runnable tasks.
+
+extern crate alloc;
+
+use super::apic::apic_send_ipi;
+use super::idt::common::IPI_VECTOR;
+use super::irq_state::IrqGuard;
+use super::percpu::{this_cpu_shared, PerCpuShared, PERCPU_AREAS};
+use crate::error::SvsmError;
+use crate::locking::SpinLock;
+use crate::task::{preempt_disable, preempt_enable, reschedule};
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A function call requested by another CPU. The requesting CPU waits until
+/// every target CPU completed the call, so the function and the counter
+/// outlive the request even though their lifetimes are erased here.
+#[derive(Debug)]
+struct IpiCall {
+    /// Calls the function behind `data`
+    trampoline: unsafe fn(*const ()),
+    /// Pointer to the function to call
+    data: *const (),
+    /// Number of CPUs which did not complete the call yet
+    pending: *const AtomicUsize,
+}
+
+// SAFETY: The function is Sync and the counter is atomic, so both can be
+// used from the target CPU while the requesting CPU waits.
+unsafe impl Send for IpiCall {}
+
+/// # Safety
+///
+/// `data` must point to a live `F`.
+unsafe fn call_trampoline<F: Fn()>(data: *const ()) {
+    (*data.cast::<F>())();
+}
+
+impl IpiCall {
+    /// Runs the function and signals completion to the requesting CPU.
+    ///
+    /// # Safety
+    ///
+    /// The requesting CPU must still wait for the call to complete.
+    unsafe fn run(self) {
+        (self.trampoline)(self.data);
+        (*self.pending).fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Per-CPU IPI state, located in the shared per-CPU area
+#[derive(Debug)]
+pub struct IpiState {
+    /// Set once the local APIC of the CPU accepts IPIs
+    enabled: AtomicBool,
+    /// Set when the CPU needs to check its run-queue for new tasks
+    resched: AtomicBool,
+    /// Function calls requested by other CPUs. Only locked with interrupts
+    /// disabled, as the IPI handler takes the lock as well.
+    calls: SpinLock<Vec<IpiCall>>,
+}
+
+impl IpiState {
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            resched: AtomicBool::new(false),
+            calls: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Marks the CPU as ready to receive IPIs. Called on the CPU itself
+    /// after its local APIC was enabled.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+}
+
+impl Default for IpiState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn send_ipi(cpu: &PerCpuShared) -> Result<(), SvsmError> {
+    if !cpu.ipi().is_enabled() {
+        return Err(SvsmError::Ipi);
+    }
+    apic_send_ipi(cpu.apic_id(), IPI_VECTOR as u8)
+}
+
+/// Runs the function calls other CPUs queued for the current CPU. Must be
+/// called with interrupts disabled.
+fn run_ipi_calls(cpu: &PerCpuShared) {
+    let calls = mem::take(&mut *cpu.ipi().calls.lock());
+    for call in calls {
+        // SAFETY: The requesting CPU waits until the call completed.
+        unsafe { call.run() };
+    }
+}
+
+/// Handles an IPI on the current CPU. Called from the IPI interrupt handler
+/// with interrupts disabled.
+pub fn handle_ipi() {
+    let cpu = this_cpu_shared();
+    run_ipi_calls(cpu);
+    if cpu.ipi().resched.swap(false, Ordering::Acquire) {
+        reschedule();
+    }
+}
+
+/// Removes the call which signals completion through `pending` from the
+/// queue of `cpu`.
+///
+/// # Returns
+///
+/// `true` if the call was still queued, `false` if the CPU already took it.
+fn dequeue_call(cpu: &PerCpuShared, pending: &AtomicUsize) -> bool {
+    let _irq_guard = IrqGuard::new();
+    let mut calls = cpu.ipi().calls.lock();
+    let queued = calls.len();
+    calls.retain(|call| !ptr::eq(call.pending, pending));
+    calls.len() != queued
+}
+
+/// Waits until all calls signalling completion through `pending` ran,
+/// while handling calls queued for the current CPU.
+fn wait_for_calls(pending: &AtomicUsize) {
+    let this = this_cpu_shared();
+    while pending.load(Ordering::Acquire) != 0 {
+        let irq_guard = IrqGuard::new();
+        run_ipi_calls(this);
+        drop(irq_guard);
+        spin_loop();
+    }
+}
+
+/// Queues `func` on all CPUs in `targets` and waits until each of them ran
+/// it. Calls queued for the current CPU by other CPUs are handled while
+/// waiting, so that two CPUs calling each other can not deadlock. Must be
+/// called with preemption disabled.
+///
+/// When sending an IPI fails, the call is withdrawn from that CPU and no
+/// further CPUs are called. The function returns the error after the CPUs
+/// which were called already completed the function.
+fn call_function_many<F>(targets: &[&'static PerCpuShared], func: &F) -> Result<(), SvsmError>
+where
+    F: Fn() + Sync,
+{
+    if let Some(cpu) = targets.iter().find(|cpu| !cpu.ipi().is_enabled()) {
+        log::warn!("CPU {} does not accept IPIs", cpu.apic_id());
+        return Err(SvsmError::Ipi);
+    }
+
+    let pending = AtomicUsize::new(0);
+    let mut result = Ok(());
+
+    for cpu in targets {
+        pending.fetch_add(1, Ordering::Relaxed);
+        {
+            let _irq_guard = IrqGuard::new();
+            cpu.ipi().calls.lock().push(IpiCall {
+                trampoline: call_trampoline::<F>,
+                data: ptr::from_ref(func).cast(),
+                pending: &pending,
+            });
+        }
+        if let Err(e) = send_ipi(cpu) {
+            log::warn!("Failed to send IPI to CPU {}: {:?}", cpu.apic_id(), e);
+            // A CPU which took the call already completes it like the others
+            if dequeue_call(cpu, &pending) {
+                pending.fetch_sub(1, Ordering::Relaxed);
+            }
+            result = Err(e);
+            break;
+        }
+    }
+
+    // The function must stay alive until every queued call ran
+    wait_for_calls(&pending);
+
+    result
+}
+
+/// Runs `func` on the CPU with APIC ID `apic_id` and waits for it to
+/// complete. The function runs in interrupt context on the target CPU and
+/// must not sleep. On the current CPU it is called directly.
+///
+/// # Returns
+///
+/// `Ok(())` after the function ran, or `Err(SvsmError::Ipi)` when the target
+/// CPU does not exist or does not accept IPIs.
+pub fn smp_call_function<F>(apic_id: u32, func: F) -> Result<(), SvsmError>
+where
+    F: Fn() + Sync,
+{
+    let cpu = PERCPU_AREAS.get(apic_id).ok_or(SvsmError::Ipi)?;
+
+    preempt_disable();
+    let result = if apic_id == this_cpu_shared().apic_id() {
+        let _irq_guard = IrqGuard::new();
+        func();
+        Ok(())
+    } else {
+        call_function_many(&[cpu], &func)
+    };
+    preempt_enable();
+
+    result
+}
+
+/// Runs `func` on every online CPU, including the current one, and waits
+/// until all of them completed it. The function must not sleep.
+///
+/// # Returns
+///
+/// `Ok(())` after the function ran everywhere, or an [`SvsmError`] when an
+/// online CPU does not accept IPIs or sending an IPI failed. The function
+/// did not run on the current CPU in that case, but might have run on some
+/// of the other CPUs.
+pub fn smp_call_function_all<F>(func: F) -> Result<(), SvsmError>
+where
+    F: Fn() + Sync,
+{
+    preempt_disable();
+
+    let this_id = this_cpu_shared().apic_id();
+    let targets: Vec<_> = PERCPU_AREAS
+        .iter()
+        .filter(|cpu| cpu.is_online() && cpu.apic_id() != this_id)
+        .collect();
+
+    let result = call_function_many(&targets, &func);
+    if result.is_ok() {
+        let _irq_guard = IrqGuard::new();
+        func();
+    }
+
+    preempt_enable();
+    result
+}
+
+/// Interrupts the CPU with APIC ID `apic_id` without queueing any work, so
+/// that it leaves the guest VMPL and presents new guest interrupts. Nothing
+/// is sent when the CPU does not accept IPIs yet.
+pub fn smp_send_kick(apic_id: u32) {
+    let Some(cpu) = PERCPU_AREAS.get(apic_id) else {
+        return;
+    };
+
+    if !cpu.ipi().is_enabled() {
+        return;
+    }
+
+    if let Err(e) = send_ipi(cpu) {
+        log::warn!("Failed to send kick IPI to CPU {}: {:?}", apic_id, e);
+    }
+}
+
+/// Asks the CPU with APIC ID `apic_id` to check its run-queue, because a
+/// task was put on it. Nothing is sent when the CPU does not accept IPIs
+/// yet; it notices the task with its next scheduling event.
+pub fn smp_send_reschedule(apic_id: u32) {
+    let Some(cpu) = PERCPU_AREAS.get(apic_id) else {
+        return;
+    };
+
+    if !cpu.ipi().is_enabled() || cpu.ipi().resched.swap(true, Ordering::Release) {
+        // Not possible or an IPI is already on its way
+        return;
+    }
+
+    if let Err(e) = send_ipi(cpu) {
+        log::warn!("Failed to send reschedule IPI to CPU {}: {:?}", apic_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU32;
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_call_function_local() {
+        let this_id = this_cpu_shared().apic_id();
+        let ran_on = AtomicU32::new(u32::MAX);
+
+        smp_call_function(this_id, || {
+            ran_on.store(this_cpu_shared().apic_id(), Ordering::Relaxed)
+        })
+        .unwrap();
+        assert_eq!(ran_on.load(Ordering::Relaxed), this_id);
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_call_function_remote() {
+        let this_id = this_cpu_shared().apic_id();
+        let Some(target) = PERCPU_AREAS
+            .iter()
+            .find(|cpu| cpu.is_online() && cpu.apic_id() != this_id && cpu.ipi().is_enabled())
+        else {
+            log::info!("No other CPU accepts IPIs - skipping test");
+            return;
+        };
+
+        let ran_on = AtomicU32::new(u32::MAX);
+        smp_call_function(target.apic_id(), || {
+            ran_on.store(this_cpu_shared().apic_id(), Ordering::Relaxed)
+        })
+        .unwrap();
+        assert_eq!(ran_on.load(Ordering::Relaxed), target.apic_id());
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_call_function_all() {
+        let count = AtomicUsize::new(0);
+        let result = smp_call_function_all(|| {
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let online = PERCPU_AREAS.iter().filter(|cpu| cpu.is_online()).count();
+        match result {
+            Ok(()) => assert_eq!(count.load(Ordering::Relaxed), online),
+            // Some CPU did not accept the IPI, the others may have run the
+            // function anyway
+            Err(_) => assert!(count.load(Ordering::Relaxed) <= online),
+        }
+    }
+}
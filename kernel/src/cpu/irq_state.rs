@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::cpu::msr::read_flags;
+use core::arch::asm;
+
+/// Interrupt flag in RFLAGS
+pub const EFLAGS_IF: u64 = 1 << 9;
+
+/// Unconditionally disable interrupts on the current CPU.
+#[inline(always)]
+pub fn raw_irqs_disable() {
+    unsafe {
+        asm!("cli", options(att_syntax, preserves_flags, nomem, nostack));
+    }
+}
+
+/// Unconditionally enable interrupts on the current CPU.
+#[inline(always)]
+pub fn raw_irqs_enable() {
+    unsafe {
+        asm!("sti", options(att_syntax, preserves_flags, nomem, nostack));
+    }
+}
+
+/// Query whether interrupts are enabled on the current CPU.
+#[inline(always)]
+pub fn irqs_enabled() -> bool {
+    (read_flags() & EFLAGS_IF) == EFLAGS_IF
+}
+
+/// A guard which disables interrupts on the current CPU for as long as it
+/// is alive. When dropped, the previous interrupt state is restored, so
+/// guards can be nested.
+///
+/// The guard must be dropped on the CPU it was created on.
+#[derive(Debug)]
+#[must_use = "if unused interrupts will immediately be re-enabled"]
+pub struct IrqGuard {
+    /// Whether interrupts were enabled when the guard was created
+    enabled: bool,
+}
+
+impl IrqGuard {
+    pub fn new() -> Self {
+        let enabled = irqs_enabled();
+        if enabled {
+            raw_irqs_disable();
+        }
+        Self { enabled }
+    }
+}
+
+impl Default for IrqGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for IrqGuard {
+    fn drop(&mut self) {
+        if self.enabled {
+            raw_irqs_enable();
+        }
+    }
+}
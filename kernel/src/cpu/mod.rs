@@ -4,19 +4,27 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
+pub mod apic;
 pub mod control_regs;
 pub mod cpuid;
+pub mod cpuset;
 pub mod efer;
 pub mod extable;
 pub mod features;
+pub mod fpu;
 pub mod gdt;
 pub mod ghcb;
+pub mod guest_apic;
 pub mod idt;
 pub mod insn;
+pub mod ipi;
+pub mod irq_state;
 pub mod msr;
 pub mod percpu;
+pub mod rdrand;
 pub mod registers;
 pub mod smp;
+pub mod syscall;
 pub mod tlb;
 pub mod tss;
 pub mod vc;
@@ -24,5 +32,6 @@ pub mod vmsa;
 
 pub use gdt::{gdt, gdt_mut};
 pub use idt::common::X86ExceptionContext;
+pub use irq_state::IrqGuard;
 pub use registers::{X86GeneralRegs, X86InterruptFrame, X86SegmentRegs};
 pub use tlb::*;
@@ -6,7 +6,12 @@
 
 extern crate alloc;
 
+use super::fpu::fpu_cpu_init;
 use super::gdt_mut;
+use super::ghcb::current_ghcb;
+use super::guest_apic::GuestApic;
+use super::ipi::IpiState;
+use super::syscall::syscall_init;
 use super::tss::{X86Tss, IST_DF};
 use crate::address::{Address, PhysAddr, VirtAddr};
 use crate::cpu::tss::TSS_LIMIT;
@@ -14,27 +19,33 @@ use crate::cpu::vmsa::init_guest_vmsa;
 use crate::cpu::vmsa::vmsa_mut_ref_from_vaddr;
 use crate::error::SvsmError;
 use crate::locking::{LockGuard, RWLock, SpinLock};
-use crate::mm::alloc::{allocate_zeroed_page, free_page};
+use crate::mm::alloc::{allocate_zeroed_page, free_page, SlabCaches};
+use crate::mm::guestmem::GuestMapCache;
 use crate::mm::pagetable::{get_init_pgtable_locked, PTEntryFlags, PageTableRef};
 use crate::mm::virtualrange::VirtualRange;
 use crate::mm::vm::{Mapping, VMKernelStack, VMPhysMem, VMRMapping, VMReserved, VMR};
+use crate::mm::SharedBuffer;
 use crate::mm::{
     virt_to_phys, SVSM_PERCPU_BASE, SVSM_PERCPU_CAA_BASE, SVSM_PERCPU_END,
     SVSM_PERCPU_TEMP_BASE_2M, SVSM_PERCPU_TEMP_BASE_4K, SVSM_PERCPU_TEMP_END_2M,
     SVSM_PERCPU_TEMP_END_4K, SVSM_PERCPU_VMSA_BASE, SVSM_STACKS_INIT_TASK, SVSM_STACK_IST_DF_BASE,
 };
 use crate::sev::ghcb::GHCB;
+use crate::sev::hv_doorbell::HVDoorbell;
+use crate::sev::status::{sev_flags, SEVStatusFlags};
 use crate::sev::utils::RMPFlags;
 use crate::sev::vmsa::allocate_new_vmsa;
 use crate::task::{
-    schedule, schedule_task, RunQueue, Task, TaskPointer, WaitQueue, TASK_FLAG_SHARE_PT,
+    preempt_disable, preempt_enable, schedule, schedule_task, PreemptState, RunQueue, Task,
+    TaskError, TaskPointer, TaskTimer, WaitQueue, TASK_FLAG_SHARE_PT,
 };
+use crate::time::{Instant, TimerWheel};
 use crate::types::{PAGE_SHIFT, PAGE_SHIFT_2M, PAGE_SIZE, PAGE_SIZE_2M, SVSM_TR_FLAGS, SVSM_TSS};
 use crate::utils::MemoryRegion;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::cell::UnsafeCell;
-use core::mem::size_of;
+use core::cell::{OnceCell, UnsafeCell};
+use core::mem::{offset_of, size_of};
 use core::ops::{Deref, DerefMut};
 use core::ptr;
 use core::sync::atomic::{AtomicBool, Ordering};
@@ -91,6 +102,16 @@ impl PerCpuAreas {
             unsafe { ptr.as_ref().unwrap() }
         })
     }
+
+    /// Returns an iterator over the shared per-CPU data of all CPUs.
+    pub fn iter(&self) -> impl Iterator<Item = &'static PerCpuShared> + '_ {
+        // SAFETY: See get()
+        let ptr = unsafe { self.areas.get().as_ref().unwrap() };
+        ptr.iter().map(|info| {
+            let ptr = info.addr.as_ptr::<PerCpuShared>();
+            unsafe { ptr.as_ref().unwrap() }
+        })
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -193,16 +214,61 @@ impl GuestVmsaRef {
 
 #[derive(Debug)]
 pub struct PerCpuShared {
+    apic_id: u32,
+    online: AtomicBool,
     guest_vmsa: SpinLock<GuestVmsaRef>,
+
+    /// Function calls and reschedule requests from other CPUs
+    ipi: IpiState,
+
+    /// Emulated local APIC of the guest vCPU running on this CPU
+    guest_apic: GuestApic,
+
+    /// Task list that has been assigned for scheduling on this CPU. Other
+    /// CPUs access it to place tasks on this CPU or to take tasks from it.
+    runqueue: RWLock<RunQueue>,
+
+    /// Caches of free slab objects used by heap allocations on this CPU
+    slab_caches: &'static SlabCaches,
 }
 
 impl PerCpuShared {
-    fn new() -> Self {
+    fn new(apic_id: u32, slab_caches: &'static SlabCaches) -> Self {
         PerCpuShared {
+            apic_id,
+            online: AtomicBool::new(false),
             guest_vmsa: SpinLock::new(GuestVmsaRef::new()),
+            ipi: IpiState::new(),
+            guest_apic: GuestApic::new(apic_id),
+            runqueue: RWLock::new(RunQueue::new(apic_id)),
+            slab_caches,
         }
     }
 
+    pub fn slab_caches(&self) -> &'static SlabCaches {
+        self.slab_caches
+    }
+
+    pub const fn apic_id(&self) -> u32 {
+        self.apic_id
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Acquire)
+    }
+
+    pub fn runqueue(&self) -> &RWLock<RunQueue> {
+        &self.runqueue
+    }
+
+    pub fn ipi(&self) -> &IpiState {
+        &self.ipi
+    }
+
+    pub fn guest_apic(&self) -> &GuestApic {
+        &self.guest_apic
+    }
+
     pub fn update_guest_vmsa_caa(&self, vmsa: PhysAddr, caa: PhysAddr) {
         let mut locked = self.guest_vmsa.lock();
         locked.update_vmsa_caa(Some(vmsa), Some(caa));
@@ -231,13 +297,23 @@ impl PerCpuShared {
     }
 }
 
+/// Address of the SYSCALL scratch space of the current CPU
+pub const PERCPU_SYSCALL_SCRATCH: usize =
+    SVSM_PERCPU_BASE.const_bits() + offset_of!(PerCpu, syscall_scratch);
+
+/// Address of the kernel stack pointer for entries from user-space (RSP0 in
+/// the TSS) of the current CPU
+pub const PERCPU_KERNEL_STACK: usize =
+    SVSM_PERCPU_BASE.const_bits() + offset_of!(PerCpu, tss) + offset_of!(X86Tss, stacks);
+
 #[derive(Debug)]
 pub struct PerCpu {
     pub shared: &'static PerCpuShared,
-    online: AtomicBool,
     apic_id: u32,
     pgtbl: SpinLock<PageTableRef>,
     ghcb: *mut GHCB,
+    /// #HV doorbell page, only set up with restricted injection
+    hv_doorbell: OnceCell<SharedBuffer>,
     init_stack: Option<VirtAddr>,
     ist: IstStacks,
     tss: X86Tss,
@@ -252,8 +328,8 @@ pub struct PerCpu {
     /// Address allocator for per-cpu 2m temporary mappings
     pub vrange_2m: VirtualRange,
 
-    /// Task list that has been assigned for scheduling on this CPU
-    runqueue: RWLock<RunQueue>,
+    /// Cache of 2m mappings of guest memory
+    guest_map_cache: GuestMapCache,
 
     /// Stack boundaries of the currently running task. This is stored in
     /// [PerCpu] because it needs lockless read access.
@@ -262,6 +338,20 @@ pub struct PerCpu {
     /// WaitQueue for request processing
     request_waitqueue: WaitQueue,
 
+    /// WaitQueue for a user task waiting for protocol requests
+    user_request_waitqueue: WaitQueue,
+
+    /// Timers of tasks sleeping on this CPU
+    timers: TimerWheel<TaskTimer>,
+
+    /// Preemption state of this CPU. This is accessed without a [CpuRef]
+    /// because taking one disables preemption.
+    pub preempt: PreemptState,
+
+    /// Scratch space for the SYSCALL entry code, which has no stack and no
+    /// free register to work with
+    syscall_scratch: u64,
+
     // The borrow count tuple holds (total, mutable) borrow counts.
     #[cfg(debug_assertions)]
     borrow_count: (usize, usize),
@@ -271,10 +361,10 @@ impl PerCpu {
     fn new(apic_id: u32, shared: &'static PerCpuShared) -> Self {
         PerCpu {
             shared,
-            online: AtomicBool::new(false),
             apic_id,
             pgtbl: SpinLock::<PageTableRef>::new(PageTableRef::unset()),
             ghcb: ptr::null_mut(),
+            hv_doorbell: OnceCell::new(),
             init_stack: None,
             ist: IstStacks::new(),
             tss: X86Tss::new(),
@@ -283,9 +373,13 @@ impl PerCpu {
             vm_range: VMR::new(SVSM_PERCPU_BASE, SVSM_PERCPU_END, PTEntryFlags::GLOBAL),
             vrange_4k: VirtualRange::new(),
             vrange_2m: VirtualRange::new(),
-            runqueue: RWLock::new(RunQueue::new()),
+            guest_map_cache: GuestMapCache::new(),
             current_stack: MemoryRegion::new(VirtAddr::null(), 0),
             request_waitqueue: WaitQueue::new(),
+            user_request_waitqueue: WaitQueue::new(),
+            timers: TimerWheel::new(),
+            preempt: PreemptState::new(),
+            syscall_scratch: 0,
 
             // Every new CPU is constructed with via a raw pointer so no
             // borrow is active at the time of construction.
@@ -295,6 +389,7 @@ impl PerCpu {
     }
 
     pub fn alloc(apic_id: u32) -> Result<*mut PerCpu, SvsmError> {
+        let slab_caches = SlabCaches::alloc()?;
         let vaddr = allocate_zeroed_page()?;
         unsafe {
             // Within each CPU state page, the first portion is the private
@@ -307,7 +402,7 @@ impl PerCpu {
 
             let shared_vaddr = vaddr + private_size;
             let percpu_shared = shared_vaddr.as_mut_ptr::<PerCpuShared>();
-            (*percpu_shared) = PerCpuShared::new();
+            (*percpu_shared) = PerCpuShared::new(apic_id, slab_caches);
 
             let percpu = vaddr.as_mut_ptr::<PerCpu>();
 
@@ -319,11 +414,11 @@ impl PerCpu {
     }
 
     pub fn set_online(&mut self) {
-        self.online.store(true, Ordering::Relaxed);
+        self.shared.online.store(true, Ordering::Release);
     }
 
     pub fn is_online(&self) -> bool {
-        self.online.load(Ordering::Acquire)
+        self.shared.is_online()
     }
 
     pub const fn get_apic_id(&self) -> u32 {
@@ -368,6 +463,22 @@ impl PerCpu {
         self.pgtbl.lock()
     }
 
+    /// Maps a 2M frame of private guest memory through the per-CPU cache of
+    /// guest mappings, see [`GuestMapCache::get()`].
+    pub fn guest_map_get(
+        &mut self,
+        frame: PhysAddr,
+    ) -> Result<Option<(usize, VirtAddr)>, SvsmError> {
+        let mut pgtbl = self.pgtbl.lock();
+        self.guest_map_cache
+            .get(frame, &mut pgtbl, &mut self.vrange_2m)
+    }
+
+    /// Releases a mapping returned by [`Self::guest_map_get()`]
+    pub fn guest_map_put(&mut self, slot: usize) {
+        self.guest_map_cache.put(slot);
+    }
+
     pub fn setup_ghcb(&mut self) -> Result<(), SvsmError> {
         let ghcb_page = allocate_zeroed_page().expect("Failed to allocate GHCB page");
         if let Err(e) = GHCB::init(ghcb_page) {
@@ -460,12 +571,39 @@ impl PerCpu {
 
     // Setup code which needs to run on the target CPU
     pub fn setup_on_cpu(&self) -> Result<(), SvsmError> {
-        self.register_ghcb()
+        self.register_ghcb()?;
+        syscall_init();
+        fpu_cpu_init();
+        Ok(())
+    }
+
+    /// Allocates the #HV doorbell page of the current CPU and registers it
+    /// with the hypervisor. Only needed when the SVSM runs with restricted
+    /// injection. Must run on the target CPU after its per-CPU area is
+    /// loaded.
+    pub fn setup_hv_doorbell(&self) -> Result<(), SvsmError> {
+        if !sev_flags().contains(SEVStatusFlags::REST_INJ) || self.hv_doorbell.get().is_some() {
+            return Ok(());
+        }
+
+        let page = SharedBuffer::new(PAGE_SIZE)?;
+        current_ghcb().register_hv_doorbell(page.paddr())?;
+        let _ = self.hv_doorbell.set(page);
+        Ok(())
+    }
+
+    /// Returns the #HV doorbell page of this CPU, if it has one.
+    pub fn hv_doorbell(&self) -> Option<&HVDoorbell> {
+        let page = self.hv_doorbell.get()?;
+        // SAFETY: the page lives as long as the CPU and is page aligned.
+        // HVDoorbell only consists of atomics and padding, so whatever the
+        // hypervisor writes to it is valid.
+        Some(unsafe { &*page.vaddr().as_ptr::<HVDoorbell>() })
     }
 
     pub fn setup_idle_task(&mut self, entry: extern "C" fn()) -> Result<(), SvsmError> {
         let idle_task = Task::create(self, entry, TASK_FLAG_SHARE_PT)?;
-        self.runqueue.lock_read().set_idle_task(idle_task);
+        self.runqueue().lock_read().set_idle_task(idle_task);
         Ok(())
     }
 
@@ -623,22 +761,39 @@ impl PerCpu {
         self.vm_range.handle_page_fault(vaddr, write)
     }
 
-    pub fn schedule_init(&mut self) -> TaskPointer {
-        let task = self.runqueue.lock_write().schedule_init();
+    /// Record the kernel stack of `task` as the current stack and use it for
+    /// entries from user-space.
+    fn set_task_stack(&mut self, task: &TaskPointer) {
         self.current_stack = task.stack_bounds();
+        self.tss.stacks[0] = self.current_stack.end();
+    }
+
+    pub fn schedule_init(&mut self) -> TaskPointer {
+        let task = self.runqueue().lock_write().schedule_init();
+        self.set_task_stack(&task);
         task
     }
 
     pub fn schedule_prepare(&mut self) -> Option<(TaskPointer, TaskPointer)> {
-        let ret = self.runqueue.lock_write().schedule_prepare();
+        let ret = self.runqueue().lock_write().schedule_prepare();
         if let Some((_, ref next)) = ret {
-            self.current_stack = next.stack_bounds();
+            self.set_task_stack(next);
         };
         ret
     }
 
     pub fn runqueue(&self) -> &RWLock<RunQueue> {
-        &self.runqueue
+        self.shared.runqueue()
+    }
+
+    /// Arms a task timer on this CPU which expires at `deadline`.
+    pub fn add_timer(&mut self, deadline: Instant, timer: TaskTimer) {
+        self.timers.add(deadline, timer);
+    }
+
+    /// Removes the task timers which expired at time `now`.
+    pub fn expire_timers(&mut self, now: Instant) -> Vec<TaskTimer> {
+        self.timers.expire(now)
     }
 }
 
@@ -650,6 +805,17 @@ pub unsafe fn this_cpu_unsafe() -> *mut PerCpu {
     SVSM_PERCPU_BASE.as_mut_ptr::<PerCpu>()
 }
 
+/// Returns the shared per-CPU data of the current CPU. Unlike [`this_cpu()`]
+/// this is usable in interrupt context, as it takes no reference to the
+/// private per-CPU data. The caller needs to disable preemption if it must
+/// stay on the same CPU.
+pub fn this_cpu_shared() -> &'static PerCpuShared {
+    // SAFETY: The shared field is set when the per-CPU area is created and
+    // never changes afterwards, so reading it can not race with holders of
+    // a per-CPU reference.
+    unsafe { *ptr::addr_of!((*this_cpu_unsafe()).shared) }
+}
+
 #[derive(Debug)]
 pub struct CpuRef {
     cpu: &'static mut PerCpu,
@@ -681,6 +847,9 @@ impl DerefMut for CpuRefMut {
 }
 
 pub fn this_cpu() -> CpuRef {
+    // The per-CPU data must not change under the reference when the task is
+    // preempted and migrated. Re-enabled when the CpuRef is dropped.
+    preempt_disable();
     unsafe {
         let cpu = SVSM_PERCPU_BASE.as_mut_ptr::<PerCpu>();
         #[cfg(debug_assertions)]
@@ -694,6 +863,7 @@ pub fn this_cpu() -> CpuRef {
 }
 
 pub fn this_cpu_mut() -> CpuRefMut {
+    preempt_disable();
     unsafe {
         let cpu = SVSM_PERCPU_BASE.as_mut_ptr::<PerCpu>();
         #[cfg(debug_assertions)]
@@ -708,21 +878,27 @@ pub fn this_cpu_mut() -> CpuRefMut {
     }
 }
 
-#[cfg(debug_assertions)]
 impl Drop for CpuRef {
     fn drop(&mut self) {
-        assert!(self.cpu.borrow_count.0 != 0);
-        self.cpu.borrow_count.0 -= 1;
+        #[cfg(debug_assertions)]
+        {
+            assert!(self.cpu.borrow_count.0 != 0);
+            self.cpu.borrow_count.0 -= 1;
+        }
+        preempt_enable();
     }
 }
 
-#[cfg(debug_assertions)]
 impl Drop for CpuRefMut {
     fn drop(&mut self) {
-        assert!(self.cpu.borrow_count.0 == 1);
-        self.cpu.borrow_count.0 = 0;
-        assert!(self.cpu.borrow_count.1 == 1);
-        self.cpu.borrow_count.1 = 0;
+        #[cfg(debug_assertions)]
+        {
+            assert!(self.cpu.borrow_count.0 == 1);
+            self.cpu.borrow_count.0 = 0;
+            assert!(self.cpu.borrow_count.1 == 1);
+            self.cpu.borrow_count.1 = 0;
+        }
+        preempt_enable();
     }
 }
 
@@ -767,6 +943,14 @@ impl PerCpuVmsas {
             .any(|vmsa| vmsa.paddr == paddr)
     }
 
+    /// Returns whether any registered VMSA is located within `region`.
+    pub fn exists_in(&self, region: MemoryRegion<PhysAddr>) -> bool {
+        self.vmsas
+            .lock_read()
+            .iter()
+            .any(|vmsa| region.contains(vmsa.paddr))
+    }
+
     pub fn register(
         &self,
         paddr: PhysAddr,
@@ -825,13 +1009,39 @@ pub fn wait_for_requests() {
     schedule();
 }
 
+/// Blocks the current task until the next protocol request arrives on this
+/// CPU. Only one user task per CPU can wait for requests.
+///
+/// # Returns
+///
+/// `Ok(())` after a request arrived, or `Err(SvsmError::Task(TaskError::Busy))`
+/// when another task is already waiting.
+pub fn wait_for_user_requests() -> Result<(), SvsmError> {
+    let current_task = current_task();
+    {
+        let mut cpu = this_cpu_mut();
+        if cpu.user_request_waitqueue.has_waiter() {
+            return Err(TaskError::Busy.into());
+        }
+        cpu.user_request_waitqueue.wait_for_event(current_task);
+    }
+    schedule();
+    Ok(())
+}
+
 pub fn process_requests() {
-    let maybe_task = this_cpu_mut().request_waitqueue.wakeup();
-    if let Some(task) = maybe_task {
+    let (kernel_task, user_task) = {
+        let mut cpu = this_cpu_mut();
+        (
+            cpu.request_waitqueue.wakeup(),
+            cpu.user_request_waitqueue.wakeup(),
+        )
+    };
+    for task in [kernel_task, user_task].into_iter().flatten() {
         schedule_task(task);
     }
 }
 
 pub fn current_task() -> TaskPointer {
-    this_cpu().runqueue.lock_read().current_task()
+    this_cpu().runqueue().lock_read().current_task()
 }
@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use super::features::cpu_has_rdrand;
+use core::arch::asm;
+
+/// Number of attempts before giving up on RDRAND. The instruction can fail
+/// transiently when the hardware entropy source is exhausted.
+const RDRAND_RETRIES: usize = 10;
+
+fn rdrand64_once() -> Option<u64> {
+    let value: u64;
+    let ok: u8;
+
+    // SAFETY: RDRAND has no side effects besides writing its destination
+    // register and the flags.
+    unsafe {
+        asm!("rdrand {0}",
+             "setc {1}",
+             out(reg) value,
+             out(reg_byte) ok,
+             options(nomem, nostack));
+    }
+
+    (ok != 0).then_some(value)
+}
+
+/// Returns a 64-bit random number from the CPU's hardware random number
+/// generator, or `None` if RDRAND is not supported or keeps failing.
+pub fn rdrand64() -> Option<u64> {
+    if !cpu_has_rdrand() {
+        return None;
+    }
+
+    (0..RDRAND_RETRIES).find_map(|_| rdrand64_once())
+}
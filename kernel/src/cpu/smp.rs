@@ -9,7 +9,9 @@ use crate::cpu::ghcb::current_ghcb;
 use crate::cpu::percpu::{this_cpu_mut, PerCpu};
 use crate::cpu::vmsa::init_svsm_vmsa;
 use crate::requests::{request_loop, request_processing_main};
-use crate::task::{create_kernel_task, schedule_init, TASK_FLAG_SHARE_PT};
+use crate::task::{
+    create_kernel_task, enable_preemption, schedule_init, TASK_FLAG_REALTIME, TASK_FLAG_SHARE_PT,
+};
 
 fn start_cpu(apic_id: u32) {
     unsafe {
@@ -59,6 +61,10 @@ fn start_ap() {
         .setup_on_cpu()
         .expect("setup_on_cpu() failed");
 
+    this_cpu()
+        .setup_hv_doorbell()
+        .expect("Failed to set up the #HV doorbell page");
+
     this_cpu_mut()
         .setup_idle_task(ap_request_loop)
         .expect("Failed to allocated idle task for AP");
@@ -74,8 +80,15 @@ fn start_ap() {
 
 #[no_mangle]
 pub extern "C" fn ap_request_loop() {
-    create_kernel_task(request_processing_main, TASK_FLAG_SHARE_PT)
-        .expect("Failed to launch request processing task");
+    if let Err(e) = enable_preemption() {
+        log::warn!("Failed to enable preemption: {:?}", e);
+    }
+
+    create_kernel_task(
+        request_processing_main,
+        TASK_FLAG_SHARE_PT | TASK_FLAG_REALTIME,
+    )
+    .expect("Failed to launch request processing task");
     request_loop();
     panic!("Returned from request_loop!");
 }
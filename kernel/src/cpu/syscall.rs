@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use super::irq_state::{raw_irqs_enable, EFLAGS_IF};
+use super::msr::{write_msr, MSR_LSTAR, MSR_SFMASK, MSR_STAR};
+use super::percpu::{PERCPU_KERNEL_STACK, PERCPU_SYSCALL_SCRATCH};
+use super::X86ExceptionContext;
+use crate::syscall::handle_syscall;
+use crate::types::{SVSM_CS, SVSM_USER_CS, SVSM_USER_DS};
+use core::arch::global_asm;
+
+// RFLAGS bits cleared on kernel entry via SYSCALL
+const EFLAGS_TF: u64 = 1 << 8;
+const EFLAGS_DF: u64 = 1 << 10;
+const EFLAGS_NT: u64 = 1 << 14;
+const EFLAGS_AC: u64 = 1 << 18;
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// Set up the SYSCALL/SYSRET MSRs on the current CPU. The SCE bit in EFER
+/// is set by `efer_init()`.
+pub fn syscall_init() {
+    // SYSRET loads CS from STAR[63:48] + 16 and SS from STAR[63:48] + 8
+    let sysret_base = u64::from(SVSM_USER_DS - 8);
+    let star = (sysret_base << 48) | (u64::from(SVSM_CS) << 32);
+
+    write_msr(MSR_STAR, star);
+    write_msr(MSR_LSTAR, syscall_entry as *const () as u64);
+    write_msr(
+        MSR_SFMASK,
+        EFLAGS_IF | EFLAGS_TF | EFLAGS_DF | EFLAGS_NT | EFLAGS_AC,
+    );
+}
+
+/// Called from the SYSCALL entry code. The context uses the same layout as
+/// for exceptions, with the user-space RIP and RFLAGS (passed in RCX and
+/// R11) in the interrupt frame.
+#[no_mangle]
+extern "C" fn syscall_handler(ctx: &mut X86ExceptionContext) {
+    // Interrupts are masked on entry, restore the user-space state
+    if (ctx.frame.flags as u64 & EFLAGS_IF) != 0 {
+        raw_irqs_enable();
+    }
+
+    handle_syscall(ctx);
+}
+
+global_asm!(
+    r#"
+        .text
+        .globl  syscall_entry
+    syscall_entry:
+        // Interrupts are disabled. Switch to the kernel stack of the current
+        // task, using per-CPU scratch space to free up RAX.
+        movabsq %rax, {scratch}
+        movabsq {kernel_stack}, %rax
+        xchgq   %rax, %rsp
+        // Build an interrupt frame with the user-space state
+        pushq   ${user_ss}
+        pushq   %rax
+        pushq   %r11
+        pushq   ${user_cs}
+        pushq   %rcx
+        movabsq {scratch}, %rax
+        // Error code and vector
+        pushq   $0
+        pushq   $0
+        pushq   %rax
+        pushq   %rbx
+        pushq   %rcx
+        pushq   %rdx
+        pushq   %rsi
+        pushq   %rdi
+        pushq   %rbp
+        pushq   %r8
+        pushq   %r9
+        pushq   %r10
+        pushq   %r11
+        pushq   %r12
+        pushq   %r13
+        pushq   %r14
+        pushq   %r15
+
+        movq    %rsp, %rdi
+        call    syscall_handler
+
+        cli
+        popq    %r15
+        popq    %r14
+        popq    %r13
+        popq    %r12
+        popq    %r11
+        popq    %r10
+        popq    %r9
+        popq    %r8
+        popq    %rbp
+        popq    %rdi
+        popq    %rsi
+        popq    %rdx
+        popq    %rcx
+        popq    %rbx
+        popq    %rax
+        // Skip vector and error code
+        addq    $16, %rsp
+        // Return to user-space with RIP and RFLAGS from the frame
+        popq    %rcx
+        addq    $8, %rsp
+        popq    %r11
+        popq    %rsp
+        sysretq
+    "#,
+    scratch = const PERCPU_SYSCALL_SCRATCH,
+    kernel_stack = const PERCPU_KERNEL_STACK,
+    user_ss = const SVSM_USER_DS | 3,
+    user_cs = const SVSM_USER_CS | 3,
+    options(att_syntax)
+);
@@ -7,11 +7,13 @@
 use super::idt::common::X86ExceptionContext;
 use crate::cpu::cpuid::{cpuid_table_raw, CpuidLeaf};
 use crate::cpu::ghcb::current_ghcb;
-use crate::cpu::insn::{insn_fetch, Instruction};
+use crate::cpu::insn::{insn_fetch, Instruction, MAX_INSN_SIZE};
 use crate::debug::gdbstub::svsm_gdbstub::handle_debug_exception;
 use crate::error::SvsmError;
 use crate::sev::ghcb::{GHCBIOSize, GHCB};
+use crate::types::PAGE_SIZE;
 use core::fmt;
+use core::ptr;
 
 pub const SVM_EXIT_EXCP_BASE: usize = 0x40;
 pub const SVM_EXIT_LAST_EXCP: usize = 0x5f;
@@ -145,6 +147,37 @@ pub fn handle_vc_exception(ctx: &mut X86ExceptionContext) {
     vc_finish_insn(ctx, &insn);
 }
 
+/// Handles a #VC exception raised by a user task. Only CPUID is emulated for
+/// user mode, everything else is reported as an error and must terminate the
+/// task instead of the SVSM.
+pub fn handle_user_vc_exception(ctx: &mut X86ExceptionContext) -> Result<(), SvsmError> {
+    if ctx.error_code != SVM_EXIT_CPUID {
+        return Err(SvsmError::Vc(VcError {
+            rip: ctx.frame.rip,
+            code: ctx.error_code,
+            error_type: VcErrorType::Unsupported,
+        }));
+    }
+
+    let mut insn = Instruction::new(vc_fetch_user_insn(ctx.frame.rip));
+    insn.decode()?;
+    handle_cpuid(ctx)?;
+    vc_finish_insn(ctx, &insn);
+
+    Ok(())
+}
+
+/// Copies the instruction bytes at a user-mode RIP. Only the bytes up to the
+/// end of the page are read, because the page after it might not be mapped.
+fn vc_fetch_user_insn(rip: usize) -> [u8; MAX_INSN_SIZE] {
+    let mut insn_raw = [0u8; MAX_INSN_SIZE];
+    let len = MAX_INSN_SIZE.min(PAGE_SIZE - (rip & (PAGE_SIZE - 1)));
+    // SAFETY: the CPU just fetched the instruction from this page, so it is
+    // mapped.
+    unsafe { ptr::copy_nonoverlapping(rip as *const u8, insn_raw.as_mut_ptr(), len) };
+    insn_raw
+}
+
 fn handle_cpuid(ctx: &mut X86ExceptionContext) -> Result<(), SvsmError> {
     /*
      * Section 2.3.1 GHCB MSR Protocol in SEV-ES Guest-Hypervisor Communication Block
@@ -19,7 +19,7 @@ pub mod svsm_gdbstub {
     use crate::error::SvsmError;
     use crate::locking::{LockGuard, SpinLock};
     use crate::mm::guestmem::{read_u8, write_u8};
-    use crate::mm::PerCPUPageMappingGuard;
+    use crate::mm::{kernel_slide, PerCPUPageMappingGuard};
     use crate::serial::{SerialPort, Terminal};
     use crate::svsm_console::SVSMIOPort;
     use crate::task::{is_current_task, TaskContext, INITIAL_TASK_ID, TASKLIST};
@@ -36,6 +36,7 @@ pub mod svsm_gdbstub {
     };
     use gdbstub::target::ext::base::BaseOps;
     use gdbstub::target::ext::breakpoints::{Breakpoints, SwBreakpoint};
+    use gdbstub::target::ext::section_offsets::{Offsets, SectionOffsets};
     use gdbstub::target::ext::thread_extra_info::ThreadExtraInfo;
     use gdbstub::target::{Target, TargetError};
     use gdbstub_arch::x86::reg::X86_64CoreRegs;
@@ -401,6 +402,26 @@ pub mod svsm_gdbstub {
         ) -> Option<gdbstub::target::ext::breakpoints::BreakpointsOps<'_, Self>> {
             Some(self)
         }
+
+        #[inline(always)]
+        fn support_section_offsets(
+            &mut self,
+        ) -> Option<gdbstub::target::ext::section_offsets::SectionOffsetsOps<'_, Self>> {
+            Some(self)
+        }
+    }
+
+    impl SectionOffsets for GdbStubTarget {
+        // Report the kernel slide so that GDB relocates the symbols of the
+        // kernel ELF file to the randomized load address.
+        fn get_section_offsets(&mut self) -> Result<Offsets<u64>, Self::Error> {
+            let slide = kernel_slide() as u64;
+            Ok(Offsets::Sections {
+                text: slide,
+                data: slide,
+                bss: Some(slide),
+            })
+        }
     }
 
     impl From<&TaskContext> for X86_64CoreRegs {
@@ -7,8 +7,8 @@
 use crate::{
     address::VirtAddr,
     cpu::idt::common::{is_exception_handler_return_site, X86ExceptionContext},
-    cpu::percpu::this_cpu,
-    mm::address_space::STACK_SIZE,
+    cpu::percpu::{this_cpu, this_cpu_unsafe, PerCpu},
+    mm::address_space::{kernel_link_addr, STACK_SIZE},
     utils::MemoryRegion,
 };
 use core::{arch::asm, mem};
@@ -39,21 +39,43 @@ struct StackUnwinder {
 
 impl StackUnwinder {
     pub fn unwind_this_cpu() -> Self {
+        let cpu = this_cpu();
+        Self::unwind_with_stacks(Self::stacks_of(&cpu))
+    }
+
+    /// Like [`Self::unwind_this_cpu()`], but without borrowing the per-CPU
+    /// data, so that it can be used while a per-CPU reference is held, e.g.
+    /// from within the heap allocator.
+    ///
+    /// # Safety
+    ///
+    /// The per-CPU area of the current CPU must be mapped.
+    unsafe fn unwind_this_cpu_unsafe() -> Self {
+        // Only the stack bounds are read, which do not change while the
+        // current task runs.
+        let cpu = &*this_cpu_unsafe();
+        Self::unwind_with_stacks(Self::stacks_of(cpu))
+    }
+
+    fn stacks_of(cpu: &PerCpu) -> StacksBounds {
+        let top_of_init_stack = cpu.get_top_of_stack();
+        let top_of_df_stack = cpu.get_top_of_df_stack();
+
+        [
+            MemoryRegion::from_addresses(top_of_init_stack - STACK_SIZE, top_of_init_stack),
+            MemoryRegion::from_addresses(top_of_df_stack - STACK_SIZE, top_of_df_stack),
+            cpu.current_stack,
+        ]
+    }
+
+    #[inline(always)]
+    fn unwind_with_stacks(stacks: StacksBounds) -> Self {
         let mut rbp: usize;
         unsafe {
             asm!("movq %rbp, {}", out(reg) rbp,
                  options(att_syntax));
         };
 
-        let top_of_init_stack = this_cpu().get_top_of_stack();
-        let top_of_df_stack = this_cpu().get_top_of_df_stack();
-
-        let stacks: StacksBounds = [
-            MemoryRegion::from_addresses(top_of_init_stack - STACK_SIZE, top_of_init_stack),
-            MemoryRegion::from_addresses(top_of_df_stack - STACK_SIZE, top_of_df_stack),
-            this_cpu().current_stack,
-        ];
-
         Self::new(VirtAddr::from(rbp), stacks)
     }
 
@@ -180,9 +202,30 @@ pub fn print_stack(skip: usize) {
     log::info!("---BACKTRACE---:");
     for frame in unwinder.skip(skip) {
         match frame {
-            UnwoundStackFrame::Valid(item) => log::info!("  [{:#018x}]", item.rip),
+            UnwoundStackFrame::Valid(item) => log::info!(
+                "  [{:#018x}] (link address {:#018x})",
+                item.rip,
+                kernel_link_addr(item.rip)
+            ),
             UnwoundStackFrame::Invalid => log::info!("  Invalid frame"),
         }
     }
     log::info!("---END---");
 }
+
+/// Records the return addresses of the current call stack in `frames`,
+/// skipping the innermost `skip` frames. Unused entries are set to null.
+///
+/// # Safety
+///
+/// The per-CPU area of the current CPU must be mapped.
+pub unsafe fn capture_stack(skip: usize, frames: &mut [VirtAddr]) {
+    let unwinder = StackUnwinder::unwind_this_cpu_unsafe();
+    let mut rips = unwinder.skip(skip).map_while(|frame| match frame {
+        UnwoundStackFrame::Valid(item) => Some(item.rip),
+        UnwoundStackFrame::Invalid => None,
+    });
+    for frame in frames.iter_mut() {
+        *frame = rips.next().unwrap_or(VirtAddr::null());
+    }
+}
@@ -8,6 +8,7 @@
 
 extern crate alloc;
 
+use crate::error::SvsmError;
 use alloc::vec::Vec;
 use bitflags::bitflags;
 use core::cmp;
@@ -190,6 +191,12 @@ impl fmt::Display for ElfError {
     }
 }
 
+impl From<ElfError> for SvsmError {
+    fn from(e: ElfError) -> Self {
+        Self::Elf(e)
+    }
+}
+
 pub type Elf64Addr = u64;
 pub type Elf64Off = u64;
 pub type Elf64Half = u16;
@@ -1065,6 +1072,7 @@ impl Elf64Hdr {
     const ELFOSABI_GNU: Elf64char = 3;
 
     const ET_EXEC: Elf64Half = 2;
+    const ET_DYN: Elf64Half = 3;
 
     const EM_X86_64: Elf64Half = 62;
 
@@ -1137,7 +1145,8 @@ impl Elf64Hdr {
         let e_shnum = Elf64Half::from_le_bytes(buf[60..62].try_into().unwrap()) as Elf64Word;
         let e_shstrndx = Elf64Half::from_le_bytes(buf[62..64].try_into().unwrap()) as Elf64Word;
 
-        if e_type != Self::ET_EXEC {
+        // Position independent executables are of type ET_DYN
+        if e_type != Self::ET_EXEC && e_type != Self::ET_DYN {
             return Err(ElfError::UnsupportedType);
         }
         if e_machine != Self::EM_X86_64 {
@@ -2559,6 +2568,28 @@ mod tests {
         assert_eq!(elf_hdr.e_version, expected_version);
     }
 
+    #[test]
+    fn test_elf64_hdr_type() {
+        let mut byte_data = [0u8; 64];
+        byte_data[..8].copy_from_slice(&[0x7f, 0x45, 0x4c, 0x46, 0x02, 0x01, 0x01, 0x00]);
+        byte_data[18..20].copy_from_slice(&62u16.to_le_bytes());
+        byte_data[20..24].copy_from_slice(&1u32.to_le_bytes());
+
+        // ET_EXEC and ET_DYN (PIE) are accepted
+        for e_type in [2u16, 3u16] {
+            byte_data[16..18].copy_from_slice(&e_type.to_le_bytes());
+            let elf_hdr = Elf64Hdr::read(&byte_data).unwrap();
+            assert_eq!(elf_hdr.e_type, e_type);
+        }
+
+        // ET_REL is not
+        byte_data[16..18].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(
+            Elf64Hdr::read(&byte_data).err(),
+            Some(crate::elf::ElfError::UnsupportedType)
+        );
+    }
+
     #[test]
     fn test_elf64_load_segments() {
         let mut load_segments = Elf64LoadSegments::new();
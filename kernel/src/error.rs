@@ -4,10 +4,13 @@
 //
 // Author: Carlos López <carlos.lopez@suse.com>
 
+use crate::cpu::guest_apic::ApicError;
 use crate::cpu::vc::VcError;
+use crate::elf::ElfError;
 use crate::fs::FsError;
 use crate::fw_cfg::FwCfgError;
 use crate::mm::alloc::AllocError;
+use crate::mm::guest_pagetable::GuestPtError;
 use crate::sev::ghcb::GhcbError;
 use crate::sev::msr_protocol::GhcbMsrError;
 use crate::sev::SevSnpError;
@@ -36,6 +39,8 @@ pub enum SvsmError {
     MissingCAA,
     // Invalid address, usually provided by the guest
     InvalidAddress,
+    // Errors from translating guest virtual addresses
+    GuestPageTable(GuestPtError),
     // Errors related to firmware parsing
     Firmware,
     // Errors related to firmware configuration contents
@@ -48,4 +53,14 @@ pub enum SvsmError {
     Task(TaskError),
     // Errors from #VC handler
     Vc(VcError),
+    // Errors from the ELF parser
+    Elf(ElfError),
+    // The TSC frequency could not be determined
+    Clock,
+    // The target CPU of an IPI can not receive interrupts
+    Ipi,
+    // Extended FPU state is not supported
+    Fpu,
+    // Errors from the emulated guest APIC
+    Apic(ApicError),
 }
@@ -154,6 +154,12 @@ impl FileHandle {
     pub fn mapping(&self, offset: usize) -> Option<PageRef> {
         self.handle.lock().mapping(offset)
     }
+
+    /// Open a new handle to the same file. The new handle has its own file
+    /// position, which starts at the beginning of the file.
+    pub fn reopen(&self) -> FileHandle {
+        FileHandle::new(&self.handle.lock().file)
+    }
 }
 
 /// Represents SVSM filesystem
@@ -20,13 +20,11 @@ use core::{
 
 use crate::{
     address::{Address, VirtAddr},
-    cpu::ghcb::current_ghcb,
-    cpu::percpu::this_cpu_mut,
     crypto::aead::{Aes256Gcm, Aes256GcmTrait, AUTHTAG_SIZE, IV_SIZE},
-    mm::virt_to_phys,
+    mm::SharedData,
     protocols::errors::SvsmReqError,
-    sev::{ghcb::PageStateChangeOp, secrets_page::VMPCK_SIZE},
-    types::{PageSize, PAGE_SIZE},
+    sev::secrets_page::VMPCK_SIZE,
+    types::PAGE_SIZE,
 };
 
 /// Version of the message header
@@ -207,6 +205,10 @@ pub struct SnpGuestRequestMsg {
 // The GHCB spec says it has to fit in one page and be page aligned
 const _: () = assert!(size_of::<SnpGuestRequestMsg>() <= PAGE_SIZE);
 
+// SAFETY: the message only consists of integers and byte arrays, so every
+// bit pattern is valid.
+unsafe impl SharedData for SnpGuestRequestMsg {}
+
 impl SnpGuestRequestMsg {
     /// Allocate the object in the heap without going through stack as
     /// this is a large object
@@ -230,50 +232,6 @@ impl SnpGuestRequestMsg {
         }
     }
 
-    /// Clear the C-bit (memory encryption bit) for the Self page
-    ///
-    /// # Safety
-    ///
-    /// * The caller is responsible for setting the page back to encrypted
-    ///   before the object is dropped. Shared pages should not be freed
-    ///   (returned to the allocator)
-    pub fn set_shared(&mut self) -> Result<(), SvsmReqError> {
-        let vaddr = VirtAddr::from(addr_of!(*self));
-        this_cpu_mut()
-            .get_pgtable()
-            .set_shared_4k(vaddr)
-            .map_err(|_| SvsmReqError::invalid_request())?;
-
-        let paddr = virt_to_phys(vaddr);
-        current_ghcb()
-            .page_state_change(
-                paddr,
-                paddr + PAGE_SIZE,
-                PageSize::Regular,
-                PageStateChangeOp::PscShared,
-            )
-            .map_err(|_| SvsmReqError::invalid_request())
-    }
-
-    /// Set the C-bit (memory encryption bit) for the Self page
-    pub fn set_encrypted(&mut self) -> Result<(), SvsmReqError> {
-        let vaddr = VirtAddr::from(addr_of!(*self));
-        this_cpu_mut()
-            .get_pgtable()
-            .set_encrypted_4k(vaddr)
-            .map_err(|_| SvsmReqError::invalid_request())?;
-
-        let paddr = virt_to_phys(vaddr);
-        current_ghcb()
-            .page_state_change(
-                paddr,
-                paddr + PAGE_SIZE,
-                PageSize::Regular,
-                PageStateChangeOp::PscPrivate,
-            )
-            .map_err(|_| SvsmReqError::invalid_request())
-    }
-
     /// Fill the [`SnpGuestRequestMsg`] fields with zeros
     pub fn clear(&mut self) {
         self.hdr.as_slice_mut().fill(0);
@@ -411,58 +369,10 @@ fn build_iv(msg_seqno: u64) -> [u8; IV_SIZE] {
     iv
 }
 
-/// Set to encrypted all the 4k pages of a memory range
-fn set_encrypted_region_4k(start: VirtAddr, end: VirtAddr) -> Result<(), SvsmReqError> {
-    for addr in (start.bits()..end.bits())
-        .step_by(PAGE_SIZE)
-        .map(VirtAddr::from)
-    {
-        this_cpu_mut()
-            .get_pgtable()
-            .set_encrypted_4k(addr)
-            .map_err(|_| SvsmReqError::invalid_request())?;
-
-        let paddr = virt_to_phys(addr);
-        current_ghcb()
-            .page_state_change(
-                paddr,
-                paddr + PAGE_SIZE,
-                PageSize::Regular,
-                PageStateChangeOp::PscPrivate,
-            )
-            .map_err(|_| SvsmReqError::invalid_request())?;
-    }
-    Ok(())
-}
-
-/// Set to shared all the 4k pages of a memory range
-fn set_shared_region_4k(start: VirtAddr, end: VirtAddr) -> Result<(), SvsmReqError> {
-    for addr in (start.bits()..end.bits())
-        .step_by(PAGE_SIZE)
-        .map(VirtAddr::from)
-    {
-        this_cpu_mut()
-            .get_pgtable()
-            .set_shared_4k(addr)
-            .map_err(|_| SvsmReqError::invalid_request())?;
-
-        let paddr = virt_to_phys(addr);
-        current_ghcb()
-            .page_state_change(
-                paddr,
-                paddr + PAGE_SIZE,
-                PageSize::Regular,
-                PageStateChangeOp::PscShared,
-            )
-            .map_err(|_| SvsmReqError::invalid_request())?;
-    }
-    Ok(())
-}
-
 /// Data page(s) the hypervisor will use to store certificate data in
 /// an extended `SNP_GUEST_REQUEST`
 #[repr(C, align(4096))]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct SnpGuestRequestExtData {
     /// According to the GHCB spec, the data page(s) must be contiguous pages if
     /// supplying more than one page and all certificate pages must be
@@ -470,6 +380,9 @@ pub struct SnpGuestRequestExtData {
     data: [u8; SNP_GUEST_REQ_MAX_DATA_SIZE],
 }
 
+// SAFETY: the data is a plain byte array.
+unsafe impl SharedData for SnpGuestRequestExtData {}
+
 impl SnpGuestRequestExtData {
     /// Allocate the object in the heap without going through stack as
     /// this is a large object
@@ -487,26 +400,6 @@ impl SnpGuestRequestExtData {
         }
     }
 
-    /// Clear the C-bit (memory encryption bit) for the Self pages
-    ///
-    /// # Safety
-    ///
-    /// * The caller is responsible for setting the page back to encrypted
-    ///   before the object is dropped. Shared pages should not be freed
-    ///   (returned to the allocator)
-    pub fn set_shared(&mut self) -> Result<(), SvsmReqError> {
-        let start = VirtAddr::from(addr_of!(*self));
-        let end = start + size_of::<Self>();
-        set_shared_region_4k(start, end)
-    }
-
-    /// Set the C-bit (memory encryption bit) for the Self pages
-    pub fn set_encrypted(&mut self) -> Result<(), SvsmReqError> {
-        let start = VirtAddr::from(addr_of!(*self));
-        let end = start + size_of::<Self>();
-        set_encrypted_region_4k(start, end)
-    }
-
     /// Clear the first `n` bytes from data
     pub fn nclear(&mut self, n: usize) -> Result<(), SvsmReqError> {
         self.data
@@ -35,7 +35,9 @@ pub mod sev;
 pub mod string;
 pub mod svsm_console;
 pub mod svsm_paging;
+pub mod syscall;
 pub mod task;
+pub mod time;
 pub mod types;
 pub mod utils;
 
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use super::{MutexGuard, SpinLock};
+use crate::cpu::percpu::current_task;
+use crate::task::{schedule, schedule_timeout, wake_task, WaitQueue};
+use crate::time::{Duration, Instant};
+
+/// A condition variable to wait for a condition protected by a
+/// [`Mutex`](super::Mutex) to become true.
+///
+/// Waiting tasks sleep until they are notified. Wakeups can be spurious, so
+/// the condition needs to be checked again after [`Condvar::wait()`] returns.
+/// [`Condvar::wait_while()`] takes care of this.
+///
+/// # Examples
+///
+/// ```no_run
+/// use svsm::locking::{Condvar, Mutex};
+///
+/// let ready = Mutex::new(false);
+/// let cond = Condvar::new();
+///
+/// // Waiting side
+/// let guard = cond.wait_while(ready.lock(), |ready| !*ready);
+/// drop(guard);
+///
+/// // Notifying side
+/// *ready.lock() = true;
+/// cond.notify_all();
+/// ```
+#[derive(Debug)]
+pub struct Condvar {
+    waiters: SpinLock<WaitQueue>,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            waiters: SpinLock::new(WaitQueue::new()),
+        }
+    }
+
+    /// Atomically releases the mutex held by `guard` and puts the current
+    /// task to sleep until it is notified. The mutex is acquired again
+    /// before the function returns.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+
+        // The task is on the wait queue before the mutex is released, so a
+        // notification sent after the release can not get lost.
+        self.waiters.lock().wait_for_event(current_task());
+        drop(guard);
+
+        schedule();
+
+        mutex.lock()
+    }
+
+    /// Like [`Condvar::wait()`], but stops waiting after `timeout` when no
+    /// notification arrived.
+    ///
+    /// # Returns
+    ///
+    /// The re-acquired guard, and `true` if the timeout expired before the
+    /// task was notified.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let deadline = Instant::now() + timeout;
+        let mutex = guard.mutex();
+        let task = current_task();
+
+        self.waiters.lock().wait_for_event(task.clone());
+        drop(guard);
+
+        schedule_timeout(deadline);
+
+        // The task is still on the queue when the timer woke it up
+        let timed_out = self.waiters.lock().remove(&task);
+        (mutex.lock(), timed_out)
+    }
+
+    /// Sleeps until `condition` returns `false` for the data protected by
+    /// the mutex of `guard`.
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wakes up the longest waiting task, if any.
+    pub fn notify_one(&self) {
+        let waiter = self.waiters.lock().wakeup();
+        if let Some(task) = waiter {
+            wake_task(task);
+        }
+    }
+
+    /// Wakes up all waiting tasks.
+    pub fn notify_all(&self) {
+        loop {
+            let waiter = self.waiters.lock().wakeup();
+            match waiter {
+                Some(task) => wake_task(task),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locking::Mutex;
+    use crate::task::run_blocked_task;
+
+    static READY: Mutex<bool> = Mutex::new(false);
+    static READY_COND: Condvar = Condvar::new();
+
+    extern "C" fn waiting_task() {
+        let mut guard = READY.lock();
+        while !*guard {
+            guard = READY_COND.wait(guard);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_condvar_notify_one() {
+        // The task sleeps on the condition variable until it is notified
+        let exit_code = run_blocked_task(waiting_task, |task| {
+            assert!(!task.is_terminated());
+            *READY.lock() = true;
+            READY_COND.notify_one();
+        });
+        assert_eq!(exit_code, 0);
+    }
+}
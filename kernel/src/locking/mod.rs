@@ -4,8 +4,14 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
+pub mod condvar;
+pub mod mutex;
 pub mod rwlock;
+pub mod semaphore;
 pub mod spinlock;
 
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexGuard};
 pub use rwlock::{RWLock, ReadLockGuard, WriteLockGuard};
+pub use semaphore::Semaphore;
 pub use spinlock::{LockGuard, SpinLock};
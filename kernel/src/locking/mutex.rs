@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use super::SpinLock;
+use crate::cpu::percpu::current_task;
+use crate::task::{schedule, wake_task, WaitQueue};
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(debug_assertions)]
+use crate::task::current_task_id;
+
+/// Internal state of a [`Mutex`], protected by a [`SpinLock`]
+#[derive(Debug)]
+struct MutexState {
+    /// Whether the mutex is currently held
+    locked: bool,
+    /// Tasks waiting for the mutex to be released
+    waiters: WaitQueue,
+    /// ID of the task holding the mutex
+    #[cfg(debug_assertions)]
+    owner: Option<u32>,
+}
+
+/// A lock guard obtained from a [`Mutex`]. The mutex is released when the
+/// guard goes out of scope.
+///
+/// The guard can not be sent to other tasks, as the mutex must be released
+/// by the task which acquired it.
+#[derive(Debug)]
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    /// Returns the [`Mutex`] this guard was obtained from
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+/// Implements the behavior of the [`MutexGuard`] when it is dropped
+impl<T> Drop for MutexGuard<'_, T> {
+    /// Releases the mutex and wakes up the longest waiting task
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: The guard guarantees exclusive access to the data.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The guard guarantees exclusive access to the data.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+/// A sleeping lock for protecting data across long-running operations.
+///
+/// Unlike [`SpinLock`], a task which can not acquire the mutex is put to
+/// sleep until the mutex is released, and preemption stays enabled while the
+/// mutex is held. A mutex must therefore only be used in task context and
+/// never from interrupt handlers.
+///
+/// In debug builds the mutex tracks its owner and panics on recursive
+/// locking and on release by a task which does not hold it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use svsm::locking::Mutex;
+///
+/// let mutex = Mutex::new(0);
+/// {
+///     let mut guard = mutex.lock();
+///     *guard += 1;
+/// }; // The mutex is released when `guard` goes out of scope.
+/// ```
+#[derive(Debug)]
+pub struct Mutex<T> {
+    state: SpinLock<MutexState>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Creates a new, unlocked mutex protecting `data`.
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: SpinLock::new(MutexState {
+                locked: false,
+                waiters: WaitQueue::new(),
+                #[cfg(debug_assertions)]
+                owner: None,
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Acquires the mutex, sleeping until it becomes available.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if the current task already holds the mutex.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        loop {
+            let mut state = self.state.lock();
+            #[cfg(debug_assertions)]
+            assert!(
+                state.owner != Some(current_task_id()),
+                "Recursive locking of Mutex"
+            );
+            if !state.locked {
+                return self.acquire(&mut state);
+            }
+
+            state.waiters.wait_for_event(current_task());
+            drop(state);
+
+            schedule();
+        }
+    }
+
+    /// Tries to acquire the mutex without sleeping.
+    ///
+    /// # Returns
+    ///
+    /// A [`MutexGuard`] if the mutex was acquired, `None` if it is already
+    /// held.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+
+        Some(self.acquire(&mut state))
+    }
+
+    fn acquire(&self, state: &mut MutexState) -> MutexGuard<'_, T> {
+        state.locked = true;
+        #[cfg(debug_assertions)]
+        {
+            state.owner = Some(current_task_id());
+        }
+
+        MutexGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Consumes the mutex and returns the protected data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a mutable reference to the protected data. No locking is
+    /// needed as the mutable borrow guarantees exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self) {
+        let mut state = self.state.lock();
+        #[cfg(debug_assertions)]
+        {
+            assert_eq!(
+                state.owner,
+                Some(current_task_id()),
+                "Mutex released by a task which does not hold it"
+            );
+            state.owner = None;
+        }
+        state.locked = false;
+        let waiter = state.waiters.wakeup();
+        drop(state);
+
+        if let Some(task) = waiter {
+            wake_task(task);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::run_blocked_task;
+
+    static CONTENDED: Mutex<u32> = Mutex::new(0);
+
+    extern "C" fn contended_task() {
+        *CONTENDED.lock() += 1;
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_mutex() {
+        let mutex = Mutex::new(0);
+        {
+            let mut guard = mutex.lock();
+            *guard += 1;
+            assert!(mutex.try_lock().is_none());
+        }
+
+        let mut guard = mutex.try_lock().expect("Mutex is still locked");
+        *guard += 1;
+        drop(guard);
+
+        assert_eq!(mutex.into_inner(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_mutex_contended() {
+        let guard = CONTENDED.lock();
+        // The task blocks on the mutex, releasing it wakes the task up
+        let exit_code = run_blocked_task(contended_task, move |_| {
+            assert_eq!(*guard, 0);
+            drop(guard);
+        });
+        assert_eq!(exit_code, 0);
+        assert_eq(*CONTENDED.lock(), 1);
+    }
+}
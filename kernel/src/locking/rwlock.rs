@@ -4,6 +4,7 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
+use crate::task::{preempt_disable, preempt_enable};
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU64, Ordering};
@@ -23,6 +24,7 @@ impl<T> Drop for ReadLockGuard<'_, T> {
     /// Release the read lock
     fn drop(&mut self) {
         self.rwlock.fetch_sub(1, Ordering::Release);
+        preempt_enable();
     }
 }
 
@@ -51,6 +53,7 @@ impl<T> Drop for WriteLockGuard<'_, T> {
     fn drop(&mut self) {
         // There are no readers - safe to just set lock to 0
         self.rwlock.store(0, Ordering::Release);
+        preempt_enable();
     }
 }
 
@@ -72,7 +75,8 @@ impl<T> DerefMut for WriteLockGuard<'_, T> {
 }
 
 /// A simple Read-Write Lock (RWLock) that allows multiple readers or
-/// one exclusive writer.
+/// one exclusive writer. Preemption is disabled on the current CPU while
+/// the lock is held.
 #[derive(Debug)]
 pub struct RWLock<T> {
     /// An atomic 64-bit integer used for synchronization
@@ -207,6 +211,7 @@ impl<T> RWLock<T> {
     /// A [`ReadLockGuard`] that provides read access to the protected data.
     ///
     pub fn lock_read(&self) -> ReadLockGuard<'_, T> {
+        preempt_disable();
         loop {
             let val = self.wait_for_writers();
             let (readers, _) = split_val(val);
@@ -236,6 +241,8 @@ impl<T> RWLock<T> {
     /// A [`WriteLockGuard`] that provides write access to the protected data.
     ///
     pub fn lock_write(&self) -> WriteLockGuard<'_, T> {
+        preempt_disable();
+
         // Waiting for current writer to finish
         loop {
             let val = self.wait_for_writers();
@@ -288,6 +295,7 @@ impl<T> RWLock<T> {
     pub unsafe fn unlock_write_direct(&self) {
         // There are no readers - safe to just set lock to 0
         self.rwlock.store(0, Ordering::Release);
+        preempt_enable();
     }
 }
 
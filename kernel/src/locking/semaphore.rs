@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use super::SpinLock;
+use crate::cpu::percpu::current_task;
+use crate::task::{schedule, schedule_timeout, wake_task, WaitQueue};
+use crate::time::{Duration, Instant};
+
+/// Internal state of a [`Semaphore`], protected by a [`SpinLock`]
+#[derive(Debug)]
+struct SemaphoreState {
+    /// Number of available units
+    count: usize,
+    /// Tasks waiting for a unit to become available
+    waiters: WaitQueue,
+}
+
+/// A counting semaphore. Tasks which try to take a unit while none is
+/// available sleep until another task releases one.
+///
+/// # Examples
+///
+/// ```no_run
+/// use svsm::locking::Semaphore;
+///
+/// let sem = Semaphore::new(2);
+/// sem.down();
+/// // ... use the resource ...
+/// sem.up();
+/// ```
+#[derive(Debug)]
+pub struct Semaphore {
+    state: SpinLock<SemaphoreState>,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with `count` available units.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            state: SpinLock::new(SemaphoreState {
+                count,
+                waiters: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Takes a unit from the semaphore, sleeping until one is available.
+    pub fn down(&self) {
+        loop {
+            let mut state = self.state.lock();
+            if state.count > 0 {
+                state.count -= 1;
+                return;
+            }
+
+            state.waiters.wait_for_event(current_task());
+            drop(state);
+
+            schedule();
+        }
+    }
+
+    /// Takes a unit from the semaphore, sleeping at most for `timeout` until
+    /// one is available.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a unit was taken, `false` if the timeout expired.
+    pub fn down_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let task = current_task();
+        loop {
+            let mut state = self.state.lock();
+            // A task woken up by up() is no longer on the queue
+            state.waiters.remove(&task);
+            if state.count > 0 {
+                state.count -= 1;
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+
+            state.waiters.wait_for_event(task.clone());
+            drop(state);
+
+            schedule_timeout(deadline);
+        }
+    }
+
+    /// Tries to take a unit from the semaphore without sleeping.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a unit was taken, `false` if none was available.
+    pub fn try_down(&self) -> bool {
+        let mut state = self.state.lock();
+        if state.count > 0 {
+            state.count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns a unit to the semaphore and wakes up the longest waiting
+    /// task, if any.
+    pub fn up(&self) {
+        let waiter = {
+            let mut state = self.state.lock();
+            state.count += 1;
+            state.waiters.wakeup()
+        };
+
+        if let Some(task) = waiter {
+            wake_task(task);
+        }
+    }
+
+    /// Returns the number of currently available units.
+    pub fn count(&self) -> usize {
+        self.state.lock().count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::run_blocked_task;
+
+    static BLOCKING: Semaphore = Semaphore::new(0);
+
+    extern "C" fn blocking_task() {
+        BLOCKING.down();
+    }
+
+    #[test]
+    fn test_semaphore() {
+        let sem = Semaphore::new(2);
+        sem.down();
+        assert!(sem.try_down());
+        assert!(!sem.try_down());
+        assert_eq!(sem.count(), 0);
+
+        sem.up();
+        sem.up();
+        assert_eq!(sem.count(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_semaphore_blocking() {
+        // No unit is available, so the task has to wait
+        let exit_code = run_blocked_task(blocking_task, |task| {
+            assert!(!task.is_terminated());
+            BLOCKING.up();
+        });
+        assert_eq!(exit_code, 0);
+        assert_eq!(BLOCKING.count(), 0);
+    }
+}
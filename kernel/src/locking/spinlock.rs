@@ -4,6 +4,7 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
+use crate::task::{preempt_disable, preempt_enable};
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU64, Ordering};
@@ -37,6 +38,7 @@ impl<T> Drop for LockGuard<'_, T> {
     /// Automatically releases the lock when the guard is dropped
     fn drop(&mut self) {
         self.holder.fetch_add(1, Ordering::Release);
+        preempt_enable();
     }
 }
 
@@ -61,6 +63,10 @@ impl<T> DerefMut for LockGuard<'_, T> {
 
 /// A simple spinlock implementation for protecting concurrent data access.
 ///
+/// Preemption is disabled on the current CPU while the lock is held, so that
+/// a task holding the lock is never switched out in favor of a task spinning
+/// on it.
+///
 /// # Examples
 ///
 /// ```
@@ -133,6 +139,7 @@ impl<T> SpinLock<T> {
     /// }; // Lock is automatically released when `guard` goes out of scope.
     /// ```
     pub fn lock(&self) -> LockGuard<'_, T> {
+        preempt_disable();
         let ticket = self.current.fetch_add(1, Ordering::Relaxed);
         loop {
             let h = self.holder.load(Ordering::Acquire);
@@ -152,6 +159,7 @@ impl<T> SpinLock<T> {
     /// successfully acquired, it returns a [`LockGuard`] that automatically
     /// releases the lock when it goes out of scope.
     pub fn try_lock(&self) -> Option<LockGuard<'_, T>> {
+        preempt_disable();
         let current = self.current.load(Ordering::Relaxed);
         let holder = self.holder.load(Ordering::Acquire);
 
@@ -170,6 +178,7 @@ impl<T> SpinLock<T> {
             }
         }
 
+        preempt_enable();
         None
     }
 }
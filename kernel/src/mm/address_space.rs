@@ -4,7 +4,9 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
-use crate::address::{PhysAddr, VirtAddr};
+use crate::address::{Address, PhysAddr, VirtAddr};
+#[cfg(target_os = "none")]
+use crate::mm::alloc::{root_mem_phys_to_virt, root_mem_virt_to_phys};
 use crate::utils::immut_after_init::ImmutAfterInitCell;
 
 #[derive(Copy, Clone)]
@@ -28,10 +30,33 @@ pub fn init_kernel_mapping_info(vstart: VirtAddr, vend: VirtAddr, pstart: PhysAd
         .expect("Already initialized kernel mapping info");
 }
 
+static KERNEL_SLIDE: ImmutAfterInitCell<usize> = ImmutAfterInitCell::new(0);
+
+/// Record the offset of the kernel's run-time virtual address relative to its
+/// link address, as chosen by stage2.
+pub fn init_kernel_slide(slide: usize) {
+    KERNEL_SLIDE.reinit(&slide);
+}
+
+/// Returns the offset of the kernel's run-time virtual address relative to
+/// its link address.
+pub fn kernel_slide() -> usize {
+    *KERNEL_SLIDE
+}
+
+/// Translates a run-time kernel address into the address the kernel was
+/// linked at, which is what symbol information refers to.
+pub fn kernel_link_addr(vaddr: VirtAddr) -> VirtAddr {
+    VirtAddr::from(vaddr.bits().wrapping_sub(kernel_slide()))
+}
+
 #[cfg(target_os = "none")]
 pub fn virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
     if vaddr < KERNEL_MAPPING.virt_start || vaddr >= KERNEL_MAPPING.virt_end {
-        panic!("Invalid physical address {:#018x}", vaddr);
+        // Memory regions added to the page allocator later on are mapped
+        // outside of the kernel mapping.
+        return root_mem_virt_to_phys(vaddr)
+            .unwrap_or_else(|| panic!("Invalid physical address {:#018x}", vaddr));
     }
 
     let offset: usize = vaddr - KERNEL_MAPPING.virt_start;
@@ -43,7 +68,8 @@ pub fn virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
 pub fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
     let size: usize = KERNEL_MAPPING.virt_end - KERNEL_MAPPING.virt_start;
     if paddr < KERNEL_MAPPING.phys_start || paddr >= KERNEL_MAPPING.phys_start + size {
-        panic!("Invalid physical address {:#018x}", paddr);
+        return root_mem_phys_to_virt(paddr)
+            .unwrap_or_else(|| panic!("Invalid physical address {:#018x}", paddr));
     }
 
     let offset: usize = paddr - KERNEL_MAPPING.phys_start;
@@ -53,13 +79,11 @@ pub fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
 
 #[cfg(not(target_os = "none"))]
 pub fn virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
-    use crate::address::Address;
     PhysAddr::from(vaddr.bits())
 }
 
 #[cfg(not(target_os = "none"))]
 pub fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
-    use crate::address::Address;
     VirtAddr::from(paddr.bits())
 }
 
@@ -142,6 +166,16 @@ pub const SVSM_PERCPU_TEMP_END_4K: VirtAddr = SVSM_PERCPU_TEMP_BASE_4K.const_add
 pub const SVSM_PERCPU_TEMP_BASE_2M: VirtAddr = SVSM_PERCPU_TEMP_BASE.const_add(SIZE_LEVEL1);
 pub const SVSM_PERCPU_TEMP_END_2M: VirtAddr = SVSM_PERCPU_TEMP_BASE.const_add(SIZE_LEVEL2);
 
+/// Large heap allocations level 3 index
+pub const PGTABLE_LVL3_IDX_HEAP: usize = 509;
+
+/// Base address of the region for heap allocations which are too big for
+/// the page allocator
+pub const SVSM_HEAP_VMALLOC_BASE: VirtAddr = virt_from_idx(PGTABLE_LVL3_IDX_HEAP);
+
+/// End address of the region for large heap allocations
+pub const SVSM_HEAP_VMALLOC_END: VirtAddr = SVSM_HEAP_VMALLOC_BASE.const_add(SIZE_LEVEL3);
+
 /// Task mappings level 3 index
 pub const PGTABLE_LVL3_IDX_PERTASK: usize = 508;
 
@@ -154,6 +188,25 @@ pub const SVSM_PERTASK_END: VirtAddr = SVSM_PERTASK_BASE.const_add(SIZE_LEVEL3);
 /// Kernel stack for a task
 pub const SVSM_PERTASK_STACK_BASE: VirtAddr = SVSM_PERTASK_BASE;
 
+/// User-space mappings level 3 index
+pub const PGTABLE_LVL3_IDX_USER: usize = 0;
+
+/// Base address of the user-space memory region
+pub const USER_MEM_START: VirtAddr = virt_from_idx(PGTABLE_LVL3_IDX_USER);
+
+/// End address of the user-space memory region
+pub const USER_MEM_END: VirtAddr = USER_MEM_START.const_add(SIZE_LEVEL3);
+
+/// Load address for position independent user-space executables
+pub const USER_IMAGE_BASE: VirtAddr = USER_MEM_START.const_add(4 * SIZE_1M);
+
+/// Size of the user-space stack of a task
+pub const USER_STACK_SIZE: usize = SIZE_1M;
+
+/// Base address of the user-space stack of a task. The stack is located at
+/// the very end of the user-space memory region.
+pub const USER_STACK_BASE: VirtAddr = USER_MEM_START.const_add(SIZE_LEVEL3 - USER_STACK_SIZE);
+
 #[cfg(test)]
 mod tests {
     use super::*;
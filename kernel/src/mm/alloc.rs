@@ -5,14 +5,21 @@
 // Author: Joerg Roedel <jroedel@suse.de>
 
 use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::cpu::percpu::{this_cpu_shared, PERCPU_AREAS};
+use crate::cpu::IrqGuard;
 use crate::error::SvsmError;
 use crate::locking::SpinLock;
+#[cfg(feature = "heap-debug")]
+use crate::mm::heap_debug;
 use crate::mm::virt_to_phys;
+use crate::mm::vmalloc_heap::{is_vmalloc_heap_addr, vmalloc_heap_alloc, vmalloc_heap_free};
 use crate::types::{PAGE_SHIFT, PAGE_SIZE};
 use crate::utils::{align_down, align_up, zero_mem_region};
 use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
 use core::mem::size_of;
 use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use log;
 
 #[cfg(any(test, feature = "fuzzing-hooks"))]
@@ -33,6 +40,12 @@ pub enum AllocError {
     InvalidFilePage(VirtAddr),
     /// The page frame number (PFN) is invalid.
     InvalidPfn(usize),
+    /// The memory region is invalid, overlaps an existing one or is unknown.
+    InvalidRegion(VirtAddr),
+    /// No more memory regions can be added.
+    TooManyRegions,
+    /// The memory region still has pages in use.
+    RegionInUse(VirtAddr),
 }
 
 impl From<AllocError> for SvsmError {
@@ -339,6 +352,23 @@ impl PageInfo {
 pub struct MemInfo {
     total_pages: [usize; MAX_ORDER],
     free_pages: [usize; MAX_ORDER],
+    slab_caches: SlabCacheStats,
+}
+
+impl MemInfo {
+    /// Returns the amount of free memory in units of 4KiB pages
+    pub fn free_pages_4k(&self) -> usize {
+        self.free_pages
+            .iter()
+            .enumerate()
+            .map(|(order, count)| count << order)
+            .sum()
+    }
+
+    /// Returns the statistics of the per-CPU slab caches
+    pub fn slab_cache_stats(&self) -> SlabCacheStats {
+        self.slab_caches
+    }
 }
 
 /// Memory region with its physical/virtual addresses, page count, as well
@@ -386,7 +416,6 @@ impl MemoryRegion {
     }
 
     /// Converts a virtual address to a physical address within the memory region.
-    #[allow(dead_code)]
     fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
         let offset = self.get_virt_offset(vaddr)?;
         Some(self.start_phys + offset)
@@ -419,6 +448,22 @@ impl MemoryRegion {
         self.start_virt + (self.page_count * PAGE_SIZE)
     }
 
+    /// Calculates the end physical address of the memory region.
+    fn end_phys(&self) -> PhysAddr {
+        self.start_phys + (self.page_count * PAGE_SIZE)
+    }
+
+    /// Number of pages at the start of a region of `page_count` pages which
+    /// hold the page information.
+    fn meta_pages(page_count: usize) -> usize {
+        align_up(page_count * size_of::<PageStorageType>(), PAGE_SIZE) / PAGE_SIZE
+    }
+
+    /// Returns whether none of the region's pages is allocated.
+    fn is_unused(&self) -> bool {
+        self.nr_pages == self.free_pages
+    }
+
     /// Writes page information for a given page frame number.
     fn write_page_info(&self, pfn: usize, pi: PageInfo) {
         self.check_pfn(pfn);
@@ -771,6 +816,9 @@ impl MemoryRegion {
 
         let res = self.read_page_info(pfn);
 
+        #[cfg(feature = "heap-debug")]
+        self.debug_free_page(pfn, &res);
+
         match res {
             PageInfo::Allocated(ai) => {
                 self.free_page_order(pfn, ai.order);
@@ -786,18 +834,37 @@ impl MemoryRegion {
             PageInfo::File(_) => {
                 self.free_page_order(pfn, 0);
             }
+            PageInfo::Free(_) => {
+                panic!("Double free of page {:#018x}", vaddr);
+            }
             _ => {
                 panic!("Unexpected page type in MemoryRegion::free_page()");
             }
         }
     }
 
+    /// Poisons pages which are about to be freed and drops their allocation
+    /// record.
+    #[cfg(feature = "heap-debug")]
+    fn debug_free_page(&self, pfn: usize, info: &PageInfo) {
+        let (start_pfn, order) = match info {
+            PageInfo::Allocated(ai) => (pfn, ai.order),
+            PageInfo::Compound(ci) => (pfn & !((1usize << ci.order) - 1), ci.order),
+            PageInfo::Slab(_) | PageInfo::File(_) => (pfn, 0),
+            _ => return,
+        };
+        let vaddr = self.start_virt + start_pfn * PAGE_SIZE;
+        heap_debug::poison(vaddr, PAGE_SIZE << order);
+        heap_debug::untrack_alloc(vaddr);
+    }
+
     /// Retrieves information about memory, including total and free pages
     /// in different orders.
     fn memory_info(&self) -> MemInfo {
         MemInfo {
             total_pages: self.nr_pages,
             free_pages: self.free_pages,
+            slab_caches: SlabCacheStats::default(),
         }
     }
 
@@ -805,8 +872,7 @@ impl MemoryRegion {
     /// as allocated. It then frees all pages and organizes them into their
     /// respective order buckets.
     fn init_memory(&mut self) {
-        let size = size_of::<PageStorageType>();
-        let meta_pages = align_up(self.page_count * size, PAGE_SIZE) / PAGE_SIZE;
+        let meta_pages = Self::meta_pages(self.page_count);
 
         /* Mark page storage as reserved */
         for i in 0..meta_pages {
@@ -952,11 +1018,200 @@ pub fn print_memory_info(info: &MemInfo) {
         (pages_4k * PAGE_SIZE) / 1024,
         (free_pages_4k * PAGE_SIZE) / 1024
     );
+
+    let caches = &info.slab_caches;
+    log::info!(
+        "Slab caches: {} cached objects, {} hits, {} refills, {} drains",
+        caches.cached,
+        caches.hits,
+        caches.refills,
+        caches.drains
+    );
+}
+
+/// Maximum number of memory regions managed by the root page allocator
+const MAX_MEMORY_REGIONS: usize = 16;
+
+/// Root page allocator managing a set of [`MemoryRegion`]s. Every region
+/// keeps its own [`PageInfo`] array at its start. Allocations are served from
+/// the regions in the order they were added.
+#[derive(Debug)]
+struct RootMem {
+    regions: [MemoryRegion; MAX_MEMORY_REGIONS],
+}
+
+impl RootMem {
+    /// Creates a new [`RootMem`] without any memory regions.
+    const fn new() -> Self {
+        const EMPTY: MemoryRegion = MemoryRegion::new();
+        Self {
+            regions: [EMPTY; MAX_MEMORY_REGIONS],
+        }
+    }
+
+    /// Returns an iterator over all regions in use.
+    fn active(&self) -> impl Iterator<Item = &MemoryRegion> {
+        self.regions.iter().filter(|r| r.page_count != 0)
+    }
+
+    /// Returns an iterator over all regions in use.
+    fn active_mut(&mut self) -> impl Iterator<Item = &mut MemoryRegion> {
+        self.regions.iter_mut().filter(|r| r.page_count != 0)
+    }
+
+    /// Finds the region containing the virtual address `vaddr`.
+    fn region(&self, vaddr: VirtAddr) -> Result<&MemoryRegion, AllocError> {
+        self.active()
+            .find(|r| r.get_virt_offset(vaddr).is_some())
+            .ok_or(AllocError::InvalidHeapAddress(vaddr))
+    }
+
+    /// Finds the region containing the virtual address `vaddr`.
+    fn region_mut(&mut self, vaddr: VirtAddr) -> Result<&mut MemoryRegion, AllocError> {
+        self.active_mut()
+            .find(|r| r.get_virt_offset(vaddr).is_some())
+            .ok_or(AllocError::InvalidHeapAddress(vaddr))
+    }
+
+    /// Adds a new memory region to the allocator and makes its pages
+    /// available for allocation.
+    ///
+    /// # Arguments
+    ///
+    /// * `pstart` - Physical start address of the region
+    /// * `vstart` - Virtual address the region is mapped at
+    /// * `page_count` - Size of the region in pages
+    fn add_region(
+        &mut self,
+        pstart: PhysAddr,
+        vstart: VirtAddr,
+        page_count: usize,
+    ) -> Result<(), AllocError> {
+        let size = page_count * PAGE_SIZE;
+        let overlaps = self.active().any(|r| {
+            let end_virt = vstart + size;
+            let end_phys = pstart + size;
+            (vstart < r.end_virt() && r.start_virt < end_virt)
+                || (pstart < r.end_phys() && r.start_phys < end_phys)
+        });
+        if overlaps || page_count <= MemoryRegion::meta_pages(page_count) {
+            return Err(AllocError::InvalidRegion(vstart));
+        }
+
+        let region = self
+            .regions
+            .iter_mut()
+            .find(|r| r.page_count == 0)
+            .ok_or(AllocError::TooManyRegions)?;
+
+        *region = MemoryRegion::new();
+        region.start_phys = pstart;
+        region.start_virt = vstart;
+        region.page_count = page_count;
+        region.init_memory();
+
+        Ok(())
+    }
+
+    /// Removes the memory region starting at virtual address `vstart`. This
+    /// only succeeds if none of the region's pages is in use.
+    fn remove_region(&mut self, vstart: VirtAddr) -> Result<(), AllocError> {
+        let region = self
+            .active_mut()
+            .find(|r| r.start_virt == vstart)
+            .ok_or(AllocError::InvalidRegion(vstart))?;
+
+        if !region.is_unused() {
+            return Err(AllocError::RegionInUse(vstart));
+        }
+
+        *region = MemoryRegion::new();
+        Ok(())
+    }
+
+    /// Tries `alloc` on every region until one succeeds.
+    fn allocate_with<F>(&mut self, mut alloc: F) -> Result<VirtAddr, AllocError>
+    where
+        F: FnMut(&mut MemoryRegion) -> Result<VirtAddr, AllocError>,
+    {
+        let mut err = AllocError::OutOfMemory;
+        for region in self.active_mut() {
+            match alloc(region) {
+                Ok(vaddr) => return Ok(vaddr),
+                Err(e) => err = e,
+            }
+        }
+        Err(err)
+    }
+
+    fn allocate_pages(&mut self, order: usize) -> Result<VirtAddr, AllocError> {
+        self.allocate_with(|r| r.allocate_pages(order))
+    }
+
+    fn allocate_page(&mut self) -> Result<VirtAddr, AllocError> {
+        self.allocate_with(|r| r.allocate_page())
+    }
+
+    fn allocate_zeroed_page(&mut self) -> Result<VirtAddr, AllocError> {
+        self.allocate_with(|r| r.allocate_zeroed_page())
+    }
+
+    fn allocate_slab_page(&mut self) -> Result<VirtAddr, AllocError> {
+        self.allocate_with(|r| r.allocate_slab_page())
+    }
+
+    fn allocate_file_page(&mut self) -> Result<VirtAddr, AllocError> {
+        self.allocate_with(|r| r.allocate_file_page())
+    }
+
+    fn get_file_page(&mut self, vaddr: VirtAddr) -> Result<(), AllocError> {
+        self.region_mut(vaddr)?.get_file_page(vaddr)
+    }
+
+    fn put_file_page(&mut self, vaddr: VirtAddr) -> Result<(), AllocError> {
+        self.region_mut(vaddr)?.put_file_page(vaddr)
+    }
+
+    fn free_page(&mut self, vaddr: VirtAddr) {
+        if let Ok(region) = self.region_mut(vaddr) {
+            region.free_page(vaddr);
+        }
+    }
+
+    /// Reads the page information of the page containing `vaddr`.
+    fn page_info(&self, vaddr: VirtAddr) -> Result<PageInfo, AllocError> {
+        let region = self.region(vaddr)?;
+        let pfn = region.get_pfn(vaddr)?;
+        Ok(region.read_page_info(pfn))
+    }
+
+    fn virt_to_phys(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        self.active().find_map(|r| r.virt_to_phys(vaddr))
+    }
+
+    fn phys_to_virt(&self, paddr: PhysAddr) -> Option<VirtAddr> {
+        self.active()
+            .find(|r| r.start_phys <= paddr && paddr < r.end_phys())
+            .map(|r| r.start_virt + (paddr - r.start_phys))
+    }
+
+    /// Sums up the page statistics of all regions.
+    fn memory_info(&self) -> MemInfo {
+        self.active()
+            .map(|r| r.memory_info())
+            .fold(MemInfo::default(), |mut acc, info| {
+                for order in 0..MAX_ORDER {
+                    acc.total_pages[order] += info.total_pages[order];
+                    acc.free_pages[order] += info.free_pages[order];
+                }
+                acc
+            })
+    }
 }
 
-/// Static spinlock-protected instance of [`MemoryRegion`] representing the
-/// root memory region.
-static ROOT_MEM: SpinLock<MemoryRegion> = SpinLock::new(MemoryRegion::new());
+/// Static spinlock-protected instance of [`RootMem`] managing all memory
+/// regions of the page allocator.
+static ROOT_MEM: SpinLock<RootMem> = SpinLock::new(RootMem::new());
 
 /// Allocates a single memory page from the root memory region.
 ///
@@ -965,7 +1220,10 @@ static ROOT_MEM: SpinLock<MemoryRegion> = SpinLock::new(MemoryRegion::new());
 /// Result containing the virtual address of the allocated page or an
 /// `SvsmError` if allocation fails.
 pub fn allocate_page() -> Result<VirtAddr, SvsmError> {
-    Ok(ROOT_MEM.lock().allocate_page()?)
+    let vaddr = ROOT_MEM.lock().allocate_page()?;
+    #[cfg(feature = "heap-debug")]
+    heap_debug::track_alloc(vaddr, PAGE_SIZE);
+    Ok(vaddr)
 }
 
 /// Allocates multiple memory pages with a specified order from the root
@@ -980,7 +1238,10 @@ pub fn allocate_page() -> Result<VirtAddr, SvsmError> {
 /// Result containing the virtual address of the allocated pages or an
 /// `SvsmError` if allocation fails.
 pub fn allocate_pages(order: usize) -> Result<VirtAddr, SvsmError> {
-    Ok(ROOT_MEM.lock().allocate_pages(order)?)
+    let vaddr = ROOT_MEM.lock().allocate_pages(order)?;
+    #[cfg(feature = "heap-debug")]
+    heap_debug::track_alloc(vaddr, PAGE_SIZE << order);
+    Ok(vaddr)
 }
 
 /// Allocate a slab page.
@@ -1000,7 +1261,10 @@ pub fn allocate_slab_page() -> Result<VirtAddr, SvsmError> {
 /// Result containing the virtual address of the allocated zeroed page or an
 /// `SvsmError` if allocation fails.
 pub fn allocate_zeroed_page() -> Result<VirtAddr, SvsmError> {
-    Ok(ROOT_MEM.lock().allocate_zeroed_page()?)
+    let vaddr = ROOT_MEM.lock().allocate_zeroed_page()?;
+    #[cfg(feature = "heap-debug")]
+    heap_debug::track_alloc(vaddr, PAGE_SIZE);
+    Ok(vaddr)
 }
 
 /// Allocate a file page.
@@ -1041,9 +1305,52 @@ pub fn free_page(vaddr: VirtAddr) {
     ROOT_MEM.lock().free_page(vaddr)
 }
 
+/// Adds a region of physical memory to the root page allocator. The memory
+/// must already be mapped at `vstart`. The start of the region is used to hold
+/// the page information for the region.
+///
+/// # Arguments
+///
+/// * `pstart` - Physical start address of the region
+/// * `vstart` - Virtual address the region is mapped at
+/// * `page_count` - Size of the region in pages
+///
+/// # Returns
+///
+/// `Ok(())` on success, or an `SvsmError` if the region overlaps an existing
+/// one, is too small or no more regions can be added.
+pub fn root_mem_add_region(
+    pstart: PhysAddr,
+    vstart: VirtAddr,
+    page_count: usize,
+) -> Result<(), SvsmError> {
+    Ok(ROOT_MEM.lock().add_region(pstart, vstart, page_count)?)
+}
+
+/// Removes the region starting at virtual address `vstart` from the root
+/// page allocator. Fails if any of its pages is still allocated. Afterwards
+/// the memory can be unmapped and used for other purposes.
+pub fn root_mem_remove_region(vstart: VirtAddr) -> Result<(), SvsmError> {
+    Ok(ROOT_MEM.lock().remove_region(vstart)?)
+}
+
+/// Translates a virtual address in any of the root allocator's regions to
+/// its physical address.
+pub fn root_mem_virt_to_phys(vaddr: VirtAddr) -> Option<PhysAddr> {
+    ROOT_MEM.lock().virt_to_phys(vaddr)
+}
+
+/// Translates a physical address in any of the root allocator's regions to
+/// its virtual address.
+pub fn root_mem_phys_to_virt(paddr: PhysAddr) -> Option<VirtAddr> {
+    ROOT_MEM.lock().phys_to_virt(paddr)
+}
+
 /// Retrieve information about the root memory
 pub fn memory_info() -> MemInfo {
-    ROOT_MEM.lock().memory_info()
+    let mut info = ROOT_MEM.lock().memory_info();
+    info.slab_caches = slab_cache_stats();
+    info
 }
 
 /// Represents a slab memory page, used for efficient allocation of
@@ -1152,6 +1459,11 @@ impl SlabPage {
         let idx = i / 64;
         let mask = 1u64 << (i % 64);
 
+        #[cfg(feature = "heap-debug")]
+        if self.used_bitmap[idx] & mask == 0 {
+            panic!("Double free of slab object {:#018x}", vaddr);
+        }
+
         self.used_bitmap[idx] &= !mask;
         self.free += 1;
 
@@ -1449,6 +1761,215 @@ impl Slab {
 /// slab page allocator.
 static SLAB_PAGE_SLAB: SpinLock<SlabPageSlab> = SpinLock::new(SlabPageSlab::new());
 
+/// Number of slab sizes served by [`SvsmAllocator`]
+const SLAB_COUNT: usize = 7;
+
+/// Number of free objects a per-CPU magazine can hold
+const MAGAZINE_SIZE: usize = 32;
+
+/// Number of objects moved between a magazine and the global slab at once
+const MAGAZINE_BATCH: usize = MAGAZINE_SIZE / 2;
+
+/// Per-CPU stack of free objects of one slab size.
+#[derive(Clone, Copy, Debug)]
+struct Magazine {
+    objects: [VirtAddr; MAGAZINE_SIZE],
+    count: usize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            objects: [VirtAddr::null(); MAGAZINE_SIZE],
+            count: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Option<VirtAddr> {
+        self.count = self.count.checked_sub(1)?;
+        Some(self.objects[self.count])
+    }
+
+    fn push(&mut self, vaddr: VirtAddr) {
+        self.objects[self.count] = vaddr;
+        self.count += 1;
+    }
+
+    fn is_full(&self) -> bool {
+        self.count == MAGAZINE_SIZE
+    }
+}
+
+/// Statistics about the per-CPU slab caches, summed up over all CPUs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SlabCacheStats {
+    /// Free objects currently held in per-CPU magazines
+    pub cached: usize,
+    /// Allocations served from a per-CPU magazine
+    pub hits: usize,
+    /// Allocations which refilled a magazine from the global slab
+    pub refills: usize,
+    /// Frees which drained a full magazine to the global slab
+    pub drains: usize,
+}
+
+/// Per-CPU caches of free slab objects with one [`Magazine`] per slab size.
+/// Allocations and frees are served from the magazine of the current CPU
+/// without taking the lock of the global slab. Empty magazines are refilled
+/// and full magazines drained in batches of [`MAGAZINE_BATCH`] objects.
+///
+/// The magazines are only accessed from the CPU owning them with interrupts
+/// disabled. The statistics are atomic, so that they can be read from other
+/// CPUs.
+#[derive(Debug)]
+pub struct SlabCaches {
+    magazines: UnsafeCell<[Magazine; SLAB_COUNT]>,
+    cached: AtomicUsize,
+    hits: AtomicUsize,
+    refills: AtomicUsize,
+    drains: AtomicUsize,
+}
+
+// SAFETY: The magazines are only accessed by the CPU owning them, other CPUs
+// only read the atomic statistics.
+unsafe impl Sync for SlabCaches {}
+
+const _: () = assert!(size_of::<SlabCaches>() <= PAGE_SIZE);
+
+impl SlabCaches {
+    const fn new() -> Self {
+        Self {
+            magazines: UnsafeCell::new([Magazine::new(); SLAB_COUNT]),
+            cached: AtomicUsize::new(0),
+            hits: AtomicUsize::new(0),
+            refills: AtomicUsize::new(0),
+            drains: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allocates the per-CPU slab caches for one CPU. They live as long as
+    /// the CPU's per-CPU data and are never freed.
+    pub fn alloc() -> Result<&'static Self, SvsmError> {
+        let vaddr = allocate_page()?;
+        let caches = vaddr.as_mut_ptr::<Self>();
+        // SAFETY: The page was just allocated and is large enough to hold
+        // the caches.
+        unsafe {
+            caches.write(Self::new());
+            Ok(&*caches)
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Must only be called on the CPU owning the caches with interrupts
+    /// disabled, so that no other reference to the magazine exists.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn magazine(&self, idx: usize) -> &mut Magazine {
+        &mut (*self.magazines.get())[idx]
+    }
+
+    /// Allocates an object from the magazine for slab `idx`, refilling the
+    /// magazine from `slab` if it is empty.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called on the CPU owning the caches with interrupts
+    /// disabled.
+    unsafe fn allocate(&self, idx: usize, slab: &SpinLock<Slab>) -> Result<VirtAddr, AllocError> {
+        let magazine = self.magazine(idx);
+        if let Some(vaddr) = magazine.pop() {
+            self.cached.fetch_sub(1, Ordering::Relaxed);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(vaddr);
+        }
+
+        let mut slab = slab.lock();
+        let vaddr = slab.allocate()?;
+        // Refilling is best effort, the allocation itself succeeded already
+        while magazine.count < MAGAZINE_BATCH {
+            let Ok(obj) = slab.allocate() else {
+                break;
+            };
+            magazine.push(obj);
+            self.cached.fetch_add(1, Ordering::Relaxed);
+        }
+        self.refills.fetch_add(1, Ordering::Relaxed);
+
+        Ok(vaddr)
+    }
+
+    /// Returns an object to the magazine for slab `idx`. A full magazine is
+    /// first drained to `slab`.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called on the CPU owning the caches with interrupts
+    /// disabled.
+    unsafe fn deallocate(&self, idx: usize, slab: &SpinLock<Slab>, vaddr: VirtAddr) {
+        let magazine = self.magazine(idx);
+        if magazine.is_full() {
+            let mut slab = slab.lock();
+            for _ in 0..MAGAZINE_BATCH {
+                let obj = magazine.pop().unwrap();
+                slab.deallocate(obj);
+            }
+            self.cached.fetch_sub(MAGAZINE_BATCH, Ordering::Relaxed);
+            self.drains.fetch_add(1, Ordering::Relaxed);
+        }
+
+        magazine.push(vaddr);
+        self.cached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> SlabCacheStats {
+        SlabCacheStats {
+            cached: self.cached.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            refills: self.refills.load(Ordering::Relaxed),
+            drains: self.drains.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Set once the per-CPU areas are mapped. Before that (and in stage2 or unit
+/// tests) all slab allocations go to the global slabs.
+static SLAB_CACHES_READY: AtomicBool = AtomicBool::new(false);
+
+/// Start using the per-CPU slab caches. Must be called on the BSP right after
+/// its per-CPU area has been loaded.
+pub fn slab_caches_init() {
+    SLAB_CACHES_READY.store(true, Ordering::Relaxed);
+}
+
+/// Returns whether the per-CPU area of the current CPU is mapped, see
+/// [`slab_caches_init()`].
+pub(crate) fn percpu_ready() -> bool {
+    SLAB_CACHES_READY.load(Ordering::Relaxed)
+}
+
+/// Returns whether allocations go through the per-CPU slab caches.
+fn slab_caches_enabled() -> bool {
+    // Heap debugging needs to see every free immediately
+    !cfg!(feature = "heap-debug") && percpu_ready()
+}
+
+fn slab_cache_stats() -> SlabCacheStats {
+    if !percpu_ready() {
+        return SlabCacheStats::default();
+    }
+
+    PERCPU_AREAS
+        .iter()
+        .map(|cpu| cpu.slab_caches().stats())
+        .fold(SlabCacheStats::default(), |acc, s| SlabCacheStats {
+            cached: acc.cached + s.cached,
+            hits: acc.hits + s.hits,
+            refills: acc.refills + s.refills,
+            drains: acc.drains + s.drains,
+        })
+}
+
 /// Represents a simple virtual-to-physical memory allocator ([`SvsmAllocator`])
 /// implementing the [`GlobalAlloc`] trait.
 ///
@@ -1456,7 +1977,7 @@ static SLAB_PAGE_SLAB: SpinLock<SlabPageSlab> = SpinLock::new(SlabPageSlab::new(
 /// back to page allocation for larger objects.
 #[derive(Debug)]
 pub struct SvsmAllocator {
-    slabs: [SpinLock<Slab>; 7],
+    slabs: [SpinLock<Slab>; SLAB_COUNT],
 }
 
 impl SvsmAllocator {
@@ -1482,16 +2003,70 @@ impl SvsmAllocator {
         }
     }
 
-    /// Get a reference to the appropriate slab for an allocation of the specified size,
-    /// or [`None`] if the size is too big.
-    fn get_slab(&self, size: usize) -> Option<&SpinLock<Slab>> {
+    /// Number of bytes allocated for a request of `size` bytes. With the
+    /// `heap-debug` feature this includes space for the redzone.
+    #[cfg(feature = "heap-debug")]
+    fn alloc_size(size: usize) -> usize {
+        size + heap_debug::REDZONE_SIZE
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    fn alloc_size(size: usize) -> usize {
+        size
+    }
+
+    /// Size of the memory block backing an allocation of `size` bytes
+    #[cfg(feature = "heap-debug")]
+    fn block_size(size: usize) -> usize {
+        match Self::slab_index(size) {
+            Some(idx) => (Self::MIN_SLAB_SIZE as usize) << idx,
+            None if get_order(size) < MAX_ORDER => PAGE_SIZE << get_order(size),
+            None => align_up(size, PAGE_SIZE),
+        }
+    }
+
+    /// Get the index into `self.slabs` for an allocation of the specified
+    /// size, or [`None`] if the size is too big.
+    fn slab_index(size: usize) -> Option<usize> {
         let slab_size = size.checked_next_power_of_two()?;
         // Go from an allocation size to an index into `self.slabs`.
         let idx = slab_size
             .trailing_zeros()
             .saturating_sub(Self::MIN_ALIGNMENT) as usize;
-        // The index might be too large, so use a safe getter.
-        self.slabs.get(idx)
+        (idx < SLAB_COUNT).then_some(idx)
+    }
+
+    /// Allocates an object from slab `idx`, going through the per-CPU
+    /// caches once they are available.
+    fn slab_allocate(&self, idx: usize) -> Result<VirtAddr, AllocError> {
+        let slab = &self.slabs[idx];
+        if !slab_caches_enabled() {
+            return slab.lock().allocate();
+        }
+
+        // The caches of the current CPU must only be looked up with
+        // interrupts disabled, otherwise the task could migrate to another
+        // CPU in between.
+        let _guard = IrqGuard::new();
+        let caches = this_cpu_shared().slab_caches();
+        // SAFETY: Interrupts are disabled, so the task can neither migrate
+        // nor be interrupted by another allocation while it uses the caches
+        // of the current CPU.
+        unsafe { caches.allocate(idx, slab) }
+    }
+
+    /// Returns an object to slab `idx`, going through the per-CPU caches
+    /// once they are available.
+    fn slab_deallocate(&self, idx: usize, vaddr: VirtAddr) {
+        let slab = &self.slabs[idx];
+        if !slab_caches_enabled() {
+            return slab.lock().deallocate(vaddr);
+        }
+
+        let _guard = IrqGuard::new();
+        let caches = this_cpu_shared().slab_caches();
+        // SAFETY: See slab_allocate()
+        unsafe { caches.deallocate(idx, slab, vaddr) }
     }
 
     /// Resets the internal state. This is equivalent to reassigning `self`
@@ -1513,44 +2088,76 @@ impl SvsmAllocator {
 unsafe impl GlobalAlloc for SvsmAllocator {
     /// Allocates memory based on the specified layout.
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let size = layout.size();
-        let ret = match self.get_slab(size) {
-            Some(slab) => slab.lock().allocate().map_err(|e| e.into()),
+        let size = Self::alloc_size(layout.size());
+        let ret = match Self::slab_index(size) {
+            Some(idx) => self.slab_allocate(idx).map_err(|e| e.into()),
             None => {
                 let order = get_order(size);
-                if order >= MAX_ORDER {
+                if order < MAX_ORDER {
+                    allocate_pages(order)
+                } else if layout.align() <= PAGE_SIZE {
+                    vmalloc_heap_alloc(size).map_err(|_| AllocError::OutOfMemory)
+                } else {
                     return ptr::null_mut();
                 }
-                allocate_pages(order)
             }
         };
 
-        ret.map_or_else(|_| ptr::null_mut(), |addr| addr.as_mut_ptr::<u8>())
+        let Ok(addr) = ret else {
+            return ptr::null_mut();
+        };
+
+        #[cfg(feature = "heap-debug")]
+        {
+            heap_debug::fill_redzone(addr, layout.size(), Self::block_size(size));
+            heap_debug::track_alloc(addr, layout.size());
+        }
+
+        addr.as_mut_ptr::<u8>()
     }
 
     /// Deallocates memory based on the specified pointer and layout.
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let virt_addr = VirtAddr::from(ptr);
-        let size = layout.size();
+        let size = Self::alloc_size(layout.size());
 
-        let info = {
-            let mem = ROOT_MEM.lock();
-            let pfn = mem.get_pfn(virt_addr).expect("Freeing unknown memory");
-            mem.read_page_info(pfn)
-        };
+        if is_vmalloc_heap_addr(virt_addr) {
+            #[cfg(feature = "heap-debug")]
+            {
+                heap_debug::check_and_poison(virt_addr, layout.size(), Self::block_size(size));
+                heap_debug::untrack_alloc(virt_addr);
+            }
+            vmalloc_heap_free(virt_addr);
+            return;
+        }
+
+        let info = ROOT_MEM
+            .lock()
+            .page_info(virt_addr)
+            .expect("Freeing unknown memory");
 
         match info {
-            PageInfo::Allocated(_ai) => {
-                free_page(virt_addr);
-            }
-            PageInfo::Slab(_si) => {
-                let slab = self.get_slab(size).expect("Invalid page info");
-                slab.lock().deallocate(virt_addr);
+            PageInfo::Allocated(_) | PageInfo::Slab(_) => {}
+            PageInfo::Free(_) => {
+                panic!("Double free of heap memory at {:#018x}", virt_addr);
             }
             _ => {
                 panic!("Freeing memory on unsupported page type");
             }
         }
+
+        #[cfg(feature = "heap-debug")]
+        {
+            heap_debug::check_and_poison(virt_addr, layout.size(), Self::block_size(size));
+            heap_debug::untrack_alloc(virt_addr);
+        }
+
+        if let PageInfo::Slab(_) = info {
+            let idx = Self::slab_index(size).expect("Invalid page info");
+            self.slab_deallocate(idx, virt_addr);
+        } else {
+            free_page(virt_addr);
+        }
     }
 }
 
@@ -1558,17 +2165,14 @@ unsafe impl GlobalAlloc for SvsmAllocator {
 #[cfg_attr(not(target_os = "none"), allow(dead_code))]
 static ALLOCATOR: SvsmAllocator = SvsmAllocator::new();
 
-/// Initializes the root memory region with the specified physical start
-/// address, virtual start address, and page count.
+/// Initializes the root page allocator with its first memory region, with the
+/// specified physical start address, virtual start address, and page count.
+/// More regions can be added with [`root_mem_add_region()`].
 pub fn root_mem_init(pstart: PhysAddr, vstart: VirtAddr, page_count: usize) {
-    {
-        let mut region = ROOT_MEM.lock();
-        region.start_phys = pstart;
-        region.start_virt = vstart;
-        region.page_count = page_count;
-        region.init_memory();
-        // drop lock here so slab initialization does not deadlock
-    }
+    ROOT_MEM
+        .lock()
+        .add_region(pstart, vstart, page_count)
+        .expect("Failed to initialize root memory region");
 
     SLAB_PAGE_SLAB
         .lock()
@@ -1646,10 +2250,13 @@ impl Drop for TestRootMem<'_> {
         extern crate alloc;
         use alloc::alloc::dealloc;
 
+        // The region set up by TestRootMem::setup() is always the first one,
+        // tests adding more regions must remove them again.
         let mut root_mem = ROOT_MEM.lock();
-        let layout = Layout::from_size_align(root_mem.page_count * PAGE_SIZE, PAGE_SIZE).unwrap();
-        unsafe { dealloc(root_mem.start_virt.as_mut_ptr::<u8>(), layout) };
-        *root_mem = MemoryRegion::new();
+        let region = &root_mem.regions[0];
+        let layout = Layout::from_size_align(region.page_count * PAGE_SIZE, PAGE_SIZE).unwrap();
+        unsafe { dealloc(region.start_virt.as_mut_ptr::<u8>(), layout) };
+        *root_mem = RootMem::new();
 
         // Reset the Slabs
         *SLAB_PAGE_SLAB.lock() = SlabPageSlab::new();
@@ -1783,6 +2390,61 @@ fn test_page_alloc_oom() {
     assert_eq!(info_before.free_pages, root_mem.memory_info().free_pages);
 }
 
+#[test]
+#[cfg_attr(test_in_svsm, ignore = "FIXME")]
+/// Add a second memory region, allocate from it and remove it again.
+fn test_root_mem_regions() {
+    extern crate alloc;
+    use alloc::alloc::{alloc, dealloc};
+
+    const REGION_PAGES: usize = 64;
+
+    let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+    let layout = Layout::from_size_align(REGION_PAGES * PAGE_SIZE, PAGE_SIZE).unwrap();
+    let ptr = unsafe { alloc(layout) };
+    assert!(!ptr.is_null());
+    let vaddr = VirtAddr::from(ptr);
+    let paddr = PhysAddr::from(vaddr.bits()); // Identity mapping
+
+    let mut root_mem = ROOT_MEM.lock();
+    let info_before = root_mem.memory_info();
+    root_mem.add_region(paddr, vaddr, REGION_PAGES).unwrap();
+    assert_eq!(
+        root_mem.add_region(paddr + PAGE_SIZE, vaddr + PAGE_SIZE, 8),
+        Err(AllocError::InvalidRegion(vaddr + PAGE_SIZE))
+    );
+
+    // One page of the new region holds its page information
+    let info = root_mem.memory_info();
+    assert_eq!(
+        info.free_pages_4k(),
+        info_before.free_pages_4k() + REGION_PAGES - 1
+    );
+
+    // Pages of the new region are looked up in the right region
+    let page = root_mem.regions[1].allocate_page().unwrap();
+    assert!(matches!(
+        root_mem.page_info(page),
+        Ok(PageInfo::Allocated(_))
+    ));
+    assert_eq!(
+        root_mem.virt_to_phys(page),
+        Some(PhysAddr::from(page.bits()))
+    );
+    assert_eq!(
+        root_mem.remove_region(vaddr),
+        Err(AllocError::RegionInUse(vaddr))
+    );
+
+    root_mem.free_page(page);
+    root_mem.remove_region(vaddr).unwrap();
+    assert_eq!(root_mem.memory_info().free_pages, info_before.free_pages);
+    assert!(root_mem.page_info(page).is_err());
+    drop(root_mem);
+
+    unsafe { dealloc(ptr, layout) };
+}
+
 #[test]
 fn test_page_file() {
     let _mem_lock = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
@@ -1790,26 +2452,25 @@ fn test_page_file() {
 
     // Allocate page and check ref-count
     let vaddr = root_mem.allocate_file_page().unwrap();
-    let pfn = root_mem.get_pfn(vaddr).unwrap();
-    let info = root_mem.read_page_info(pfn);
+    let info = root_mem.page_info(vaddr).unwrap();
 
     assert!(matches!(info, PageInfo::File(ref fi) if fi.ref_count == 1));
 
     // Get another reference and check ref-count
     root_mem.get_file_page(vaddr).expect("Not a file page");
-    let info = root_mem.read_page_info(pfn);
+    let info = root_mem.page_info(vaddr).unwrap();
 
     assert!(matches!(info, PageInfo::File(ref fi) if fi.ref_count == 2));
 
     // Drop reference and check ref-count
     root_mem.put_file_page(vaddr).expect("Not a file page");
-    let info = root_mem.read_page_info(pfn);
+    let info = root_mem.page_info(vaddr).unwrap();
 
     assert!(matches!(info, PageInfo::File(ref fi) if fi.ref_count == 1));
 
     // Drop last reference and check if page is released
     root_mem.put_file_page(vaddr).expect("Not a file page");
-    let info = root_mem.read_page_info(pfn);
+    let info = root_mem.page_info(vaddr).unwrap();
 
     assert!(matches!(info, PageInfo::Free { .. }));
 }
@@ -1929,3 +2590,55 @@ fn test_slab_oom() {
         unsafe { ALLOCATOR.dealloc(p, layout) };
     }
 }
+
+#[test]
+#[cfg_attr(test_in_svsm, ignore = "FIXME")]
+/// Allocate and free objects through a set of per-CPU slab caches and verify
+/// that magazines are refilled and drained in batches.
+fn test_slab_caches() {
+    extern crate alloc;
+    use alloc::vec::Vec;
+
+    let _mem_lock = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+
+    const IDX: usize = 1;
+    let slab = SpinLock::new(Slab::new(TEST_SLAB_SIZES[IDX] as u16));
+    let caches = SlabCaches::new();
+
+    // The first allocation refills the magazine
+    let first = unsafe { caches.allocate(IDX, &slab) }.unwrap();
+    let stats = caches.stats();
+    assert_eq!(stats.refills, 1);
+    assert_eq!(stats.hits, 0);
+    assert_eq!(stats.cached, MAGAZINE_BATCH);
+    {
+        let slab = slab.lock();
+        assert_eq!(
+            slab.common.free as usize,
+            slab.common.capacity as usize - MAGAZINE_BATCH - 1
+        );
+    }
+
+    // Further allocations are served from the magazine
+    let mut allocs: Vec<VirtAddr> = Vec::new();
+    allocs.push(first);
+    for _ in 0..MAGAZINE_BATCH {
+        allocs.push(unsafe { caches.allocate(IDX, &slab) }.unwrap());
+    }
+    let stats = caches.stats();
+    assert_eq!(stats.refills, 1);
+    assert_eq!(stats.hits, MAGAZINE_BATCH);
+    assert_eq!(stats.cached, 0);
+
+    // Allocate enough objects to overflow the magazine when freeing them
+    while allocs.len() <= MAGAZINE_SIZE {
+        allocs.push(unsafe { caches.allocate(IDX, &slab) }.unwrap());
+    }
+    let nr_allocs = allocs.len();
+    for vaddr in allocs {
+        unsafe { caches.deallocate(IDX, &slab, vaddr) };
+    }
+    let stats = caches.stats();
+    assert_eq!(stats.drains, 1);
+    assert_eq!(stats.cached, nr_allocs - MAGAZINE_BATCH);
+}
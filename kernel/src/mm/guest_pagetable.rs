@@ -0,0 +1,459 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Translation of guest virtual addresses through the page-tables of the
+//! guest, and access to guest memory by guest virtual address.
+
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::cpu::control_regs::{CR0Flags, CR4Flags};
+use crate::cpu::efer::EFERFlags;
+use crate::error::SvsmError;
+use crate::mm::guestmem::{read_guest_phys, read_guest_phys_mem, write_guest_phys_mem};
+use crate::mm::pagetable::{encrypt_mask, PTEntryFlags};
+use crate::types::PAGE_SIZE;
+use bitflags::bitflags;
+use cpuarch::vmsa::VMSA;
+
+/// Page-fault error code bit for protection violations on present pages
+const PF_PRESENT: u32 = 1 << 0;
+/// Page-fault error code bit for reserved bits set in a page-table entry
+const PF_RSVD: u32 = 1 << 3;
+
+/// Mask of the physical address bits in a page-table entry
+const PTE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+bitflags! {
+    /// Type of a guest memory access. The values match the corresponding
+    /// bits of the x86 page-fault error code.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct GuestAccess: u32 {
+        const WRITE = 1 << 1;
+        const USER  = 1 << 2;
+        const FETCH = 1 << 4;
+    }
+}
+
+/// Errors from translating guest virtual addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuestPtError {
+    /// The guest uses a paging mode which is not supported (legacy or PAE
+    /// paging)
+    UnsupportedMode,
+    /// The address is not canonical for the paging mode of the guest
+    NonCanonical(VirtAddr),
+    /// The access would cause a page fault in the guest, contains the
+    /// page-fault error code
+    PageFault(u32),
+}
+
+impl From<GuestPtError> for SvsmError {
+    fn from(err: GuestPtError) -> Self {
+        Self::GuestPageTable(err)
+    }
+}
+
+/// Paging mode of the guest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuestPagingMode {
+    /// Paging is disabled, guest virtual addresses are physical addresses
+    Disabled,
+    /// 4-level paging with 48-bit virtual addresses
+    Level4,
+    /// 5-level paging with 57-bit virtual addresses
+    Level5,
+}
+
+impl GuestPagingMode {
+    fn levels(self) -> usize {
+        match self {
+            Self::Disabled => 0,
+            Self::Level4 => 4,
+            Self::Level5 => 5,
+        }
+    }
+}
+
+/// Result of a guest address translation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GuestTranslation {
+    /// Guest physical address with the C-bit removed
+    pub paddr: PhysAddr,
+    /// Size of the guest page containing the address
+    pub page_size: usize,
+    /// Whether the page is private to the guest (C-bit set)
+    pub encrypted: bool,
+}
+
+/// Snapshot of the paging configuration of a guest VCPU
+#[derive(Clone, Copy, Debug)]
+pub struct GuestPageTable {
+    mode: GuestPagingMode,
+    /// Guest physical address of the top-level page-table
+    root: PhysAddr,
+    /// CR0.WP - supervisor writes honor read-only pages
+    write_protect: bool,
+    /// EFER.NXE - the NX bit is valid in page-table entries
+    nx_enabled: bool,
+    /// Mask of the C-bit in page-table entries
+    c_bit: u64,
+}
+
+impl GuestPageTable {
+    /// Creates a [`GuestPageTable`] from the control registers of a guest
+    /// VCPU.
+    ///
+    /// # Returns
+    ///
+    /// The page-table on success, or `GuestPtError::UnsupportedMode` when
+    /// the guest has paging enabled but is not in long mode.
+    pub fn new(cr0: u64, cr3: u64, cr4: u64, efer: u64) -> Result<Self, SvsmError> {
+        let cr0 = CR0Flags::from_bits_truncate(cr0);
+        let cr4 = CR4Flags::from_bits_truncate(cr4);
+        let efer = EFERFlags::from_bits_truncate(efer);
+        let c_bit = encrypt_mask() as u64;
+
+        let mode = if !cr0.contains(CR0Flags::PG) {
+            GuestPagingMode::Disabled
+        } else if !efer.contains(EFERFlags::LMA) {
+            return Err(GuestPtError::UnsupportedMode.into());
+        } else if cr4.contains(CR4Flags::LA57) {
+            GuestPagingMode::Level5
+        } else {
+            GuestPagingMode::Level4
+        };
+
+        Ok(Self {
+            mode,
+            root: PhysAddr::from(cr3 & PTE_ADDR_MASK & !c_bit),
+            write_protect: cr0.contains(CR0Flags::WP),
+            nx_enabled: efer.contains(EFERFlags::NXE),
+            c_bit,
+        })
+    }
+
+    /// Creates a [`GuestPageTable`] from the current register state in a
+    /// guest VMSA.
+    pub fn from_vmsa(vmsa: &VMSA) -> Result<Self, SvsmError> {
+        Self::new(vmsa.cr0, vmsa.cr3, vmsa.cr4, vmsa.efer)
+    }
+
+    /// Returns the paging mode of the guest
+    pub fn mode(&self) -> GuestPagingMode {
+        self.mode
+    }
+
+    fn canonical(&self, vaddr: VirtAddr) -> bool {
+        let bits = match self.mode {
+            GuestPagingMode::Disabled => return true,
+            GuestPagingMode::Level4 => 48,
+            GuestPagingMode::Level5 => 57,
+        };
+        let shift = 64 - bits;
+        (((vaddr.bits() << shift) as i64) >> shift) as usize == vaddr.bits()
+    }
+
+    /// Walks the guest page-table, reading entries with `read_entry`.
+    fn walk<F>(
+        &self,
+        vaddr: VirtAddr,
+        access: GuestAccess,
+        mut read_entry: F,
+    ) -> Result<GuestTranslation, SvsmError>
+    where
+        F: FnMut(PhysAddr) -> Result<u64, SvsmError>,
+    {
+        if self.mode == GuestPagingMode::Disabled {
+            // Without paging all accesses of SEV-SNP guests are private
+            return Ok(GuestTranslation {
+                paddr: PhysAddr::from(vaddr.bits()),
+                page_size: PAGE_SIZE,
+                encrypted: true,
+            });
+        }
+
+        if !self.canonical(vaddr) {
+            return Err(GuestPtError::NonCanonical(vaddr).into());
+        }
+
+        let fault = |bits: u32| GuestPtError::PageFault(access.bits() | bits);
+        let mut table = self.root;
+        let mut writable = true;
+        let mut user = true;
+        let mut no_exec = false;
+
+        for level in (0..self.mode.levels()).rev() {
+            let shift = 12 + 9 * level;
+            let index = (vaddr.bits() >> shift) & 0x1ff;
+            let entry = read_entry(table + index * 8)?;
+            let flags = PTEntryFlags::from_bits_truncate(entry);
+
+            if !flags.contains(PTEntryFlags::PRESENT) {
+                return Err(fault(0).into());
+            }
+
+            let huge = flags.contains(PTEntryFlags::HUGE);
+            if (!self.nx_enabled && flags.contains(PTEntryFlags::NX)) || (huge && level > 2) {
+                return Err(fault(PF_PRESENT | PF_RSVD).into());
+            }
+
+            writable &= flags.contains(PTEntryFlags::WRITABLE);
+            user &= flags.contains(PTEntryFlags::USER);
+            no_exec |= self.nx_enabled && flags.contains(PTEntryFlags::NX);
+
+            let addr = entry & PTE_ADDR_MASK & !self.c_bit;
+            if level > 0 && !huge {
+                table = PhysAddr::from(addr);
+                continue;
+            }
+
+            let page_size = 1usize << shift;
+            // Large pages have the PAT bit in bit 12
+            let base = addr as usize & !(page_size - 1);
+
+            let violation = (access.contains(GuestAccess::WRITE)
+                && !writable
+                && (access.contains(GuestAccess::USER) || self.write_protect))
+                || (access.contains(GuestAccess::USER) && !user)
+                || (access.contains(GuestAccess::FETCH) && no_exec);
+            if violation {
+                return Err(fault(PF_PRESENT).into());
+            }
+
+            return Ok(GuestTranslation {
+                paddr: PhysAddr::from(base | (vaddr.bits() & (page_size - 1))),
+                page_size,
+                encrypted: (entry & self.c_bit) != 0,
+            });
+        }
+
+        unreachable!()
+    }
+
+    /// Translates a guest virtual address to a guest physical address and
+    /// checks whether the guest is allowed to perform `access` on it. The
+    /// accessed and dirty bits in the guest page-table are not updated.
+    ///
+    /// # Returns
+    ///
+    /// The translation on success, `GuestPtError::PageFault` when the
+    /// access would fault in the guest, or `SvsmError::InvalidAddress` when
+    /// a page-table page is outside of guest memory.
+    pub fn translate(
+        &self,
+        vaddr: VirtAddr,
+        access: GuestAccess,
+    ) -> Result<GuestTranslation, SvsmError> {
+        self.walk(vaddr, access, |paddr| {
+            // Page-table walks of SEV-SNP guests always use private memory
+            let mut buf = [0u8; 8];
+            read_guest_phys(paddr, &mut buf)?;
+            Ok(u64::from_le_bytes(buf))
+        })
+    }
+
+    /// Calls `f` for each guest page touched by a virtual address range with
+    /// the translation of the first address in the page, the offset into
+    /// the range and the number of bytes in the page.
+    fn for_each_page<F>(
+        &self,
+        vaddr: VirtAddr,
+        len: usize,
+        access: GuestAccess,
+        mut f: F,
+    ) -> Result<(), SvsmError>
+    where
+        F: FnMut(&GuestTranslation, usize, usize) -> Result<(), SvsmError>,
+    {
+        let mut done: usize = 0;
+
+        while done < len {
+            let addr = vaddr.checked_add(done).ok_or(SvsmError::InvalidAddress)?;
+            let translation = self.translate(addr, access)?;
+            let chunk = (PAGE_SIZE - addr.page_offset()).min(len - done);
+            f(&translation, done, chunk)?;
+            done += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Reads guest memory at a guest virtual address into `buf`. The range
+    /// may span multiple guest pages.
+    ///
+    /// # Arguments
+    ///
+    /// * `vaddr` - Guest virtual address to read from
+    /// * `buf` - Buffer to fill
+    /// * `user` - Whether to check permissions for a user-mode access
+    pub fn read(&self, vaddr: VirtAddr, buf: &mut [u8], user: bool) -> Result<(), SvsmError> {
+        let access = if user {
+            GuestAccess::USER
+        } else {
+            GuestAccess::empty()
+        };
+        let len = buf.len();
+        self.for_each_page(vaddr, len, access, |t, offset, chunk| {
+            read_guest_phys_mem(t.paddr, &mut buf[offset..offset + chunk], t.encrypted)
+        })
+    }
+
+    /// Writes `buf` to guest memory at a guest virtual address. The range
+    /// may span multiple guest pages. All pages are translated before
+    /// anything is written, so that a fault does not leave a partial write
+    /// behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `vaddr` - Guest virtual address to write to
+    /// * `buf` - Data to write
+    /// * `user` - Whether to check permissions for a user-mode access
+    pub fn write(&self, vaddr: VirtAddr, buf: &[u8], user: bool) -> Result<(), SvsmError> {
+        let access = if user {
+            GuestAccess::USER | GuestAccess::WRITE
+        } else {
+            GuestAccess::WRITE
+        };
+        self.for_each_page(vaddr, buf.len(), access, |_, _, _| Ok(()))?;
+        self.for_each_page(vaddr, buf.len(), access, |t, offset, chunk| {
+            write_guest_phys_mem(t.paddr, &buf[offset..offset + chunk], t.encrypted)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C_BIT: u64 = 1 << 51;
+    const P: u64 = 1 << 0;
+    const W: u64 = 1 << 1;
+    const U: u64 = 1 << 2;
+    const PS: u64 = 1 << 7;
+    const NX: u64 = 1 << 63;
+
+    /// Guest memory made of 4k page-table pages at 0x1000, 0x2000, ...
+    struct FakeMem {
+        pages: [[u64; 512]; 4],
+    }
+
+    impl FakeMem {
+        fn new() -> Self {
+            Self {
+                pages: [[0; 512]; 4],
+            }
+        }
+
+        fn set(&mut self, table: usize, index: usize, entry: u64) {
+            self.pages[table - 1][index] = entry;
+        }
+
+        fn read(&self, paddr: PhysAddr) -> Result<u64, SvsmError> {
+            let page = paddr.bits() / PAGE_SIZE;
+            let index = (paddr.bits() % PAGE_SIZE) / 8;
+            Ok(self.pages[page - 1][index])
+        }
+    }
+
+    fn guest_pt(cr0: u64, cr4: u64, efer: u64) -> GuestPageTable {
+        let mut pt = GuestPageTable::new(cr0, 0x1000 | C_BIT, cr4, efer).unwrap();
+        pt.root = PhysAddr::from(0x1000usize);
+        pt.c_bit = C_BIT;
+        pt
+    }
+
+    const CR0_PG_WP: u64 = (1 << 31) | (1 << 16);
+    const EFER_LMA_NXE: u64 = (1 << 10) | (1 << 11);
+
+    #[test]
+    fn test_guest_paging_mode() {
+        let pt = GuestPageTable::new(0, 0, 0, 0).unwrap();
+        assert_eq!(pt.mode(), GuestPagingMode::Disabled);
+        let pt = GuestPageTable::new(CR0_PG_WP, 0, 0, EFER_LMA_NXE).unwrap();
+        assert_eq!(pt.mode(), GuestPagingMode::Level4);
+        let pt = GuestPageTable::new(CR0_PG_WP, 0, 1 << 12, EFER_LMA_NXE).unwrap();
+        assert_eq!(pt.mode(), GuestPagingMode::Level5);
+        assert!(GuestPageTable::new(CR0_PG_WP, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_guest_pt_walk() {
+        let pt = guest_pt(CR0_PG_WP, 0, EFER_LMA_NXE);
+        let mut mem = FakeMem::new();
+        // PML4[0] -> PDPT at 0x2000, PDPT[0] -> PD at 0x3000
+        mem.set(1, 0, 0x2000 | C_BIT | P | W | U);
+        mem.set(2, 0, 0x3000 | C_BIT | P | W | U);
+        // PD[0] -> PT at 0x4000, PD[1] is a shared, read-only 2M page
+        mem.set(3, 0, 0x4000 | C_BIT | P | W);
+        mem.set(3, 1, 0x4000_0000 | P | PS | U);
+        // PT[5] is a private, executable 4k page
+        mem.set(4, 5, 0x8_0000 | C_BIT | P | W);
+        // PT[6] is not executable
+        mem.set(4, 6, 0x9_0000 | C_BIT | P | W | NX);
+
+        let read = |paddr| mem.read(paddr);
+        let t = pt
+            .walk(VirtAddr::from(0x5123usize), GuestAccess::WRITE, read)
+            .unwrap();
+        assert_eq!(t.paddr, PhysAddr::from(0x8_0123usize));
+        assert_eq!(t.page_size, PAGE_SIZE);
+        assert!(t.encrypted);
+
+        let t = pt
+            .walk(VirtAddr::from(0x20_1234usize), GuestAccess::USER, read)
+            .unwrap();
+        assert_eq!(t.paddr, PhysAddr::from(0x4000_1234usize));
+        assert_eq!(t.page_size, 2 * 1024 * 1024);
+        assert!(!t.encrypted);
+
+        // Not present
+        let err = pt.walk(VirtAddr::from(0x7000usize), GuestAccess::WRITE, read);
+        assert!(matches!(
+            err,
+            Err(SvsmError::GuestPageTable(GuestPtError::PageFault(0x2)))
+        ));
+        // Read-only page with CR0.WP set
+        let err = pt.walk(VirtAddr::from(0x20_0000usize), GuestAccess::WRITE, read);
+        assert!(matches!(
+            err,
+            Err(SvsmError::GuestPageTable(GuestPtError::PageFault(0x3)))
+        ));
+        // Supervisor page accessed from user mode
+        let err = pt.walk(VirtAddr::from(0x5000usize), GuestAccess::USER, read);
+        assert!(matches!(
+            err,
+            Err(SvsmError::GuestPageTable(GuestPtError::PageFault(0x5)))
+        ));
+        // Instruction fetch from a NX page
+        assert!(pt
+            .walk(VirtAddr::from(0x5000usize), GuestAccess::FETCH, read)
+            .is_ok());
+        let err = pt.walk(VirtAddr::from(0x6000usize), GuestAccess::FETCH, read);
+        assert!(matches!(
+            err,
+            Err(SvsmError::GuestPageTable(GuestPtError::PageFault(0x11)))
+        ));
+        // Non-canonical address
+        let err = pt.walk(
+            VirtAddr::from(0x8000_0000_0000usize),
+            GuestAccess::empty(),
+            read,
+        );
+        assert!(matches!(
+            err,
+            Err(SvsmError::GuestPageTable(GuestPtError::NonCanonical(_)))
+        ));
+
+        // Without CR0.WP supervisor writes ignore read-only pages, without
+        // EFER.NXE the NX bit is reserved
+        let pt = guest_pt(1 << 31, 0, 1 << 10);
+        assert!(pt
+            .walk(VirtAddr::from(0x20_0000usize), GuestAccess::WRITE, read)
+            .is_ok());
+        let err = pt.walk(VirtAddr::from(0x6000usize), GuestAccess::empty(), read);
+        assert!(matches!(
+            err,
+            Err(SvsmError::GuestPageTable(GuestPtError::PageFault(0x9)))
+        ));
+    }
+}
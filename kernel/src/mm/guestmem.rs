@@ -4,8 +4,18 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
-use crate::address::{Address, VirtAddr};
+use crate::address::{Address, PhysAddr, VirtAddr};
+use crate::cpu::percpu::this_cpu_mut;
+use crate::cpu::tlb::flush_address_sync;
 use crate::error::SvsmError;
+use crate::mm::pagetable::{PTEntryFlags, PageTableRef};
+use crate::mm::virtualrange::VirtualRange;
+use crate::mm::{
+    valid_phys_address, valid_phys_region, writable_phys_addr, PerCPUPageMappingGuard,
+};
+use crate::task::{preempt_disable, preempt_enable};
+use crate::types::{PAGE_SIZE, PAGE_SIZE_2M};
+use crate::utils::MemoryRegion;
 
 use core::arch::asm;
 use core::mem::{size_of, MaybeUninit};
@@ -147,8 +157,7 @@ unsafe fn read_u64(v: VirtAddr) -> Result<u64, SvsmError> {
 }
 
 #[inline]
-unsafe fn do_movsb<T>(src: *const T, dst: *mut T) -> Result<(), SvsmError> {
-    let size: usize = size_of::<T>();
+unsafe fn do_movsb_bytes(src: *const u8, dst: *mut u8, size: usize) -> Result<(), SvsmError> {
     let mut rcx: u64;
 
     asm!("1:cld
@@ -171,6 +180,11 @@ unsafe fn do_movsb<T>(src: *const T, dst: *mut T) -> Result<(), SvsmError> {
     }
 }
 
+#[inline]
+unsafe fn do_movsb<T>(src: *const T, dst: *mut T) -> Result<(), SvsmError> {
+    do_movsb_bytes(src.cast(), dst.cast(), size_of::<T>())
+}
+
 #[derive(Debug)]
 pub struct GuestPtr<T: Copy> {
     ptr: *mut T,
@@ -220,10 +234,450 @@ impl<T: Copy> GuestPtr<T> {
     }
 }
 
+/// Number of 2M mappings of guest memory cached per CPU
+const GUEST_MAP_CACHE_SLOTS: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+struct GuestMapSlot {
+    /// Virtual address of the slot, null until the slot is used first
+    vaddr: VirtAddr,
+    /// 2M-aligned guest physical address mapped at `vaddr`
+    frame: Option<PhysAddr>,
+    /// Number of active users of the mapping
+    users: usize,
+    /// Time stamp of the last use, for least-recently-used replacement
+    last_use: u64,
+}
+
+impl GuestMapSlot {
+    const fn new() -> Self {
+        Self {
+            vaddr: VirtAddr::null(),
+            frame: None,
+            users: 0,
+            last_use: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GuestMapLookup {
+    /// The frame is mapped by the slot
+    Hit(usize),
+    /// The frame is not mapped, the slot can be reused for it
+    Miss(usize),
+    /// The frame is not mapped and all slots are in use
+    Busy,
+}
+
+/// Per-CPU cache of 2M mappings of private guest memory. Guest memory
+/// accesses through [`GuestMemory`] and the `*_guest_phys*()` functions
+/// reuse these mappings, so that accesses to nearby guest memory do not
+/// need a TLB flush each.
+///
+/// Mappings stay in place until their slot is needed for another frame.
+#[derive(Debug)]
+pub struct GuestMapCache {
+    slots: [GuestMapSlot; GUEST_MAP_CACHE_SLOTS],
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for GuestMapCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GuestMapCache {
+    pub const fn new() -> Self {
+        Self {
+            slots: [GuestMapSlot::new(); GUEST_MAP_CACHE_SLOTS],
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn lookup(&self, frame: PhysAddr) -> GuestMapLookup {
+        if let Some(idx) = self.slots.iter().position(|s| s.frame == Some(frame)) {
+            return GuestMapLookup::Hit(idx);
+        }
+
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.users == 0)
+            .min_by_key(|(_, s)| (s.frame.is_some(), s.last_use))
+            .map_or(GuestMapLookup::Busy, |(idx, _)| GuestMapLookup::Miss(idx))
+    }
+
+    fn claim(&mut self, idx: usize) -> VirtAddr {
+        self.clock += 1;
+        let slot = &mut self.slots[idx];
+        slot.users += 1;
+        slot.last_use = self.clock;
+        slot.vaddr
+    }
+
+    /// Returns a mapping of the 2M guest frame `frame`, mapping it in place
+    /// of the least recently used idle slot if needed. The slot stays
+    /// mapped until it is released with [`Self::put()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - 2M-aligned guest physical address
+    /// * `pgtable` - Page-table of the current CPU
+    /// * `vrange` - Allocator for 2M temporary mappings of the current CPU
+    ///
+    /// # Returns
+    ///
+    /// The slot index and the virtual address of the frame, `None` if all
+    /// slots are in use, or an error if creating the mapping failed.
+    pub fn get(
+        &mut self,
+        frame: PhysAddr,
+        pgtable: &mut PageTableRef,
+        vrange: &mut VirtualRange,
+    ) -> Result<Option<(usize, VirtAddr)>, SvsmError> {
+        assert!(frame.is_aligned(PAGE_SIZE_2M));
+
+        let idx = match self.lookup(frame) {
+            GuestMapLookup::Hit(idx) => {
+                self.hits += 1;
+                idx
+            }
+            GuestMapLookup::Miss(idx) => {
+                self.misses += 1;
+                let slot = &mut self.slots[idx];
+                if slot.vaddr.is_null() {
+                    slot.vaddr = vrange.alloc(1, 0)?;
+                }
+                if slot.frame.take().is_some() {
+                    pgtable.unmap_2m(slot.vaddr);
+                    flush_address_sync(slot.vaddr);
+                }
+                pgtable.map_2m(slot.vaddr, frame, PTEntryFlags::data())?;
+                slot.frame = Some(frame);
+                idx
+            }
+            GuestMapLookup::Busy => return Ok(None),
+        };
+
+        Ok(Some((idx, self.claim(idx))))
+    }
+
+    /// Releases a mapping returned by [`Self::get()`]
+    pub fn put(&mut self, idx: usize) {
+        let slot = &mut self.slots[idx];
+        assert!(slot.users > 0);
+        slot.users -= 1;
+    }
+
+    /// Returns the number of cache hits and misses
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+/// Temporary SVSM mapping of the guest page containing a guest physical
+/// address. Private memory is mapped through the [`GuestMapCache`] of the
+/// current CPU when the whole 2M frame is guest memory.
+///
+/// The mapping uses per-CPU resources, so preemption is disabled while it
+/// exists.
+#[derive(Debug)]
+struct GuestPageMapping {
+    vaddr: VirtAddr,
+    slot: Option<usize>,
+    guard: Option<PerCPUPageMappingGuard>,
+}
+
+impl GuestPageMapping {
+    fn new(paddr: PhysAddr, encrypted: bool) -> Result<Self, SvsmError> {
+        preempt_disable();
+        let mapping = Self::map(paddr, encrypted);
+        if mapping.is_err() {
+            preempt_enable();
+        }
+        mapping
+    }
+
+    fn map(paddr: PhysAddr, encrypted: bool) -> Result<Self, SvsmError> {
+        if encrypted {
+            let frame = PhysAddr::from(paddr.bits() & !(PAGE_SIZE_2M - 1));
+            // Other parts of the frame may be SVSM memory or VMSAs, which
+            // must not be mapped
+            if valid_phys_region(MemoryRegion::new(frame, PAGE_SIZE_2M)) {
+                if let Some((slot, vaddr)) = this_cpu_mut().guest_map_get(frame)? {
+                    return Ok(Self {
+                        vaddr: vaddr + (paddr - frame),
+                        slot: Some(slot),
+                        guard: None,
+                    });
+                }
+            }
+        }
+
+        let guard = if encrypted {
+            PerCPUPageMappingGuard::create_4k(paddr.page_align())?
+        } else {
+            PerCPUPageMappingGuard::create_4k_shared(paddr.page_align())?
+        };
+        Ok(Self {
+            vaddr: guard.virt_addr() + paddr.page_offset(),
+            slot: None,
+            guard: Some(guard),
+        })
+    }
+
+    /// SVSM virtual address of the guest physical address the mapping was
+    /// created for
+    fn virt_addr(&self) -> VirtAddr {
+        self.vaddr
+    }
+}
+
+impl Drop for GuestPageMapping {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            this_cpu_mut().guest_map_put(slot);
+        }
+        // The 4K mapping must be removed on the CPU which created it
+        drop(self.guard.take());
+        preempt_enable();
+    }
+}
+
+/// Temporarily maps each page of a guest physical memory range and calls
+/// `f` with the SVSM virtual address of the part of the page inside the
+/// range, the offset of that part into the range and its length.
+///
+/// # Arguments
+///
+/// * `gpa` - Guest physical start address of the range
+/// * `len` - Length of the range in bytes
+/// * `write` - Whether the range is going to be written, in which case all
+///   pages need to be writable for the guest
+/// * `encrypted` - Whether the range is private guest memory (C-bit set) or
+///   shared with the hypervisor
+///
+/// # Returns
+///
+/// `Ok(())` on success, `Err(SvsmError::InvalidAddress)` if the range is not
+/// entirely within guest memory, or the error returned by `f`.
+fn for_each_guest_page<F>(
+    gpa: PhysAddr,
+    len: usize,
+    write: bool,
+    encrypted: bool,
+    mut f: F,
+) -> Result<(), SvsmError>
+where
+    F: FnMut(VirtAddr, usize, usize) -> Result<(), SvsmError>,
+{
+    let mut done: usize = 0;
+
+    while done < len {
+        let paddr = gpa.checked_add(done).ok_or(SvsmError::InvalidAddress)?;
+        let valid = if write {
+            writable_phys_addr(paddr)
+        } else {
+            valid_phys_address(paddr)
+        };
+        if !valid {
+            return Err(SvsmError::InvalidAddress);
+        }
+
+        let chunk = (PAGE_SIZE - paddr.page_offset()).min(len - done);
+        let mapping = GuestPageMapping::new(paddr, encrypted)?;
+        f(mapping.virt_addr(), done, chunk)?;
+        done += chunk;
+    }
+
+    Ok(())
+}
+
+/// Reads guest physical memory, which may span multiple pages, into `buf`.
+///
+/// # Arguments
+///
+/// * `gpa` - Guest physical address to read from
+/// * `buf` - Buffer to fill, its length determines the number of bytes read
+/// * `encrypted` - Whether the memory is private to the guest or shared with
+///   the hypervisor
+///
+/// # Returns
+///
+/// `Ok(())` on success, `Err(SvsmError::InvalidAddress)` if any part of the
+/// range is outside of guest memory.
+pub fn read_guest_phys_mem(
+    gpa: PhysAddr,
+    buf: &mut [u8],
+    encrypted: bool,
+) -> Result<(), SvsmError> {
+    let len = buf.len();
+    for_each_guest_page(gpa, len, false, encrypted, |vaddr, offset, chunk| {
+        // SAFETY: `vaddr` is mapped for at least `chunk` bytes and
+        // `offset + chunk` is within `buf`. Faults are caught by the
+        // exception table.
+        unsafe { do_movsb_bytes(vaddr.as_ptr(), buf[offset..].as_mut_ptr(), chunk) }
+    })
+}
+
+/// Writes `buf` to guest physical memory, which may span multiple pages.
+///
+/// # Arguments
+///
+/// * `gpa` - Guest physical address to write to
+/// * `buf` - Data to write
+/// * `encrypted` - Whether the memory is private to the guest or shared with
+///   the hypervisor
+///
+/// # Returns
+///
+/// `Ok(())` on success, `Err(SvsmError::InvalidAddress)` if any part of the
+/// range is outside of writable guest memory.
+pub fn write_guest_phys_mem(gpa: PhysAddr, buf: &[u8], encrypted: bool) -> Result<(), SvsmError> {
+    for_each_guest_page(gpa, buf.len(), true, encrypted, |vaddr, offset, chunk| {
+        // SAFETY: `vaddr` is mapped for at least `chunk` bytes and
+        // `offset + chunk` is within `buf`. Faults are caught by the
+        // exception table.
+        unsafe { do_movsb_bytes(buf[offset..].as_ptr(), vaddr.as_mut_ptr(), chunk) }
+    })
+}
+
+/// Reads private guest physical memory into `buf`, see
+/// [`read_guest_phys_mem()`].
+pub fn read_guest_phys(gpa: PhysAddr, buf: &mut [u8]) -> Result<(), SvsmError> {
+    read_guest_phys_mem(gpa, buf, true)
+}
+
+/// Writes `buf` to private guest physical memory, see
+/// [`write_guest_phys_mem()`].
+pub fn write_guest_phys(gpa: PhysAddr, buf: &[u8]) -> Result<(), SvsmError> {
+    write_guest_phys_mem(gpa, buf, true)
+}
+
+/// A range of private guest physical memory. Accesses are checked against
+/// the bounds of the range and against the guest memory map, and go through
+/// the per-CPU [`GuestMapCache`], so that walking through a buffer in guest
+/// memory does not need a new mapping and TLB flush for every access.
+#[derive(Clone, Copy, Debug)]
+pub struct GuestMemory {
+    start: PhysAddr,
+    len: usize,
+}
+
+impl GuestMemory {
+    /// Creates a new guest memory range
+    ///
+    /// # Returns
+    ///
+    /// The range on success, `Err(SvsmError::InvalidAddress)` if the range
+    /// wraps around the end of the address space.
+    pub fn new(start: PhysAddr, len: usize) -> Result<Self, SvsmError> {
+        start.checked_add(len).ok_or(SvsmError::InvalidAddress)?;
+        Ok(Self { start, len })
+    }
+
+    /// Returns the guest physical start address of the range
+    pub fn start(&self) -> PhysAddr {
+        self.start
+    }
+
+    /// Returns the length of the range in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the range is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn addr_of(&self, offset: usize, len: usize) -> Result<PhysAddr, SvsmError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => Ok(self.start + offset),
+            _ => Err(SvsmError::InvalidAddress),
+        }
+    }
+
+    /// Reads `buf.len()` bytes at `offset` into the range
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<(), SvsmError> {
+        let gpa = self.addr_of(offset, buf.len())?;
+        read_guest_phys(gpa, buf)
+    }
+
+    /// Writes `buf` at `offset` into the range
+    pub fn write_bytes(&self, offset: usize, buf: &[u8]) -> Result<(), SvsmError> {
+        let gpa = self.addr_of(offset, buf.len())?;
+        write_guest_phys(gpa, buf)
+    }
+
+    /// Reads a value of type `T` at `offset` into the range. Like with
+    /// [`GuestPtr`], `T` must be valid for any bit pattern the guest may
+    /// have written.
+    pub fn read_struct<T: Copy>(&self, offset: usize) -> Result<T, SvsmError> {
+        let mut val = MaybeUninit::<T>::zeroed();
+        // SAFETY: the zeroed bytes of `val` are initialized as u8 and the
+        // slice covers exactly `val`.
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(val.as_mut_ptr().cast::<u8>(), size_of::<T>())
+        };
+        self.read_bytes(offset, buf)?;
+        // SAFETY: all bytes of `val` have been written from guest memory
+        Ok(unsafe { val.assume_init() })
+    }
+
+    /// Writes `val` at `offset` into the range
+    pub fn write_struct<T: Copy>(&self, offset: usize, val: &T) -> Result<(), SvsmError> {
+        // SAFETY: `val` is a valid reference covering size_of::<T>() bytes
+        let buf =
+            unsafe { core::slice::from_raw_parts((val as *const T).cast::<u8>(), size_of::<T>()) };
+        self.write_bytes(offset, buf)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_guest_map_cache_lookup() {
+        let mut cache = GuestMapCache::new();
+        let frame = |n: usize| PhysAddr::from(n * PAGE_SIZE_2M);
+
+        // Unused slots are taken first
+        for n in 0..GUEST_MAP_CACHE_SLOTS {
+            assert_eq!(cache.lookup(frame(n)), GuestMapLookup::Miss(n));
+            cache.slots[n].frame = Some(frame(n));
+            cache.claim(n);
+        }
+        assert_eq!(cache.lookup(frame(2)), GuestMapLookup::Hit(2));
+
+        // Slots in use are never replaced
+        assert_eq!(cache.lookup(frame(8)), GuestMapLookup::Busy);
+
+        // The least recently used idle slot is replaced
+        cache.put(3);
+        cache.put(1);
+        assert_eq!(cache.lookup(frame(8)), GuestMapLookup::Miss(1));
+        cache.claim(1);
+        cache.put(1);
+        assert_eq!(cache.lookup(frame(8)), GuestMapLookup::Miss(3));
+    }
+
+    #[test]
+    fn test_guest_memory_bounds() {
+        let mem = GuestMemory::new(PhysAddr::from(0x10000usize), 0x100).unwrap();
+        assert_eq!(mem.addr_of(0xf8, 8).unwrap(), PhysAddr::from(0x100f8usize));
+        assert!(mem.addr_of(0xf9, 8).is_err());
+        assert!(mem.addr_of(usize::MAX, 2).is_err());
+        assert!(GuestMemory::new(PhysAddr::from(usize::MAX - 1), 4).is_err());
+    }
+
     #[test]
     #[cfg_attr(miri, ignore = "inline assembly")]
     fn test_read_u8_valid_address() {
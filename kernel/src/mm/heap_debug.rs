@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Heap debugging support, enabled with the `heap-debug` feature.
+//!
+//! Every heap object gets a trailing redzone filled with [`REDZONE_BYTE`]
+//! which is verified when the object is freed. Freed memory is overwritten
+//! with [`POISON_BYTE`] so that use-after-free bugs show up as obviously
+//! bogus values. Live allocations are recorded together with the call stack
+//! of the allocation site, [`print_heap_leaks()`] prints all allocations
+//! which have not been freed yet.
+
+use crate::address::{Address, VirtAddr};
+use crate::debug::stacktrace::capture_stack;
+use crate::locking::SpinLock;
+
+use super::alloc::percpu_ready;
+
+/// Minimum number of bytes reserved after every heap object
+pub const REDZONE_SIZE: usize = 16;
+
+/// Pattern written to the redzone of heap objects
+pub const REDZONE_BYTE: u8 = 0xcc;
+
+/// Pattern written to freed memory
+pub const POISON_BYTE: u8 = 0x6b;
+
+/// Number of call stack entries recorded per allocation
+const TRACKED_FRAMES: usize = 6;
+
+/// Maximum number of allocations which can be tracked at the same time
+const MAX_TRACKED: usize = 4096;
+
+/// Fills the redzone of a newly allocated heap object.
+///
+/// # Arguments
+///
+/// * `vaddr` - Start of the object
+/// * `size` - Size requested by the caller
+/// * `block_size` - Size of the memory block backing the object
+pub fn fill_redzone(vaddr: VirtAddr, size: usize, block_size: usize) {
+    // SAFETY: The whole block belongs to the new object
+    unsafe {
+        (vaddr + size)
+            .as_mut_ptr::<u8>()
+            .write_bytes(REDZONE_BYTE, block_size - size);
+    }
+}
+
+/// Verifies the redzone of a heap object before it is freed and poisons the
+/// object.
+///
+/// # Arguments
+///
+/// * `vaddr` - Start of the object
+/// * `size` - Size requested by the caller
+/// * `block_size` - Size of the memory block backing the object
+///
+/// # Panics
+///
+/// Panics if the redzone was overwritten or the object was already freed.
+pub fn check_and_poison(vaddr: VirtAddr, size: usize, block_size: usize) {
+    // SAFETY: The whole block belongs to the object which is being freed
+    let block = unsafe { core::slice::from_raw_parts_mut(vaddr.as_mut_ptr::<u8>(), block_size) };
+
+    if block.iter().all(|b| *b == POISON_BYTE) {
+        panic!("Double free of heap object {:#018x}", vaddr);
+    }
+    if let Some(pos) = block[size..].iter().position(|b| *b != REDZONE_BYTE) {
+        panic!(
+            "Heap redzone of object {:#018x} (size {}) corrupted at offset {}",
+            vaddr,
+            size,
+            size + pos
+        );
+    }
+
+    block.fill(POISON_BYTE);
+}
+
+/// Overwrites freed memory with [`POISON_BYTE`].
+pub fn poison(vaddr: VirtAddr, len: usize) {
+    // SAFETY: The caller owns the memory which is being freed
+    unsafe { vaddr.as_mut_ptr::<u8>().write_bytes(POISON_BYTE, len) };
+}
+
+/// Allocation site information of a live allocation
+#[derive(Clone, Copy, Debug)]
+struct AllocRecord {
+    vaddr: VirtAddr,
+    size: usize,
+    frames: [VirtAddr; TRACKED_FRAMES],
+}
+
+impl AllocRecord {
+    const fn empty() -> Self {
+        Self {
+            vaddr: VirtAddr::null(),
+            size: 0,
+            frames: [VirtAddr::null(); TRACKED_FRAMES],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.vaddr.is_null()
+    }
+}
+
+/// Hash table of live allocations using open addressing with linear
+/// probing, keyed by the allocation address.
+#[derive(Debug)]
+struct AllocTracker {
+    records: [AllocRecord; MAX_TRACKED],
+    count: usize,
+    /// Number of allocations which could not be tracked because the table
+    /// was full
+    dropped: usize,
+}
+
+impl AllocTracker {
+    const fn new() -> Self {
+        Self {
+            records: [AllocRecord::empty(); MAX_TRACKED],
+            count: 0,
+            dropped: 0,
+        }
+    }
+
+    fn slot(vaddr: VirtAddr) -> usize {
+        // Heap objects are at least 32 bytes apart
+        (vaddr.bits() >> 5) % MAX_TRACKED
+    }
+
+    fn find(&self, vaddr: VirtAddr) -> Option<usize> {
+        let mut idx = Self::slot(vaddr);
+        for _ in 0..MAX_TRACKED {
+            let record = &self.records[idx];
+            if record.is_empty() {
+                return None;
+            } else if record.vaddr == vaddr {
+                return Some(idx);
+            }
+            idx = (idx + 1) % MAX_TRACKED;
+        }
+        None
+    }
+
+    /// Records an allocation, replacing an existing record for the same
+    /// address.
+    fn insert(&mut self, record: AllocRecord) {
+        let mut idx = Self::slot(record.vaddr);
+        for _ in 0..MAX_TRACKED {
+            let slot = &mut self.records[idx];
+            if slot.is_empty() || slot.vaddr == record.vaddr {
+                if slot.is_empty() {
+                    self.count += 1;
+                }
+                *slot = record;
+                return;
+            }
+            idx = (idx + 1) % MAX_TRACKED;
+        }
+        self.dropped += 1;
+    }
+
+    fn remove(&mut self, vaddr: VirtAddr) {
+        let Some(mut hole) = self.find(vaddr) else {
+            return;
+        };
+        self.records[hole] = AllocRecord::empty();
+        self.count -= 1;
+
+        // Move following entries of the probe sequence into the hole, so that
+        // lookups do not stop early.
+        let mut idx = (hole + 1) % MAX_TRACKED;
+        while !self.records[idx].is_empty() {
+            let home = Self::slot(self.records[idx].vaddr);
+            let distance_home = (idx + MAX_TRACKED - home) % MAX_TRACKED;
+            let distance_hole = (idx + MAX_TRACKED - hole) % MAX_TRACKED;
+            if distance_home >= distance_hole {
+                self.records[hole] = self.records[idx];
+                self.records[idx] = AllocRecord::empty();
+                hole = idx;
+            }
+            idx = (idx + 1) % MAX_TRACKED;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &AllocRecord> {
+        self.records.iter().filter(|r| !r.is_empty())
+    }
+}
+
+static ALLOC_TRACKER: SpinLock<AllocTracker> = SpinLock::new(AllocTracker::new());
+
+/// Records a live allocation together with the call stack of the caller.
+/// The call stack is only available once the per-CPU areas are set up.
+pub fn track_alloc(vaddr: VirtAddr, size: usize) {
+    let mut record = AllocRecord {
+        vaddr,
+        size,
+        frames: [VirtAddr::null(); TRACKED_FRAMES],
+    };
+    if percpu_ready() {
+        // SAFETY: The per-CPU area is mapped once percpu_ready() returns
+        // true. Skip the frames of the allocator itself.
+        unsafe { capture_stack(2, &mut record.frames) };
+    }
+    ALLOC_TRACKER.lock().insert(record);
+}
+
+/// Removes the record of a freed allocation.
+pub fn untrack_alloc(vaddr: VirtAddr) {
+    ALLOC_TRACKER.lock().remove(vaddr);
+}
+
+/// Prints all allocations which have not been freed yet, together with their
+/// allocation sites.
+pub fn print_heap_leaks() {
+    let tracker = ALLOC_TRACKER.lock();
+    log::info!("---HEAP ALLOCATIONS---: {} live", tracker.count);
+    for record in tracker.iter() {
+        log::info!("  {:#018x} size {}", record.vaddr, record.size);
+        for rip in record.frames.iter().take_while(|rip| !rip.is_null()) {
+            log::info!("    [{:#018x}]", rip);
+        }
+    }
+    if tracker.dropped != 0 {
+        log::info!("  {} allocations were not tracked", tracker.dropped);
+    }
+    log::info!("---END---");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(vaddr: usize) -> AllocRecord {
+        AllocRecord {
+            vaddr: VirtAddr::from(vaddr),
+            size: 32,
+            frames: [VirtAddr::null(); TRACKED_FRAMES],
+        }
+    }
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_alloc_tracker() {
+        extern crate alloc;
+        use alloc::boxed::Box;
+
+        let mut tracker = Box::new(AllocTracker::new());
+        let colliding = 0x1000 + MAX_TRACKED * 32;
+
+        tracker.insert(record(0x1000));
+        tracker.insert(record(colliding));
+        tracker.insert(record(0x1020));
+        assert_eq!(tracker.count, 3);
+
+        // Re-inserting an address replaces its record
+        tracker.insert(record(0x1020));
+        assert_eq!(tracker.count, 3);
+
+        // Entries behind a removed one in the probe sequence stay reachable
+        tracker.remove(VirtAddr::from(0x1000usize));
+        assert_eq!(tracker.count, 2);
+        assert!(tracker.find(VirtAddr::from(0x1000usize)).is_none());
+        assert!(tracker.find(VirtAddr::from(colliding)).is_some());
+        assert!(tracker.find(VirtAddr::from(0x1020usize)).is_some());
+
+        // Removing unknown addresses is ignored
+        tracker.remove(VirtAddr::from(0x2000usize));
+        assert_eq!(tracker.count, 2);
+    }
+
+    #[test]
+    fn test_redzone() {
+        let mut buf = [0u8; 64];
+        let vaddr = VirtAddr::from(buf.as_mut_ptr());
+
+        fill_redzone(vaddr, 40, 64);
+        assert!(buf[40..].iter().all(|b| *b == REDZONE_BYTE));
+
+        let vaddr = VirtAddr::from(buf.as_mut_ptr());
+        check_and_poison(vaddr, 40, 64);
+        assert!(buf.iter().all(|b| *b == POISON_BYTE));
+    }
+}
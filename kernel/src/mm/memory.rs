@@ -110,6 +110,19 @@ pub fn valid_phys_address(paddr: PhysAddr) -> bool {
         .any(|region| region.contains(paddr))
 }
 
+/// Returns `true` if the whole region is valid guest memory, see
+/// [`valid_phys_address()`].
+pub fn valid_phys_region(region: MemoryRegion<PhysAddr>) -> bool {
+    if region.contains(LAUNCH_VMSA_ADDR) || PERCPU_VMSAS.exists_in(region) {
+        return false;
+    }
+
+    MEMORY_MAP
+        .lock_read()
+        .iter()
+        .any(|r| r.contains_region(&region))
+}
+
 /// The starting address of the ISA range.
 const ISA_RANGE_START: PhysAddr = PhysAddr::new(0xa0000);
 
@@ -127,6 +140,22 @@ pub fn writable_phys_addr(paddr: PhysAddr) -> bool {
     valid_phys_address(paddr)
 }
 
+/// Returns `true` if the whole region is writable guest memory. This is
+/// cheaper than calling [`writable_phys_addr()`] for every page, but does
+/// not check for pages which are excluded by [`valid_phys_address()`], like
+/// VMSAs.
+pub fn writable_phys_region(region: MemoryRegion<PhysAddr>) -> bool {
+    let isa_range = MemoryRegion::from_addresses(ISA_RANGE_START, ISA_RANGE_END);
+    if region.overlap(&isa_range) {
+        return false;
+    }
+
+    MEMORY_MAP
+        .lock_read()
+        .iter()
+        .any(|r| r.contains_region(&region))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,20 +6,29 @@
 
 pub mod address_space;
 pub mod alloc;
+pub mod guest_pagetable;
 pub mod guestmem;
+// Also built for unit tests, so that its tests run without the feature
+#[cfg(any(test, feature = "heap-debug"))]
+pub mod heap_debug;
 pub mod memory;
 pub mod page_visibility;
 pub mod pagetable;
 pub mod ptguards;
+pub mod shared_mem;
 pub mod stack;
+pub mod usermem;
 pub mod validate;
 pub mod virtualrange;
 pub mod vm;
+pub mod vmalloc_heap;
 
 pub use address_space::*;
-pub use guestmem::GuestPtr;
-pub use memory::{valid_phys_address, writable_phys_addr};
+pub use guestmem::{GuestMemory, GuestPtr};
+pub use memory::{valid_phys_address, valid_phys_region, writable_phys_addr};
 pub use ptguards::*;
+pub use shared_mem::{SharedBox, SharedBuffer, SharedData};
+pub use usermem::{copy_from_user, copy_to_user};
 
 pub use pagetable::PageTablePart;
 
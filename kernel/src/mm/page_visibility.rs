@@ -4,69 +4,301 @@
 //
 // Author: Jon Lange (jlange@microsoft.com)
 
-use crate::address::VirtAddr;
+use crate::address::{Address, PhysAddr, VirtAddr};
 use crate::cpu::flush_tlb_global_sync;
 use crate::cpu::ghcb::current_ghcb;
 use crate::cpu::percpu::this_cpu_mut;
+use crate::error::SvsmError;
 use crate::mm::validate::{
-    valid_bitmap_clear_valid_4k, valid_bitmap_set_valid_4k, valid_bitmap_valid_addr,
+    valid_bitmap_clear_valid_range, valid_bitmap_set_valid_range, valid_bitmap_valid_addr,
 };
 use crate::mm::virt_to_phys;
 use crate::sev::ghcb::PageStateChangeOp;
-use crate::sev::utils::pvalidate;
-use crate::sev::PvalidateOp;
-use crate::types::{PageSize, PAGE_SIZE};
+use crate::sev::{pvalidate, PvalidateOp, SevSnpError};
+use crate::types::{PageSize, PAGE_SIZE, PAGE_SIZE_2M};
 
-pub fn make_page_shared(vaddr: VirtAddr) {
+/// Returns the physical address and the length of the physically
+/// contiguous part of the page-aligned virtual range `[vaddr, end)` which
+/// starts at `vaddr`.
+fn phys_run<F>(vaddr: VirtAddr, end: VirtAddr, translate: F) -> (PhysAddr, usize)
+where
+    F: Fn(VirtAddr) -> PhysAddr,
+{
+    let paddr = translate(vaddr);
+    let mut len = PAGE_SIZE;
+
+    while vaddr + len < end && translate(vaddr + len) == paddr + len {
+        len += PAGE_SIZE;
+    }
+
+    (paddr, len)
+}
+
+/// Changes the validation state of a physically contiguous range. 2M pages
+/// are used where possible. Unlike [`crate::sev::pvalidate_range()`], either
+/// the whole range is changed or, on failure, nothing.
+fn pvalidate_run(
+    vaddr: VirtAddr,
+    paddr: PhysAddr,
+    len: usize,
+    op: PvalidateOp,
+) -> Result<(), SvsmError> {
+    let mut offset: usize = 0;
+
+    while offset < len {
+        let va = vaddr + offset;
+        let result = if va.is_aligned(PAGE_SIZE_2M)
+            && (paddr + offset).is_aligned(PAGE_SIZE_2M)
+            && len - offset >= PAGE_SIZE_2M
+        {
+            // Try to validate as a huge page.
+            // If we fail, try to fall back to regular-sized pages.
+            pvalidate(va, PageSize::Huge, op)
+                .map(|_| PAGE_SIZE_2M)
+                .or_else(|err| match err {
+                    SvsmError::SevSnp(SevSnpError::FAIL_SIZEMISMATCH(_)) => {
+                        pvalidate(va, PageSize::Regular, op).map(|_| PAGE_SIZE)
+                    }
+                    _ => Err(err),
+                })
+        } else {
+            pvalidate(va, PageSize::Regular, op).map(|_| PAGE_SIZE)
+        };
+
+        match result {
+            Ok(size) => offset += size,
+            Err(e) => {
+                let revert = match op {
+                    PvalidateOp::Valid => PvalidateOp::Invalid,
+                    PvalidateOp::Invalid => PvalidateOp::Valid,
+                };
+                pvalidate_run(vaddr, paddr, offset, revert)
+                    .expect("Failed to roll back PVALIDATE of partially changed range");
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn valid_bitmap_update(paddr: PhysAddr, len: usize, valid: bool) {
+    if !valid_bitmap_valid_addr(paddr) {
+        return;
+    }
+    if valid {
+        valid_bitmap_set_valid_range(paddr, paddr + len);
+    } else {
+        valid_bitmap_clear_valid_range(paddr, paddr + len);
+    }
+}
+
+/// Asks the hypervisor to change the state of a physically contiguous range.
+/// 2M entries are used where the range can also be validated with 2M pages,
+/// up to the maximum number of entries per GHCB call.
+fn page_state_change(
+    vaddr: VirtAddr,
+    paddr: PhysAddr,
+    len: usize,
+    op: PageStateChangeOp,
+) -> Result<(), SvsmError> {
+    let size = if (vaddr.bits() ^ paddr.bits()) & (PAGE_SIZE_2M - 1) == 0 {
+        PageSize::Huge
+    } else {
+        PageSize::Regular
+    };
+    current_ghcb().page_state_change(paddr, paddr + len, size, op)
+}
+
+/// Updates the C-bit in the page-table entries of a virtual range. Either
+/// all entries are changed or, on failure, none.
+fn set_pgtable_range(vaddr: VirtAddr, len: usize, shared: bool) -> Result<(), SvsmError> {
+    let mut pgtable = this_cpu_mut().get_pgtable();
+    let mut offset: usize = 0;
+
+    while offset < len {
+        let result = if shared {
+            pgtable.set_shared_4k(vaddr + offset)
+        } else {
+            pgtable.set_encrypted_4k(vaddr + offset)
+        };
+
+        if let Err(e) = result {
+            for revert in (0..offset).step_by(PAGE_SIZE) {
+                let revert_result = if shared {
+                    pgtable.set_encrypted_4k(vaddr + revert)
+                } else {
+                    pgtable.set_shared_4k(vaddr + revert)
+                };
+                revert_result.expect("Failed to roll back page-table update");
+            }
+            return Err(e);
+        }
+        offset += PAGE_SIZE;
+    }
+
+    Ok(())
+}
+
+/// Makes a physically contiguous range shared. On failure the range is left
+/// private.
+fn make_run_shared(vaddr: VirtAddr, paddr: PhysAddr, len: usize) -> Result<(), SvsmError> {
     // Revoke page validation before changing page state.
-    pvalidate(vaddr, PageSize::Regular, PvalidateOp::Invalid)
-        .expect("Pvalidate failed when making page shared");
-    let paddr = virt_to_phys(vaddr);
-    if valid_bitmap_valid_addr(paddr) {
-        valid_bitmap_clear_valid_4k(paddr);
+    pvalidate_run(vaddr, paddr, len, PvalidateOp::Invalid)?;
+    valid_bitmap_update(paddr, len, false);
+
+    // Ask the hypervisor to make the pages shared and update the page
+    // tables to map them as shared.
+    let result = page_state_change(vaddr, paddr, len, PageStateChangeOp::PscShared)
+        .and_then(|_| set_pgtable_range(vaddr, len, true));
+
+    if let Err(e) = result {
+        page_state_change(vaddr, paddr, len, PageStateChangeOp::PscPrivate)
+            .expect("Failed to roll back page state change");
+        pvalidate_run(vaddr, paddr, len, PvalidateOp::Valid)
+            .expect("Failed to roll back PVALIDATE");
+        valid_bitmap_update(paddr, len, true);
+        return Err(e);
     }
 
-    // Ask the hypervisor to make the page shared.
-    current_ghcb()
-        .page_state_change(
-            paddr,
-            paddr + PAGE_SIZE,
-            PageSize::Regular,
-            PageStateChangeOp::PscShared,
-        )
-        .expect("Hypervisor failed to make page shared");
-
-    // Update the page tables to map the page as shared.
-    this_cpu_mut()
-        .get_pgtable()
-        .set_shared_4k(vaddr)
-        .expect("Failed to remap shared page in page tables");
+    Ok(())
+}
+
+/// Makes a physically contiguous range private. On failure the range is
+/// left shared.
+fn make_run_private(vaddr: VirtAddr, paddr: PhysAddr, len: usize) -> Result<(), SvsmError> {
+    // Update the page tables to map the pages as private.
+    set_pgtable_range(vaddr, len, false)?;
     flush_tlb_global_sync();
+
+    // Ask the hypervisor to make the pages private and validate them.
+    let result = page_state_change(vaddr, paddr, len, PageStateChangeOp::PscPrivate)
+        .and_then(|_| pvalidate_run(vaddr, paddr, len, PvalidateOp::Valid));
+
+    if let Err(e) = result {
+        page_state_change(vaddr, paddr, len, PageStateChangeOp::PscShared)
+            .expect("Failed to roll back page state change");
+        set_pgtable_range(vaddr, len, true).expect("Failed to roll back page-table update");
+        flush_tlb_global_sync();
+        return Err(e);
+    }
+
+    valid_bitmap_update(paddr, len, true);
+    Ok(())
 }
 
-pub fn make_page_private(vaddr: VirtAddr) {
-    // Update the page tables to map the page as private.
-    this_cpu_mut()
-        .get_pgtable()
-        .set_encrypted_4k(vaddr)
-        .expect("Failed to remap private page in page tables");
+fn check_range(vaddr: VirtAddr, len: usize) -> Result<VirtAddr, SvsmError> {
+    let end = vaddr.checked_add(len).ok_or(SvsmError::Mem)?;
+    if !vaddr.is_page_aligned() || !end.is_page_aligned() {
+        return Err(SvsmError::Mem);
+    }
+    Ok(end)
+}
+
+/// Converts a range of private SVSM memory to memory shared with the
+/// hypervisor and maps it with the C-bit clear. The physically contiguous
+/// parts of the range are converted with one batch of page state changes
+/// each.
+///
+/// # Arguments
+///
+/// * `vaddr` - Page-aligned start of the range
+/// * `len` - Length of the range, a multiple of PAGE_SIZE
+///
+/// # Returns
+///
+/// `Ok(())` on success. On failure the whole range is private again and the
+/// error is returned.
+pub fn make_range_shared(vaddr: VirtAddr, len: usize) -> Result<(), SvsmError> {
+    let end = check_range(vaddr, len)?;
+    let mut addr = vaddr;
+
+    while addr < end {
+        let (paddr, run) = phys_run(addr, end, virt_to_phys);
+        if let Err(e) = make_run_shared(addr, paddr, run) {
+            if addr > vaddr {
+                flush_tlb_global_sync();
+                make_range_private(vaddr, addr - vaddr)
+                    .expect("Failed to roll back partially shared range");
+            }
+            return Err(e);
+        }
+        addr = addr + run;
+    }
+
     flush_tlb_global_sync();
+    Ok(())
+}
 
-    // Ask the hypervisor to make the page private.
-    let paddr = virt_to_phys(vaddr);
-    current_ghcb()
-        .page_state_change(
-            paddr,
-            paddr + PAGE_SIZE,
-            PageSize::Regular,
-            PageStateChangeOp::PscPrivate,
-        )
-        .expect("Hypervisor failed to make page private");
+/// Converts a range of shared memory, previously converted with
+/// [`make_range_shared()`], back to private SVSM memory.
+///
+/// # Arguments
+///
+/// * `vaddr` - Page-aligned start of the range
+/// * `len` - Length of the range, a multiple of PAGE_SIZE
+///
+/// # Returns
+///
+/// `Ok(())` on success. On failure the whole range is shared again and the
+/// error is returned.
+pub fn make_range_private(vaddr: VirtAddr, len: usize) -> Result<(), SvsmError> {
+    let end = check_range(vaddr, len)?;
+    let mut addr = vaddr;
 
-    // Revoke page validation before changing page state.
-    pvalidate(vaddr, PageSize::Regular, PvalidateOp::Valid)
-        .expect("Pvalidate failed when making page private");
-    if valid_bitmap_valid_addr(paddr) {
-        valid_bitmap_set_valid_4k(paddr);
+    while addr < end {
+        let (paddr, run) = phys_run(addr, end, virt_to_phys);
+        if let Err(e) = make_run_private(addr, paddr, run) {
+            if addr > vaddr {
+                make_range_shared(vaddr, addr - vaddr)
+                    .expect("Failed to roll back partially private range");
+            }
+            return Err(e);
+        }
+        addr = addr + run;
+    }
+
+    Ok(())
+}
+
+/// Converts a single private page to a shared page, see
+/// [`make_range_shared()`].
+pub fn make_page_shared(vaddr: VirtAddr) -> Result<(), SvsmError> {
+    make_range_shared(vaddr, PAGE_SIZE)
+}
+
+/// Converts a single shared page back to a private page, see
+/// [`make_range_private()`].
+pub fn make_page_private(vaddr: VirtAddr) -> Result<(), SvsmError> {
+    make_range_private(vaddr, PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phys_run() {
+        // Pages 0-2 and 4-5 are physically contiguous, page 3 is not
+        let translate = |vaddr: VirtAddr| {
+            let page = (vaddr.bits() - 0x10000) / PAGE_SIZE;
+            let pfn = if page == 3 { 0x900 } else { 0x100 + page };
+            PhysAddr::from(pfn * PAGE_SIZE)
+        };
+        let start = VirtAddr::from(0x10000usize);
+        let end = start + 6 * PAGE_SIZE;
+
+        assert_eq!(
+            phys_run(start, end, translate),
+            (PhysAddr::from(0x100000usize), 3 * PAGE_SIZE)
+        );
+        assert_eq!(
+            phys_run(start + 3 * PAGE_SIZE, end, translate),
+            (PhysAddr::from(0x900000usize), PAGE_SIZE)
+        );
+        assert_eq!(
+            phys_run(start + 4 * PAGE_SIZE, end, translate),
+            (PhysAddr::from(0x104000usize), 2 * PAGE_SIZE)
+        );
     }
 }
@@ -7,13 +7,13 @@
 use crate::address::{Address, PhysAddr, VirtAddr};
 use crate::cpu::control_regs::write_cr3;
 use crate::cpu::cpuid::cpuid_table;
-use crate::cpu::features::{cpu_has_nx, cpu_has_pge};
+use crate::cpu::features::{cpu_has_1g_pages, cpu_has_nx, cpu_has_pge};
 use crate::cpu::flush_tlb_global_sync;
 use crate::error::SvsmError;
 use crate::locking::{LockGuard, SpinLock};
 use crate::mm::alloc::{allocate_zeroed_page, free_page};
-use crate::mm::{phys_to_virt, virt_to_phys, PGTABLE_LVL3_IDX_SHARED};
-use crate::types::{PageSize, PAGE_SIZE, PAGE_SIZE_2M};
+use crate::mm::{phys_to_virt, virt_to_phys, PGTABLE_LVL3_IDX_HEAP, PGTABLE_LVL3_IDX_SHARED};
+use crate::types::{PageSize, PAGE_SIZE, PAGE_SIZE_1G, PAGE_SIZE_2M};
 use crate::utils::immut_after_init::ImmutAfterInitCell;
 use crate::utils::MemoryRegion;
 use bitflags::bitflags;
@@ -29,6 +29,7 @@ static MAX_PHYS_ADDR: ImmutAfterInitCell<u64> = ImmutAfterInitCell::uninit();
 pub const LAUNCH_VMSA_ADDR: PhysAddr = PhysAddr::new(0xFFFFFFFFF000);
 static FEATURE_MASK: ImmutAfterInitCell<PTEntryFlags> =
     ImmutAfterInitCell::new(PTEntryFlags::empty());
+static PAGE_1G_SUPPORTED: ImmutAfterInitCell<bool> = ImmutAfterInitCell::new(false);
 
 pub fn paging_init_early() {
     init_encrypt_mask();
@@ -37,6 +38,7 @@ pub fn paging_init_early() {
     feature_mask.remove(PTEntryFlags::NX);
     feature_mask.remove(PTEntryFlags::GLOBAL);
     FEATURE_MASK.reinit(&feature_mask);
+    PAGE_1G_SUPPORTED.reinit(&cpu_has_1g_pages());
 }
 
 pub fn paging_init() {
@@ -50,6 +52,7 @@ pub fn paging_init() {
         feature_mask.remove(PTEntryFlags::GLOBAL);
     }
     FEATURE_MASK.reinit(&feature_mask);
+    PAGE_1G_SUPPORTED.reinit(&cpu_has_1g_pages());
 }
 
 fn init_encrypt_mask() {
@@ -81,7 +84,8 @@ fn init_encrypt_mask() {
     MAX_PHYS_ADDR.reinit(&max_addr);
 }
 
-fn encrypt_mask() -> usize {
+/// Returns the mask of the C-bit in page-table entries and physical addresses.
+pub fn encrypt_mask() -> usize {
     *ENCRYPT_MASK
 }
 
@@ -238,6 +242,7 @@ impl PageTable {
         unsafe {
             let root = root_ptr.as_mut().unwrap();
             root.entries[PGTABLE_LVL3_IDX_SHARED] = self.root.entries[PGTABLE_LVL3_IDX_SHARED];
+            root.entries[PGTABLE_LVL3_IDX_HEAP] = self.root.entries[PGTABLE_LVL3_IDX_HEAP];
         }
 
         Ok(PageTableRef {
@@ -405,6 +410,83 @@ impl PageTable {
         }
     }
 
+    pub fn alloc_pte_1g(&mut self, vaddr: VirtAddr) -> Mapping<'_> {
+        let m = self.walk_addr(vaddr);
+
+        match m {
+            Mapping::Level0(entry) => Mapping::Level0(entry),
+            Mapping::Level1(entry) => Mapping::Level1(entry),
+            Mapping::Level2(entry) => Mapping::Level2(entry),
+            Mapping::Level3(entry) => PageTable::alloc_pte_lvl3_1g(entry, vaddr),
+        }
+    }
+
+    fn alloc_pte_lvl3_1g(entry: &mut PTEntry, vaddr: VirtAddr) -> Mapping<'_> {
+        let flags = entry.flags();
+
+        if flags.contains(PTEntryFlags::PRESENT) {
+            return Mapping::Level3(entry);
+        }
+
+        let page = match PageTable::allocate_page_table() {
+            Ok(page) => page,
+            _ => return Mapping::Level3(entry),
+        };
+
+        let paddr = virt_to_phys(VirtAddr::from(page));
+        let flags = PTEntryFlags::PRESENT
+            | PTEntryFlags::WRITABLE
+            | PTEntryFlags::USER
+            | PTEntryFlags::ACCESSED;
+        entry.set(set_c_bit(paddr), flags);
+
+        let idx = PageTable::index::<2>(vaddr);
+
+        unsafe { Mapping::Level2(&mut (*page)[idx]) }
+    }
+
+    fn do_split_2m(entry: &mut PTEntry) -> Result<(), SvsmError> {
+        let page = PageTable::allocate_page_table()?;
+        let mut flags = entry.flags();
+
+        assert!(flags.contains(PTEntryFlags::HUGE));
+
+        let addr_1g = PhysAddr::from(entry.address().bits() & 0x000f_ffff_c000_0000);
+
+        // Prepare PMD leaf page
+        for i in 0..512 {
+            let addr_2m = addr_1g + (i * PAGE_SIZE_2M);
+            unsafe {
+                (*page).entries[i].clear();
+                (*page).entries[i].set(set_c_bit(addr_2m), flags);
+            }
+        }
+
+        flags.remove(PTEntryFlags::HUGE);
+        entry.set(set_c_bit(virt_to_phys(VirtAddr::from(page))), flags);
+
+        flush_tlb_global_sync();
+
+        Ok(())
+    }
+
+    /// Splits a 1GiB mapping into 2MiB mappings. Mappings which are already
+    /// 2MiB or smaller are left alone.
+    pub fn split_2m(mapping: Mapping<'_>) -> Result<(), SvsmError> {
+        match mapping {
+            Mapping::Level0(_entry) => Ok(()),
+            Mapping::Level1(_entry) => Ok(()),
+            Mapping::Level2(entry) => {
+                if entry.flags().contains(PTEntryFlags::HUGE) {
+                    PageTable::do_split_2m(entry)
+                } else {
+                    Err(SvsmError::Mem)
+                }
+            }
+            Mapping::Level3(_entry) => Err(SvsmError::Mem),
+        }
+    }
+
     fn do_split_4k(entry: &mut PTEntry) -> Result<(), SvsmError> {
         let page = PageTable::allocate_page_table()?;
         let mut flags = entry.flags();
@@ -456,9 +538,17 @@ impl PageTable {
         entry.set(set_c_bit(addr), flags);
     }
 
-    pub fn set_shared_4k(&mut self, vaddr: VirtAddr) -> Result<(), SvsmError> {
+    /// Splits 1GiB and 2MiB mappings covering `vaddr` until it is mapped
+    /// by a 4KiB page.
+    pub fn split_to_4k(&mut self, vaddr: VirtAddr) -> Result<(), SvsmError> {
         let mapping = self.walk_addr(vaddr);
-        PageTable::split_4k(mapping)?;
+        PageTable::split_2m(mapping)?;
+        let mapping = self.walk_addr(vaddr);
+        PageTable::split_4k(mapping)
+    }
+
+    pub fn set_shared_4k(&mut self, vaddr: VirtAddr) -> Result<(), SvsmError> {
+        self.split_to_4k(vaddr)?;
 
         if let Mapping::Level0(entry) = self.walk_addr(vaddr) {
             PageTable::clear_c_bit(entry);
@@ -469,8 +559,7 @@ impl PageTable {
     }
 
     pub fn set_encrypted_4k(&mut self, vaddr: VirtAddr) -> Result<(), SvsmError> {
-        let mapping = self.walk_addr(vaddr);
-        PageTable::split_4k(mapping)?;
+        self.split_to_4k(vaddr)?;
 
         if let Mapping::Level0(entry) = self.walk_addr(vaddr) {
             PageTable::set_c_bit(entry);
@@ -484,10 +573,54 @@ impl PageTable {
         match self.walk_addr(vaddr) {
             Mapping::Level0(entry) => Some(entry.address()),
             Mapping::Level1(entry) => Some(entry.address()),
+            Mapping::Level2(entry) if entry.flags().contains(PTEntryFlags::HUGE) => {
+                Some(entry.address())
+            }
             _ => None,
         }
     }
 
+    pub fn map_1g(
+        &mut self,
+        vaddr: VirtAddr,
+        paddr: PhysAddr,
+        flags: PTEntryFlags,
+    ) -> Result<(), SvsmError> {
+        assert!(vaddr.is_aligned(PAGE_SIZE_1G));
+        assert!(paddr.is_aligned(PAGE_SIZE_1G));
+
+        if !*PAGE_1G_SUPPORTED {
+            return Err(SvsmError::Mem);
+        }
+
+        let mapping = self.alloc_pte_1g(vaddr);
+
+        match mapping {
+            // A present entry which is not a leaf points to a lower-level
+            // page-table, which must not be replaced.
+            Mapping::Level2(entry)
+                if !entry.present() || entry.flags().contains(PTEntryFlags::HUGE) =>
+            {
+                entry.set(set_c_bit(paddr), flags | PTEntryFlags::HUGE);
+                Ok(())
+            }
+            _ => Err(SvsmError::Mem),
+        }
+    }
+
+    pub fn unmap_1g(&mut self, vaddr: VirtAddr) {
+        assert!(vaddr.is_aligned(PAGE_SIZE_1G));
+
+        let mapping = self.walk_addr(vaddr);
+
+        match mapping {
+            Mapping::Level0(_) => unreachable!(),
+            Mapping::Level1(_) => unreachable!(),
+            Mapping::Level2(entry) => entry.clear(),
+            Mapping::Level3(entry) => assert!(!entry.present()),
+        }
+    }
+
     pub fn map_2m(
         &mut self,
         vaddr: VirtAddr,
@@ -568,7 +701,16 @@ impl PageTable {
 
                 Ok(entry.address() + offset)
             }
-            Mapping::Level2(_entry) => Err(SvsmError::Mem),
+            Mapping::Level2(entry) => {
+                let offset = vaddr.bits() & (PAGE_SIZE_1G - 1);
+                if !entry.flags().contains(PTEntryFlags::PRESENT)
+                    || !entry.flags().contains(PTEntryFlags::HUGE)
+                {
+                    return Err(SvsmError::Mem);
+                }
+
+                Ok(entry.address() + offset)
+            }
             Mapping::Level3(_entry) => Err(SvsmError::Mem),
         }
     }
@@ -622,6 +764,16 @@ impl PageTable {
         let mut paddr = phys;
 
         while vaddr < end {
+            if vaddr.is_aligned(PAGE_SIZE_1G)
+                && paddr.is_aligned(PAGE_SIZE_1G)
+                && end - vaddr >= PAGE_SIZE_1G
+                && self.map_1g(vaddr, paddr, flags).is_ok()
+            {
+                vaddr = vaddr + PAGE_SIZE_1G;
+                paddr = paddr + PAGE_SIZE_1G;
+                continue;
+            }
+
             if vaddr.is_aligned(PAGE_SIZE_2M)
                 && paddr.is_aligned(PAGE_SIZE_2M)
                 && vaddr + PAGE_SIZE_2M <= end
@@ -656,6 +808,10 @@ impl PageTable {
                     entry.clear();
                     vaddr = vaddr + PAGE_SIZE_2M;
                 }
+                Mapping::Level2(entry) if entry.flags().contains(PTEntryFlags::HUGE) => {
+                    entry.clear();
+                    vaddr = vaddr + PAGE_SIZE_1G;
+                }
                 _ => {
                     log::error!("Can't unmap - address not mapped {:#x}", vaddr);
                 }
@@ -706,6 +862,22 @@ impl PageTableRef {
     fn is_set(&self) -> bool {
         !self.pgtable_ptr.is_null()
     }
+
+    /// Frees the root page of a page-table created with
+    /// [`PageTable::clone_shared()`] and leaves the reference unset. The
+    /// lower levels are either shared with other page-tables or owned by
+    /// [`PageTablePart`]s and are not freed.
+    ///
+    /// # Safety
+    ///
+    /// The caller must make sure the page-table is not loaded on any CPU and
+    /// is not used anymore.
+    pub unsafe fn free(&mut self) {
+        if self.is_set() {
+            free_page(VirtAddr::from(self.pgtable_ptr));
+            self.pgtable_ptr = ptr::null_mut();
+        }
+    }
 }
 
 impl Deref for PageTableRef {
@@ -1038,3 +1210,156 @@ impl PageTablePart {
         self.raw.unmap_2m(vaddr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::alloc::{TestRootMem, DEFAULT_TEST_MEMORY_SIZE};
+
+    const TEST_VADDR: VirtAddr = VirtAddr::new(3 * PAGE_SIZE_1G);
+    const TEST_PADDR: PhysAddr = PhysAddr::new(5 * PAGE_SIZE_1G);
+
+    /// Enables the page-table features which paging_init() enables inside
+    /// the SVSM. Must be called with the test memory lock held.
+    fn test_paging_init() {
+        FEATURE_MASK.reinit(&PTEntryFlags::all());
+        PAGE_1G_SUPPORTED.reinit(&true);
+    }
+
+    fn is_huge_leaf(mapping: &Mapping<'_>) -> bool {
+        match mapping {
+            Mapping::Level2(entry) | Mapping::Level1(entry) => {
+                entry.present() && entry.flags().contains(PTEntryFlags::HUGE)
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_map_1g() {
+        let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+        test_paging_init();
+        let mut pgtable = PageTable::default();
+
+        pgtable
+            .map_1g(TEST_VADDR, TEST_PADDR, PTEntryFlags::data())
+            .expect("Failed to map 1G page");
+
+        let mapping = pgtable.walk_addr(TEST_VADDR);
+        assert!(matches!(mapping, Mapping::Level2(_)));
+        assert!(is_huge_leaf(&mapping));
+        assert_eq!(
+            pgtable.phys_addr(TEST_VADDR + 0x1234_5678).unwrap(),
+            TEST_PADDR + 0x1234_5678
+        );
+
+        pgtable.unmap_1g(TEST_VADDR);
+        assert!(pgtable.phys_addr(TEST_VADDR).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_map_1g_over_table() {
+        let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+        test_paging_init();
+        let mut pgtable = PageTable::default();
+
+        // The 4K mapping allocates lower-level tables for the 1G range
+        pgtable
+            .map_4k(TEST_VADDR, TEST_PADDR, PTEntryFlags::data())
+            .expect("Failed to map 4K page");
+        pgtable
+            .map_1g(TEST_VADDR, TEST_PADDR, PTEntryFlags::data())
+            .unwrap_err();
+
+        // The 4K mapping is unchanged
+        assert!(matches!(pgtable.walk_addr(TEST_VADDR), Mapping::Level0(_)));
+        assert_eq!(pgtable.phys_addr(TEST_VADDR).unwrap(), TEST_PADDR);
+    }
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_map_region_page_sizes() {
+        let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+        test_paging_init();
+        let mut pgtable = PageTable::default();
+
+        let len = PAGE_SIZE_1G + PAGE_SIZE_2M + PAGE_SIZE;
+        let region = MemoryRegion::new(TEST_VADDR, len);
+        pgtable
+            .map_region(region, TEST_PADDR, PTEntryFlags::data())
+            .expect("Failed to map region");
+
+        // The region is mapped with the largest possible pages
+        let mapping = pgtable.walk_addr(TEST_VADDR);
+        assert!(matches!(mapping, Mapping::Level2(_)) && is_huge_leaf(&mapping));
+        let mapping = pgtable.walk_addr(TEST_VADDR + PAGE_SIZE_1G);
+        assert!(matches!(mapping, Mapping::Level1(_)) && is_huge_leaf(&mapping));
+        let vaddr = TEST_VADDR + PAGE_SIZE_1G + PAGE_SIZE_2M;
+        assert!(matches!(pgtable.walk_addr(vaddr), Mapping::Level0(_)));
+        assert_eq!(
+            pgtable.phys_addr(vaddr).unwrap(),
+            TEST_PADDR + PAGE_SIZE_1G + PAGE_SIZE_2M
+        );
+
+        // Nothing is mapped beyond the end of the region
+        assert!(pgtable.phys_addr(TEST_VADDR + len).is_err());
+    }
+
+    /// Frees the lower-level page-tables of `pgtable` which map `vaddr`.
+    fn free_test_tables(pgtable: &mut PageTable, vaddr: VirtAddr) {
+        let mut tables = [None; 3];
+        let mut page = &mut pgtable.root;
+        for (level, table) in tables.iter_mut().enumerate() {
+            let idx = vaddr.bits() >> (12 + (3 - level) * 9) & 0x1ff;
+            match PageTable::entry_to_pagetable(page[idx]) {
+                Some(next) => {
+                    *table = Some(VirtAddr::from(ptr::from_mut(next)));
+                    page = next;
+                }
+                None => break,
+            }
+        }
+        for table in tables.into_iter().flatten() {
+            free_page(table);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_split_1g() {
+        if !*PAGE_1G_SUPPORTED {
+            log::info!("1G pages not supported - skipping test");
+            return;
+        }
+        let mut pgtable = PageTable::default();
+
+        pgtable
+            .map_1g(TEST_VADDR, TEST_PADDR, PTEntryFlags::data())
+            .expect("Failed to map 1G page");
+
+        // Splitting down to 4K only splits the 2M page containing the address
+        let offset = PAGE_SIZE_2M + 3 * PAGE_SIZE;
+        pgtable
+            .split_to_4k(TEST_VADDR + offset)
+            .expect("Failed to split 1G page");
+
+        let mapping = pgtable.walk_addr(TEST_VADDR);
+        assert!(matches!(mapping, Mapping::Level1(_)) && is_huge_leaf(&mapping));
+        assert!(matches!(
+            pgtable.walk_addr(TEST_VADDR + offset),
+            Mapping::Level0(_)
+        ));
+
+        // The split mappings still translate to the same addresses
+        for offset in [0, PAGE_SIZE_2M, offset, PAGE_SIZE_1G - PAGE_SIZE] {
+            assert_eq!(
+                pgtable.phys_addr(TEST_VADDR + offset).unwrap(),
+                TEST_PADDR + offset
+            );
+        }
+
+        free_test_tables(&mut pgtable, TEST_VADDR + offset);
+    }
+}
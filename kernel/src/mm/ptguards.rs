@@ -70,6 +70,16 @@ impl PerCPUPageMappingGuard {
         Self::create(paddr, paddr + PAGE_SIZE, 0)
     }
 
+    /// Maps a page which is shared with the hypervisor, i.e. with the C-bit
+    /// clear in the page-table entry.
+    pub fn create_4k_shared(paddr: PhysAddr) -> Result<Self, SvsmError> {
+        let guard = Self::create_4k(paddr)?;
+        let vaddr = guard.virt_addr();
+        this_cpu_mut().get_pgtable().set_shared_4k(vaddr)?;
+        flush_address_sync(vaddr);
+        Ok(guard)
+    }
+
     pub fn virt_addr(&self) -> VirtAddr {
         self.mapping.start()
     }
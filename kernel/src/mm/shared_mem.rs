@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Allocator for memory shared with the hypervisor.
+//!
+//! Buffers are allocated from the page allocator and converted to shared
+//! memory with [`make_range_shared()`]. Released buffers are kept in a
+//! per-order pool of already converted pages, so that repeated allocations
+//! do not need a page state change each time.
+//!
+//! The allocator guarantees that:
+//!
+//! * Buffers are zeroed when they are handed out and when they are released,
+//!   so no data leaks to the hypervisor beyond the lifetime of a buffer and
+//!   nothing the hypervisor wrote to a pooled page leaks into a new buffer.
+//! * A shared page is only returned to the page allocator after it was
+//!   successfully converted back to private memory. Pages which can not be
+//!   converted back are leaked, so shared pages never alias private
+//!   allocations.
+//!
+//! Note that the hypervisor can modify shared memory at any time. Contents
+//! read from a shared buffer must be validated before use.
+
+extern crate alloc;
+
+use crate::address::{PhysAddr, VirtAddr};
+use crate::error::SvsmError;
+use crate::locking::SpinLock;
+use crate::mm::alloc::{allocate_pages, free_page, get_order};
+use crate::mm::page_visibility::{make_range_private, make_range_shared};
+use crate::mm::virt_to_phys;
+use crate::types::PAGE_SIZE;
+use crate::utils::zero_mem_region;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ptr;
+
+/// Number of allocation orders which are cached in the pool
+const SHARED_POOL_ORDERS: usize = 4;
+/// Maximum number of released buffers cached per order
+const SHARED_POOL_MAX_CACHED: usize = 8;
+
+/// Cache of released shared buffers, indexed by allocation order.
+#[derive(Debug)]
+struct SharedPool {
+    free: [Vec<VirtAddr>; SHARED_POOL_ORDERS],
+}
+
+impl SharedPool {
+    const fn new() -> Self {
+        Self {
+            free: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Takes a cached buffer of the given order out of the pool.
+    fn take(&mut self, order: usize) -> Option<VirtAddr> {
+        self.free.get_mut(order)?.pop()
+    }
+
+    /// Puts a buffer of the given order into the pool. Returns `false` if
+    /// the buffer can not be cached and must be released by the caller.
+    fn give(&mut self, order: usize, vaddr: VirtAddr) -> bool {
+        let Some(list) = self.free.get_mut(order) else {
+            return false;
+        };
+        if list.len() >= SHARED_POOL_MAX_CACHED || list.try_reserve(1).is_err() {
+            return false;
+        }
+        list.push(vaddr);
+        true
+    }
+
+    /// Returns the number of cached buffers of the given order.
+    fn cached(&self, order: usize) -> usize {
+        self.free.get(order).map_or(0, Vec::len)
+    }
+}
+
+static SHARED_POOL: SpinLock<SharedPool> = SpinLock::new(SharedPool::new());
+
+/// Allocates `1 << order` pages of shared memory, from the pool if possible.
+fn shared_pages_alloc(order: usize) -> Result<VirtAddr, SvsmError> {
+    let size = PAGE_SIZE << order;
+    let cached = SHARED_POOL.lock().take(order);
+    let vaddr = match cached {
+        Some(vaddr) => vaddr,
+        None => {
+            let vaddr = allocate_pages(order)?;
+            if let Err(e) = make_range_shared(vaddr, size) {
+                // The range is private again on failure
+                free_page(vaddr);
+                return Err(e);
+            }
+            vaddr
+        }
+    };
+
+    // The hypervisor may have written to the pages while they were not in
+    // use.
+    zero_mem_region(vaddr, vaddr + size);
+    Ok(vaddr)
+}
+
+/// Releases pages allocated with [`shared_pages_alloc()`].
+fn shared_pages_free(vaddr: VirtAddr, order: usize) {
+    let size = PAGE_SIZE << order;
+    zero_mem_region(vaddr, vaddr + size);
+
+    if SHARED_POOL.lock().give(order, vaddr) {
+        return;
+    }
+
+    match make_range_private(vaddr, size) {
+        Ok(()) => free_page(vaddr),
+        Err(e) => log::error!(
+            "Failed to make shared memory at {:#018x} private, leaking it: {:?}",
+            vaddr,
+            e
+        ),
+    }
+}
+
+/// Returns the number of pages currently cached in the shared memory pool.
+pub fn shared_pool_cached_pages() -> usize {
+    let pool = SHARED_POOL.lock();
+    (0..SHARED_POOL_ORDERS)
+        .map(|order| pool.cached(order) << order)
+        .sum()
+}
+
+/// A page-aligned buffer in memory shared with the hypervisor.
+#[derive(Debug)]
+pub struct SharedBuffer {
+    vaddr: VirtAddr,
+    size: usize,
+    order: usize,
+}
+
+impl SharedBuffer {
+    /// Allocates a zeroed shared buffer of `size` bytes.
+    ///
+    /// # Returns
+    ///
+    /// The new buffer on success, `Err(SvsmError::Mem)` if `size` is zero,
+    /// or the error from allocating or converting the backing pages.
+    pub fn new(size: usize) -> Result<Self, SvsmError> {
+        if size == 0 {
+            return Err(SvsmError::Mem);
+        }
+        let order = get_order(size);
+        let vaddr = shared_pages_alloc(order)?;
+        Ok(Self { vaddr, size, order })
+    }
+
+    /// Returns the virtual address of the buffer.
+    pub fn vaddr(&self) -> VirtAddr {
+        self.vaddr
+    }
+
+    /// Returns the physical address of the buffer, which is the address to
+    /// pass to the hypervisor. The buffer is physically contiguous.
+    pub fn paddr(&self) -> PhysAddr {
+        virt_to_phys(self.vaddr)
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), SvsmError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size => Ok(()),
+            _ => Err(SvsmError::Mem),
+        }
+    }
+
+    /// Copies bytes out of the buffer, starting at `offset`.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, `Err(SvsmError::Mem)` if the range is not within
+    /// the buffer.
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<(), SvsmError> {
+        self.check_range(offset, buf.len())?;
+        let src = (self.vaddr + offset).as_ptr::<u8>();
+        // SAFETY: the range was checked to be within the buffer, which is
+        // mapped and owned by self.
+        unsafe { ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    /// Copies bytes into the buffer, starting at `offset`.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, `Err(SvsmError::Mem)` if the range is not within
+    /// the buffer.
+    pub fn write_bytes(&mut self, offset: usize, data: &[u8]) -> Result<(), SvsmError> {
+        self.check_range(offset, data.len())?;
+        let dst = (self.vaddr + offset).as_mut_ptr::<u8>();
+        // SAFETY: the range was checked to be within the buffer, which is
+        // mapped and owned by self.
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len()) };
+        Ok(())
+    }
+
+    /// Zeroes the whole buffer.
+    pub fn clear(&mut self) {
+        zero_mem_region(self.vaddr, self.vaddr + self.size);
+    }
+}
+
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        shared_pages_free(self.vaddr, self.order);
+    }
+}
+
+/// Marker for plain data types which can be placed in a [`SharedBox`].
+///
+/// # Safety
+///
+/// The hypervisor can modify shared memory at any time, so implementors
+/// must be valid for every bit pattern, including all zeroes, and must not
+/// contain pointers or references.
+pub unsafe trait SharedData: Copy {}
+
+/// A pointer type for a value of type `T` placed in memory shared with the
+/// hypervisor, similar to [`alloc::boxed::Box`].
+///
+/// The value is never referenced in place. It is only accessed by copying
+/// it in and out of the shared memory.
+pub struct SharedBox<T: SharedData> {
+    buf: SharedBuffer,
+    phantom: PhantomData<T>,
+}
+
+impl<T: SharedData> SharedBox<T> {
+    /// Allocates zeroed shared memory for a `T`, without going through the
+    /// stack. This is useful for large objects.
+    pub fn try_new_zeroed() -> Result<Self, SvsmError> {
+        assert!(align_of::<T>() <= PAGE_SIZE);
+        let buf = SharedBuffer::new(size_of::<T>().max(1))?;
+        Ok(Self {
+            buf,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the virtual address of the value.
+    pub fn vaddr(&self) -> VirtAddr {
+        self.buf.vaddr()
+    }
+
+    /// Returns the physical address of the value, which is the address to
+    /// pass to the hypervisor.
+    pub fn paddr(&self) -> PhysAddr {
+        self.buf.paddr()
+    }
+
+    /// Copies the current value out of shared memory into `dst`.
+    pub fn read_into(&self, dst: &mut T) {
+        let src = self.buf.vaddr().as_ptr::<T>();
+        // SAFETY: the buffer is page aligned and large enough for a T. Every
+        // bit pattern is valid for T, whatever the hypervisor wrote.
+        unsafe { ptr::copy_nonoverlapping(src, dst, 1) };
+    }
+
+    /// Copies `src` into shared memory.
+    pub fn write_from(&mut self, src: &T) {
+        let dst = self.buf.vaddr().as_mut_ptr::<T>();
+        // SAFETY: the buffer is page aligned, large enough for a T and
+        // uniquely owned by self.
+        unsafe { ptr::copy_nonoverlapping(src, dst, 1) };
+    }
+
+    /// Copies bytes out of the value, starting at `offset`.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, `Err(SvsmError::Mem)` if the range is not within
+    /// the value.
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<(), SvsmError> {
+        self.check_range(offset, buf.len())?;
+        self.buf.read_bytes(offset, buf)
+    }
+
+    /// Zeroes the whole value.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Zeroes the first `n` bytes of the value.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, `Err(SvsmError::Mem)` if `n` is larger than the
+    /// value.
+    pub fn nclear(&mut self, n: usize) -> Result<(), SvsmError> {
+        self.check_range(0, n)?;
+        let vaddr = self.buf.vaddr();
+        zero_mem_region(vaddr, vaddr + n);
+        Ok(())
+    }
+
+    /// Checks whether the first `n` bytes of the value are zero.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if all bytes are zero, `Ok(false)` if not, or
+    /// `Err(SvsmError::Mem)` if `n` is larger than the value.
+    pub fn is_nclear(&self, n: usize) -> Result<bool, SvsmError> {
+        self.check_range(0, n)?;
+        let base = self.buf.vaddr().as_ptr::<u8>();
+        // SAFETY: the range was checked to be within the value. Volatile
+        // reads avoid creating a reference to memory the hypervisor can
+        // modify.
+        Ok((0..n).all(|i| unsafe { ptr::read_volatile(base.add(i)) } == 0))
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), SvsmError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= size_of::<T>() => Ok(()),
+            _ => Err(SvsmError::Mem),
+        }
+    }
+}
+
+impl<T: SharedData> fmt::Debug for SharedBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedBox")
+            .field("vaddr", &self.vaddr())
+            .field("size", &size_of::<T>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_pool_cache() {
+        let mut pool = SharedPool::new();
+        let vaddr = VirtAddr::from(0x100000usize);
+
+        assert_eq!(pool.take(0), None);
+        for i in 0..SHARED_POOL_MAX_CACHED {
+            assert!(pool.give(1, vaddr + i * 2 * PAGE_SIZE));
+        }
+        // The pool for this order is full
+        assert!(!pool.give(1, vaddr));
+        // Orders beyond the pool are never cached
+        assert!(!pool.give(SHARED_POOL_ORDERS, vaddr));
+        assert_eq!(pool.take(SHARED_POOL_ORDERS), None);
+
+        assert_eq!(pool.cached(0), 0);
+        assert_eq!(pool.cached(1), SHARED_POOL_MAX_CACHED);
+        assert_eq!(
+            pool.take(1),
+            Some(vaddr + (SHARED_POOL_MAX_CACHED - 1) * 2 * PAGE_SIZE)
+        );
+        assert_eq!(pool.cached(1), SHARED_POOL_MAX_CACHED - 1);
+    }
+}
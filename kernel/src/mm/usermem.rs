@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::address::{Address, VirtAddr};
+use crate::error::SvsmError;
+use crate::mm::{USER_MEM_END, USER_MEM_START};
+
+use core::arch::asm;
+
+/// Checks that the range of `len` bytes at `addr` is completely contained in
+/// the user-space part of the address space.
+fn check_user_range(addr: VirtAddr, len: usize) -> Result<(), SvsmError> {
+    let end = addr
+        .bits()
+        .checked_add(len)
+        .ok_or(SvsmError::InvalidAddress)?;
+
+    if addr < USER_MEM_START || end > USER_MEM_END.bits() {
+        return Err(SvsmError::InvalidAddress);
+    }
+
+    Ok(())
+}
+
+#[inline]
+unsafe fn do_movsb_user(src: *const u8, dst: *mut u8, size: usize) -> Result<(), SvsmError> {
+    let mut rcx: u64;
+
+    asm!("1:cld
+            rep movsb
+          2:
+         .pushsection \"__exception_table\",\"a\"
+         .balign 16
+         .quad (1b)
+         .quad (2b)
+         .popsection",
+            inout("rsi") src => _,
+            inout("rdi") dst => _,
+            inout("rcx") size => rcx,
+            options(att_syntax, nostack));
+
+    if rcx == 0 {
+        Ok(())
+    } else {
+        Err(SvsmError::InvalidAddress)
+    }
+}
+
+/// Copies data from user-space memory into a kernel buffer. Faults while
+/// accessing user memory are caught by the exception table.
+///
+/// # Arguments
+///
+/// * `src` - User-space address to copy from
+/// * `dst` - Kernel buffer to copy to. The whole buffer is filled.
+///
+/// # Returns
+///
+/// `Ok(())` on success, `Err(SvsmError::InvalidAddress)` when the source
+/// range is not in user-space or not mapped readable.
+pub fn copy_from_user(src: VirtAddr, dst: &mut [u8]) -> Result<(), SvsmError> {
+    check_user_range(src, dst.len())?;
+    // SAFETY: The source is in the user-space range and faults are handled
+    // via the exception table. The destination is a valid kernel buffer.
+    unsafe { do_movsb_user(src.as_ptr::<u8>(), dst.as_mut_ptr(), dst.len()) }
+}
+
+/// Copies data from a kernel buffer to user-space memory. Faults while
+/// accessing user memory are caught by the exception table.
+///
+/// # Arguments
+///
+/// * `src` - Kernel buffer to copy from
+/// * `dst` - User-space address to copy to
+///
+/// # Returns
+///
+/// `Ok(())` on success, `Err(SvsmError::InvalidAddress)` when the
+/// destination range is not in user-space or not mapped writable.
+pub fn copy_to_user(src: &[u8], dst: VirtAddr) -> Result<(), SvsmError> {
+    check_user_range(dst, src.len())?;
+    // SAFETY: The destination is in the user-space range and faults are
+    // handled via the exception table. The source is a valid kernel buffer.
+    unsafe { do_movsb_user(src.as_ptr(), dst.as_mut_ptr::<u8>(), src.len()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::SVSM_PERCPU_BASE;
+
+    #[test]
+    fn copy_from_user_rejects_kernel_address() {
+        let mut buf = [0u8; 8];
+        assert!(matches!(
+            copy_from_user(SVSM_PERCPU_BASE, &mut buf),
+            Err(SvsmError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn copy_to_user_rejects_kernel_address() {
+        let buf = [0u8; 8];
+        assert!(matches!(
+            copy_to_user(&buf, SVSM_PERCPU_BASE),
+            Err(SvsmError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn copy_user_rejects_range_crossing_end() {
+        let mut buf = [0u8; 16];
+        let addr = USER_MEM_END - 8;
+        assert!(matches!(
+            copy_from_user(addr, &mut buf),
+            Err(SvsmError::InvalidAddress)
+        ));
+        assert!(matches!(
+            copy_to_user(&buf, addr),
+            Err(SvsmError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn copy_user_rejects_wrapping_range() {
+        let addr = VirtAddr::from(usize::MAX - 7);
+        assert!(matches!(
+            check_user_range(addr, 16),
+            Err(SvsmError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn copy_from_user_fault() {
+        // Kernel tasks have no user-space mappings, so the access faults and
+        // is recovered through the exception table.
+        let mut buf = [0u8; 8];
+        assert!(matches!(
+            copy_from_user(USER_MEM_START, &mut buf),
+            Err(SvsmError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn copy_to_user_fault() {
+        let buf = [0u8; 8];
+        assert!(matches!(
+            copy_to_user(&buf, USER_MEM_START + 0x1000),
+            Err(SvsmError::InvalidAddress)
+        ));
+    }
+}
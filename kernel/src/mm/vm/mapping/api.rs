@@ -9,6 +9,7 @@ use crate::error::SvsmError;
 use crate::locking::{RWLock, ReadLockGuard, WriteLockGuard};
 use crate::mm::pagetable::PTEntryFlags;
 use crate::mm::vm::VMR;
+use crate::mm::PageRef;
 use crate::types::{PageSize, PAGE_SHIFT};
 
 use intrusive_collections::rbtree::Link;
@@ -72,6 +73,22 @@ pub trait VirtualMapping: core::fmt::Debug {
         // Provide default in case there is nothing to do
     }
 
+    /// Release the backing page at an offset, so that the next access to it
+    /// faults again. Implementing `discard()` is optional.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset into the virtual mapping of the page to release
+    ///
+    /// # Returns
+    ///
+    /// The released page, if one was allocated. The caller must only drop it
+    /// after the page was unmapped and the TLB was flushed.
+    /// `Err(SvsmError::Mem)` if the mapping does not support releasing pages.
+    fn discard(&mut self, _offset: usize) -> Result<Option<PageRef>, SvsmError> {
+        Err(SvsmError::Mem)
+    }
+
     /// Request the PTEntryFlags used for this virtual memory mapping.
     ///
     /// # Arguments
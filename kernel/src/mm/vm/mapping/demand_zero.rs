@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::address::PhysAddr;
+use crate::error::SvsmError;
+use crate::mm::pagetable::PTEntryFlags;
+use crate::mm::vm::VMR;
+use crate::mm::PageRef;
+
+use super::rawalloc::RawAllocMapping;
+use super::{Mapping, VMPageFaultResolution, VirtualMapping};
+
+/// Virtual mapping backed by zeroed pages which are allocated on first
+/// access. Large buffers which are only partially used this way only occupy
+/// memory for the pages actually touched.
+///
+/// The number of resident pages can be limited. Accesses which would exceed
+/// the limit fault like accesses to unmapped memory. Pages can be released
+/// again with [`VMR::discard()`], the next access to them returns zeroed
+/// memory.
+#[derive(Debug)]
+pub struct VMDemandZero {
+    /// [`RawAllocMapping`] holding the pages allocated so far
+    alloc: RawAllocMapping,
+
+    /// Maximum number of resident pages, `None` for no limit
+    max_resident: Option<usize>,
+
+    /// Number of pages currently allocated
+    resident: usize,
+}
+
+impl VMDemandZero {
+    /// Create a new instance without allocating any memory
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of the mapping. Must be aligned to PAGE_SIZE
+    /// * `max_resident` - Maximum number of pages allocated at any time, or
+    ///   `None` to allow the whole mapping to become resident
+    ///
+    /// # Returns
+    ///
+    /// New instance of VMDemandZero
+    pub fn new(size: usize, max_resident: Option<usize>) -> Self {
+        VMDemandZero {
+            alloc: RawAllocMapping::new(size),
+            max_resident,
+            resident: 0,
+        }
+    }
+
+    /// Create a new [`Mapping`] of [`VMDemandZero`] without a limit on
+    /// resident pages
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of the mapping. Must be aligned to PAGE_SIZE
+    ///
+    /// # Returns
+    ///
+    /// New [`Mapping`] of VMDemandZero
+    pub fn new_mapping(size: usize) -> Mapping {
+        Mapping::new(Self::new(size, None))
+    }
+
+    /// Request the number of pages currently allocated for the mapping
+    pub fn resident_pages(&self) -> usize {
+        self.resident
+    }
+}
+
+impl VirtualMapping for VMDemandZero {
+    fn mapping_size(&self) -> usize {
+        self.alloc.mapping_size()
+    }
+
+    fn map(&self, offset: usize) -> Option<PhysAddr> {
+        self.alloc.map(offset)
+    }
+
+    fn unmap(&self, offset: usize) {
+        self.alloc.unmap(offset);
+    }
+
+    fn pt_flags(&self, _offset: usize) -> PTEntryFlags {
+        PTEntryFlags::WRITABLE | PTEntryFlags::NX | PTEntryFlags::ACCESSED | PTEntryFlags::DIRTY
+    }
+
+    fn discard(&mut self, offset: usize) -> Result<Option<PageRef>, SvsmError> {
+        let page = self.alloc.take_page(offset);
+        if page.is_some() {
+            self.resident -= 1;
+        }
+        Ok(page)
+    }
+
+    fn handle_page_fault(
+        &mut self,
+        _vmr: &VMR,
+        offset: usize,
+        _write: bool,
+    ) -> Result<VMPageFaultResolution, SvsmError> {
+        // Another CPU might have populated the page already
+        if !self.alloc.present(offset) {
+            if offset >= self.alloc.mapping_size()
+                || self.max_resident.is_some_and(|max| self.resident >= max)
+            {
+                return Err(SvsmError::Mem);
+            }
+            // File pages are zeroed on allocation
+            self.alloc.alloc_page(offset)?;
+            self.resident += 1;
+        }
+
+        let paddr = self.alloc.map(offset).ok_or(SvsmError::Mem)?;
+        Ok(VMPageFaultResolution {
+            paddr,
+            flags: self.pt_flags(offset) | PTEntryFlags::PRESENT,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{Address, VirtAddr};
+    use crate::mm::alloc::{TestRootMem, DEFAULT_TEST_MEMORY_SIZE};
+    use crate::types::PAGE_SIZE;
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_demand_zero() {
+        let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+
+        let mut vm = VMDemandZero::new(PAGE_SIZE * 4, Some(2));
+        let vmr = VMR::new(
+            VirtAddr::from(0usize),
+            VirtAddr::from(16usize * PAGE_SIZE),
+            PTEntryFlags::data(),
+        );
+        assert_eq!(vm.mapping_size(), PAGE_SIZE * 4);
+        assert!(vm.map(0).is_none());
+
+        let res = vm
+            .handle_page_fault(&vmr, PAGE_SIZE + 8, false)
+            .expect("handle_page_fault() failed");
+        assert_eq!(vm.map(PAGE_SIZE), Some(res.paddr));
+        assert_eq!(vm.resident_pages(), 1);
+        // In the test environment physical addresses are virtual addresses
+        let page = unsafe { core::slice::from_raw_parts(res.paddr.bits() as *const u8, PAGE_SIZE) };
+        assert!(page.iter().all(|b| *b == 0));
+
+        // A second fault on the same page does not allocate again
+        let res2 = vm
+            .handle_page_fault(&vmr, PAGE_SIZE, true)
+            .expect("handle_page_fault() failed");
+        assert_eq!(res.paddr, res2.paddr);
+        assert_eq!(vm.resident_pages(), 1);
+
+        // The limit of resident pages is enforced
+        vm.handle_page_fault(&vmr, 0, true)
+            .expect("handle_page_fault() failed");
+        assert!(vm.handle_page_fault(&vmr, PAGE_SIZE * 2, true).is_err());
+        assert!(vm.handle_page_fault(&vmr, PAGE_SIZE * 4, true).is_err());
+
+        // Discarded pages make room for new ones
+        assert!(vm.discard(0).expect("discard() failed").is_some());
+        assert!(vm.discard(0).expect("discard() failed").is_none());
+        assert!(vm.map(0).is_none());
+        assert_eq!(vm.resident_pages(), 1);
+        vm.handle_page_fault(&vmr, PAGE_SIZE * 2, true)
+            .expect("handle_page_fault() failed");
+        assert_eq!(vm.resident_pages(), 2);
+    }
+}
@@ -6,26 +6,16 @@
 
 extern crate alloc;
 
-use core::slice::from_raw_parts_mut;
-
-#[cfg(not(test))]
-use alloc::sync::Arc;
-
 use alloc::vec::Vec;
 
-#[cfg(not(test))]
-use super::{Mapping, VMPhysMem};
-
 use super::{RawAllocMapping, VMPageFaultResolution, VirtualMapping};
-#[cfg(test)]
-use crate::address::Address;
 use crate::address::PhysAddr;
 use crate::error::SvsmError;
 use crate::fs::FileHandle;
 use crate::mm::vm::VMR;
 use crate::mm::PageRef;
 use crate::mm::{pagetable::PTEntryFlags, PAGE_SIZE};
-use crate::types::{PageSize, PAGE_SHIFT};
+use crate::types::PAGE_SHIFT;
 use crate::utils::align_up;
 
 #[derive(Debug)]
@@ -63,9 +53,18 @@ pub enum VMFileMappingPermission {
     Write,
     // Read-only access that allows execution
     Execute,
+    // Read/Write access to the file pages themselves
+    Shared,
 }
 
 /// Map view of a ramfs file into virtual memory
+///
+/// [`VMFileMappingPermission::Write`] mappings initially map the file pages
+/// read-only. The first write to a page faults and replaces it with a
+/// private copy of the file page (copy-on-write).
+/// [`VMFileMappingPermission::Shared`] mappings map the file pages writable,
+/// so that changes are immediately visible in the file and in all other
+/// shared mappings of it. There is nothing to write back.
 #[derive(Debug)]
 pub struct VMFileMapping {
     /// The file that this mapping relates to
@@ -80,7 +79,7 @@ pub struct VMFileMapping {
     /// A vec containing references to mapped pages within the file
     pages: Vec<Option<PageRef>>,
 
-    /// A copy of the file pages for mappings with Write permission
+    /// Private copies of the file pages which were written to
     write_copy: Option<VMWriteFileMapping>,
 }
 
@@ -123,10 +122,10 @@ impl VMFileMapping {
         for page_index in 0..count {
             pages.push(file.mapping(offset + page_index * PAGE_SIZE));
         }
-        // For ranges with write access we need to take a copy of the ram pages
-        // to allow them to be written to without modifying the contents of the
-        // file itself and also to prevent pointer aliasing with any other
-        // FileHandles that may be open on the same file.
+        // Pages of writable ranges are copied on the first write to them.
+        // This allows them to be written to without modifying the contents
+        // of the file itself and also prevents pointer aliasing with any
+        // other FileHandles that may be open on the same file.
         let write_copy = if permission == VMFileMappingPermission::Write {
             Some(VMWriteFileMapping(RawAllocMapping::new(size)))
         } else {
@@ -143,42 +142,6 @@ impl VMFileMapping {
     }
 }
 
-#[cfg(not(test))]
-fn copy_page(
-    vmr: &VMR,
-    file: &FileHandle,
-    offset: usize,
-    paddr_dst: PhysAddr,
-    page_size: PageSize,
-) -> Result<(), SvsmError> {
-    let page_size = usize::from(page_size);
-    let temp_map = VMPhysMem::new(paddr_dst, page_size, true);
-    let vaddr_new_page = vmr.insert(Arc::new(Mapping::new(temp_map)))?;
-    let slice = unsafe { from_raw_parts_mut(vaddr_new_page.as_mut_ptr::<u8>(), page_size) };
-    file.seek(offset);
-    file.read(slice)?;
-    vmr.remove(vaddr_new_page)?;
-    Ok(())
-}
-
-#[cfg(test)]
-fn copy_page(
-    _vmr: &VMR,
-    file: &FileHandle,
-    offset: usize,
-    paddr_dst: PhysAddr,
-    page_size: PageSize,
-) -> Result<(), SvsmError> {
-    let page_size = usize::from(page_size);
-    // In the test environment the physical address is actually the virtual
-    // address. We can take advantage of this to copy the file contents into the
-    // mock physical address without worrying about VMRs and page tables.
-    let slice = unsafe { from_raw_parts_mut(paddr_dst.bits() as *mut u8, page_size) };
-    file.seek(offset);
-    file.read(slice)?;
-    Ok(())
-}
-
 impl VirtualMapping for VMFileMapping {
     fn mapping_size(&self) -> usize {
         self.size
@@ -212,17 +175,17 @@ impl VirtualMapping for VMFileMapping {
                 }
             }
             VMFileMappingPermission::Execute => PTEntryFlags::task_exec(),
+            VMFileMappingPermission::Shared => PTEntryFlags::task_data(),
         }
     }
 
     fn handle_page_fault(
         &mut self,
-        vmr: &VMR,
+        _vmr: &VMR,
         offset: usize,
         write: bool,
     ) -> Result<VMPageFaultResolution, SvsmError> {
-        let page_size = self.page_size();
-        let page_size_bytes = usize::from(page_size);
+        let page_size_bytes = usize::from(self.page_size());
 
         if !write {
             return Err(SvsmError::Mem);
@@ -234,12 +197,20 @@ impl VirtualMapping for VMFileMapping {
 
         // This is a writeable region with copy-on-write access. The
         // page fault will have occurred because the page has not yet
-        // been allocated. Allocate a page and copy the readonly source
-        // page into the new writeable page.
+        // been copied. Allocate a page and copy the readonly source
+        // page into the new writeable page. Newly allocated pages are
+        // zeroed, which covers file pages not backed by memory.
         let offset_aligned = offset & !(page_size_bytes - 1);
-        write_copy.get_alloc_mut().alloc_page(offset_aligned)?;
+        let page_index = offset_aligned >> PAGE_SHIFT;
+        let alloc = write_copy.get_alloc_mut();
+        if !alloc.present(offset_aligned) {
+            alloc.alloc_page(offset_aligned)?;
+            let new_page = alloc.page_mut(offset_aligned).ok_or(SvsmError::Mem)?;
+            if let Some(file_page) = self.pages.get(page_index).and_then(|p| p.as_ref()) {
+                new_page.as_mut().copy_from_slice(file_page.as_ref());
+            }
+        }
         let paddr_new_page = write_copy.map(offset_aligned).ok_or(SvsmError::Mem)?;
-        copy_page(vmr, &self.file, offset_aligned, paddr_new_page, page_size)?;
         Ok(VMPageFaultResolution {
             paddr: paddr_new_page,
             flags: PTEntryFlags::task_data(),
@@ -566,4 +537,78 @@ mod tests {
         );
         unlink(name).unwrap();
     }
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_handle_page_fault_non_zero_offset() {
+        let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+        let _test_fs = TestFileSystemGuard::setup();
+
+        let (fh, name) = create_16k_test_file();
+        let mut vm =
+            VMFileMapping::new(fh, PAGE_SIZE, PAGE_SIZE * 2, VMFileMappingPermission::Write)
+                .expect("Failed to create new VMFileMapping");
+
+        let vmr = VMR::new(
+            VirtAddr::from(0usize),
+            VirtAddr::from(16usize * PAGE_SIZE),
+            PTEntryFlags::data(),
+        );
+        let res = vm
+            .handle_page_fault(&vmr, PAGE_SIZE, true)
+            .expect("handle_page_fault() failed");
+        // The second page of the mapping is the third page of the file
+        assert_eq!(unsafe { (res.paddr.bits() as *const u8).read() }, 2);
+
+        // The copy is private, so writes do not reach the file
+        unsafe { (res.paddr.bits() as *mut u8).write(0x42) };
+        let fh2 = open(name).unwrap();
+        let mut buf = [0u8; 1];
+        fh2.seek(PAGE_SIZE * 2);
+        fh2.read(&mut buf).expect("File read failed");
+        assert_eq!(buf[0], 2);
+        unlink(name).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(test_in_svsm, ignore = "FIXME")]
+    fn test_shared_mapping() {
+        let _test_mem = TestRootMem::setup(DEFAULT_TEST_MEMORY_SIZE);
+        let _test_fs = TestFileSystemGuard::setup();
+
+        let (fh, name) = create_16k_test_file();
+        let fh2 = open(name).unwrap();
+        let vm = VMFileMapping::new(fh, 0, fh2.size(), VMFileMappingPermission::Shared)
+            .expect("Failed to create new VMFileMapping");
+        let vm2 = VMFileMapping::new(
+            fh2.reopen(),
+            PAGE_SIZE,
+            PAGE_SIZE,
+            VMFileMappingPermission::Shared,
+        )
+        .expect("Failed to create new VMFileMapping");
+
+        // The file pages themselves are mapped writable
+        assert!(vm.pt_flags(PAGE_SIZE).contains(PTEntryFlags::WRITABLE));
+        let paddr = vm.map(PAGE_SIZE).expect("Failed to map file page");
+        assert_eq!(paddr, fh2.mapping(PAGE_SIZE).unwrap().phys_addr());
+        assert_eq!(vm2.map(0), Some(paddr));
+
+        // Changes are visible in the file right away
+        unsafe { (paddr.bits() as *mut u8).write(0x42) };
+        let mut buf = [0u8; 1];
+        fh2.seek(PAGE_SIZE);
+        fh2.read(&mut buf).expect("File read failed");
+        assert_eq!(buf[0], 0x42);
+
+        // Writes to the file show up in the mapping
+        fh2.seek(PAGE_SIZE + 1);
+        fh2.write(&[0x43]).expect("File write failed");
+        assert_eq!(unsafe { (paddr.bits() as *const u8).add(1).read() }, 0x43);
+        assert_eq!(fh2.size(), PAGE_SIZE * 4);
+
+        drop(vm);
+        drop(vm2);
+        unlink(name).unwrap();
+    }
 }
@@ -98,6 +98,38 @@ impl RawAllocMapping {
             .and_then(|r| r.as_ref().map(|r| r.phys_addr()))
     }
 
+    /// Request mutable access to the backing page for a given offset
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Byte offset into the memory mapping
+    ///
+    /// # Returns
+    ///
+    /// Mutable reference to the page containing the offset, if it has been
+    /// allocated.
+    pub fn page_mut(&mut self, offset: usize) -> Option<&mut PageRef> {
+        self.pages
+            .get_mut(offset >> PAGE_SHIFT)
+            .and_then(|r| r.as_mut())
+    }
+
+    /// Removes the backing page for a given offset from the mapping
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Byte offset into the memory mapping
+    ///
+    /// # Returns
+    ///
+    /// The page containing the offset, if it was allocated. It is freed when
+    /// the returned [`PageRef`] is dropped.
+    pub fn take_page(&mut self, offset: usize) -> Option<PageRef> {
+        self.pages
+            .get_mut(offset >> PAGE_SHIFT)
+            .and_then(|r| r.take())
+    }
+
     /// Unmap call-back - currently nothing to do in this function
     ///
     /// # Arguments
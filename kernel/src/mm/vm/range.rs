@@ -346,6 +346,60 @@ impl VMR {
         self.insert_aligned(mapping, align)
     }
 
+    /// Inserts [`VMM`] into the virtual memory region at the lowest
+    /// address which leaves at least `guard` bytes of unused address space
+    /// before and after the mapping. Accesses running over the ends of the
+    /// mapping hit the guard area and fault.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping` - `Rc` pointer to the VMM to insert
+    /// * `guard` - Size of the guard area on each side, rounded up to
+    ///   PAGE_SIZE
+    ///
+    /// # Returns
+    ///
+    /// Base address where the [`VMM`] was inserted on success or SvsmError::Mem on error
+    pub fn insert_guarded(
+        &self,
+        mapping: Arc<Mapping>,
+        guard: usize,
+    ) -> Result<VirtAddr, SvsmError> {
+        let size = mapping.get().mapping_size() >> PAGE_SHIFT;
+        let guard = align_up(guard, PAGE_SIZE) >> PAGE_SHIFT;
+        let needed = guard
+            .checked_mul(2)
+            .and_then(|g| g.checked_add(size))
+            .ok_or(SvsmError::Mem)?;
+
+        if size == 0 {
+            return Err(SvsmError::Mem);
+        }
+
+        let mut tree = self.tree.lock_write();
+        let mut cursor = tree.front_mut();
+        let mut start = self.start_pfn;
+        let mut end = self.end_pfn;
+
+        while let Some(node) = cursor.get() {
+            let (node_start, node_end) = node.range_pfn();
+            if node_start - start >= needed {
+                end = node_start;
+                break;
+            }
+
+            start = node_end;
+            cursor.move_next();
+        }
+
+        if end - start >= needed {
+            self.do_insert(mapping, start + guard, &mut cursor)?;
+            Ok(VirtAddr::from((start + guard) << PAGE_SHIFT))
+        } else {
+            Err(SvsmError::Mem)
+        }
+    }
+
     /// Removes the mapping from a given base address from the RBTree
     ///
     /// # Arguments
@@ -367,6 +421,77 @@ impl VMR {
         cursor.remove().ok_or(SvsmError::Mem)
     }
 
+    /// Releases the backing pages of a mapping which supports it, like
+    /// [`VMDemandZero`](super::VMDemandZero). The pages are unmapped and
+    /// returned to the allocator, the next access to them faults again.
+    ///
+    /// # Arguments
+    ///
+    /// * `vaddr` - Page-aligned start address of the range to release
+    /// * `size` - Size of the range in bytes, a multiple of PAGE_SIZE. The
+    ///   range must be inside a single mapping.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, `Err(SvsmError::Mem)` if the range is invalid
+    /// or the mapping does not support releasing pages.
+    pub fn discard(&self, vaddr: VirtAddr, size: usize) -> Result<(), SvsmError> {
+        if !vaddr.is_page_aligned() || (size & (PAGE_SIZE - 1)) != 0 {
+            return Err(SvsmError::Mem);
+        }
+
+        let (mapping, start, end) = {
+            let tree = self.tree.lock_read();
+            let cursor = tree.upper_bound(Bound::Included(&vaddr.pfn()));
+            let node = cursor.get().ok_or(SvsmError::Mem)?;
+            let (start, end) = node.range();
+            (node.get_mapping_clone(), start, end)
+        };
+        let range_end = vaddr.checked_add(size).ok_or(SvsmError::Mem)?;
+        if vaddr < start || range_end > end {
+            return Err(SvsmError::Mem);
+        }
+
+        // Holding the mapping lock keeps page faults from populating the
+        // range again until the pages are unmapped.
+        let mut guard = mapping.get_mut();
+        if !matches!(guard.page_size(), PageSize::Regular) {
+            return Err(SvsmError::Mem);
+        }
+
+        let (rstart, _) = self.virt_range();
+        let mut pages = Vec::new();
+        let mut pgtbl_parts = self.pgtbl_parts.lock_write();
+        let mut addr = vaddr;
+        while addr < range_end {
+            if let Some(page) = guard.discard(addr - start)? {
+                let idx = PageTable::index::<3>(VirtAddr::from(addr - rstart));
+                pgtbl_parts[idx].unmap_4k(addr);
+                pages.push(page);
+            }
+            addr = addr + PAGE_SIZE;
+        }
+        drop(pgtbl_parts);
+
+        if !pages.is_empty() {
+            flush_tlb_global_sync();
+        }
+        // The pages are freed when `pages` goes out of scope
+        Ok(())
+    }
+
+    /// Removes all mappings from the region and frees the [`PageTablePart`]s
+    /// backing it. The region needs to be initialized again before it can be
+    /// used.
+    ///
+    /// No TLB flush is done, so the region must not be populated in any
+    /// page-table which is still in use.
+    pub fn clear(&self) {
+        let mut tree = self.tree.lock_write();
+        tree.clear();
+        self.pgtbl_parts.lock_write().clear();
+    }
+
     /// Dump all [`VMM`] mappings in the RBTree. This function is included for
     /// debugging purposes. And should not be called in production code.
     pub fn dump_ranges(&self) {
@@ -406,7 +531,7 @@ impl VMR {
         let (pf_mapping, start) = {
             let tree = self.tree.lock_read();
             let addr = vaddr.pfn();
-            let cursor = tree.find(&addr);
+            let cursor = tree.upper_bound(Bound::Included(&addr));
             let node = cursor.get().ok_or(SvsmError::Mem)?;
             let (start, end) = node.range();
             if vaddr < start || vaddr >= end {
@@ -423,17 +548,14 @@ impl VMR {
         let vaddr = vaddr.page_align();
         let page_size = pf_mapping.get().page_size();
         let shared = pf_mapping.get().shared();
+        let flags = self.pt_flags | resolution.flags;
         let mut pgtbl_parts = self.pgtbl_parts.lock_write();
 
         let (rstart, _) = self.virt_range();
         let idx = PageTable::index::<3>(VirtAddr::from(vaddr - rstart));
         match page_size {
-            PageSize::Regular => {
-                pgtbl_parts[idx].map_4k(vaddr, resolution.paddr, resolution.flags, shared)?
-            }
-            PageSize::Huge => {
-                pgtbl_parts[idx].map_2m(vaddr, resolution.paddr, resolution.flags, shared)?
-            }
+            PageSize::Regular => pgtbl_parts[idx].map_4k(vaddr, resolution.paddr, flags, shared)?,
+            PageSize::Huge => pgtbl_parts[idx].map_2m(vaddr, resolution.paddr, flags, shared)?,
         }
         Ok(())
     }
@@ -463,3 +585,37 @@ impl Drop for VMRMapping<'_> {
             .expect("Error removing VRMapping virtual memory range");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::vm::VMReserved;
+
+    #[test]
+    fn test_insert_guarded() {
+        let vmr = VMR::new(
+            VirtAddr::from(0usize),
+            VirtAddr::from(8usize * PAGE_SIZE),
+            PTEntryFlags::data(),
+        );
+        let reserved = |pages: usize| Arc::new(VMReserved::new_mapping(pages * PAGE_SIZE));
+
+        // Every mapping is preceded and followed by a guard area
+        let first = vmr
+            .insert_guarded(reserved(2), PAGE_SIZE)
+            .expect("insert_guarded() failed");
+        assert_eq!(first, VirtAddr::from(PAGE_SIZE));
+        let second = vmr
+            .insert_guarded(reserved(1), 1)
+            .expect("insert_guarded() failed");
+        assert_eq!(second, VirtAddr::from(4usize * PAGE_SIZE));
+
+        // The remaining 3 pages are not enough for 2 pages plus guards
+        assert!(vmr.insert_guarded(reserved(2), PAGE_SIZE).is_err());
+        assert!(vmr.insert_guarded(reserved(0), PAGE_SIZE).is_err());
+        let third = vmr
+            .insert_guarded(reserved(1), PAGE_SIZE)
+            .expect("insert_guarded() failed");
+        assert_eq!(third, VirtAddr::from(6usize * PAGE_SIZE));
+    }
+}
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Backing store for heap allocations which are too big for the page
+//! allocator. Such allocations are served from [`VMalloc`] mappings in a
+//! dedicated [`VMR`] which is shared by all page-tables. Every mapping is
+//! surrounded by unmapped guard pages, so that overflows fault instead of
+//! corrupting neighbouring allocations.
+
+use crate::address::VirtAddr;
+use crate::error::SvsmError;
+use crate::locking::RWLock;
+use crate::mm::pagetable::{get_init_pgtable_locked, PTEntryFlags};
+use crate::mm::vm::{VMalloc, VMR};
+use crate::mm::{SVSM_HEAP_VMALLOC_BASE, SVSM_HEAP_VMALLOC_END};
+use crate::types::PAGE_SIZE;
+use crate::utils::align_up;
+
+extern crate alloc;
+use alloc::sync::Arc;
+
+/// Size of the unmapped area on each side of a large heap allocation
+const HEAP_GUARD_SIZE: usize = PAGE_SIZE;
+
+static HEAP_VMR: RWLock<Option<VMR>> = RWLock::new(None);
+
+/// Sets up the region for large heap allocations and maps it into the
+/// initial page-table. Must be called before any other page-table is
+/// derived from the initial one, as those share the mappings of the region.
+///
+/// # Returns
+///
+/// `Ok(())` on success, `Err(SvsmError::Mem)` if allocating the page-tables
+/// of the region failed.
+pub fn vmalloc_heap_init() -> Result<(), SvsmError> {
+    let mut vmr = VMR::new(
+        SVSM_HEAP_VMALLOC_BASE,
+        SVSM_HEAP_VMALLOC_END,
+        PTEntryFlags::GLOBAL,
+    );
+    vmr.initialize()?;
+    vmr.populate(&mut get_init_pgtable_locked());
+
+    let mut heap_vmr = HEAP_VMR.lock_write();
+    assert!(heap_vmr.is_none());
+    *heap_vmr = Some(vmr);
+    Ok(())
+}
+
+/// Returns whether `vaddr` belongs to the region for large heap allocations.
+pub fn is_vmalloc_heap_addr(vaddr: VirtAddr) -> bool {
+    (SVSM_HEAP_VMALLOC_BASE..SVSM_HEAP_VMALLOC_END).contains(&vaddr)
+}
+
+/// Allocates `size` bytes of virtually contiguous memory. The start address
+/// is page aligned.
+///
+/// # Returns
+///
+/// The start address of the allocation on success, `Err(SvsmError::Mem)`
+/// if memory is exhausted or the region is not set up yet.
+pub fn vmalloc_heap_alloc(size: usize) -> Result<VirtAddr, SvsmError> {
+    let size = align_up(size, PAGE_SIZE);
+    let mapping = Arc::new(VMalloc::new_mapping(size)?);
+    let heap_vmr = HEAP_VMR.lock_read();
+    let vmr = heap_vmr.as_ref().ok_or(SvsmError::Mem)?;
+    vmr.insert_guarded(mapping, HEAP_GUARD_SIZE)
+}
+
+/// Unmaps a large heap allocation and frees its backing pages.
+///
+/// # Panics
+///
+/// Panics if `vaddr` is not the start address of an allocation returned by
+/// [`vmalloc_heap_alloc()`].
+pub fn vmalloc_heap_free(vaddr: VirtAddr) {
+    let vmm = {
+        let heap_vmr = HEAP_VMR.lock_read();
+        heap_vmr
+            .as_ref()
+            .and_then(|vmr| vmr.remove(vaddr).ok())
+            .unwrap_or_else(|| panic!("Freeing unknown heap memory at {:#018x}", vaddr))
+    };
+    // The backing pages and the mapping meta-data are freed after the lock
+    // is dropped, as this can recurse into the heap.
+    drop(vmm);
+}
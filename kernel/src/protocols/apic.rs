@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::cpu::cpuid::cpuid_table;
+use crate::cpu::ghcb::current_ghcb;
+use crate::cpu::percpu::this_cpu;
+use crate::protocols::errors::SvsmReqError;
+use crate::protocols::RequestParams;
+use crate::sev::status::SEVStatusFlags;
+use crate::types::GUEST_VMPL;
+
+const SVSM_REQ_APIC_QUERY_FEATURES: u32 = 0;
+const SVSM_REQ_APIC_CONFIGURE: u32 = 1;
+const SVSM_REQ_APIC_READ_REGISTER: u32 = 2;
+const SVSM_REQ_APIC_WRITE_REGISTER: u32 = 3;
+
+pub const APIC_PROTOCOL: u32 = 3;
+pub const APIC_PROTOCOL_VERSION_MIN: u32 = 1;
+pub const APIC_PROTOCOL_VERSION_MAX: u32 = 1;
+
+/// Feature bit reported when the guest APIC can be emulated
+const APIC_FEATURE_EMULATION: u64 = 1 << 0;
+
+// Values of RCX for SVSM_REQ_APIC_CONFIGURE
+const APIC_CONFIGURE_DISABLE: u64 = 0;
+const APIC_CONFIGURE_ENABLE: u64 = 1;
+
+/// Alternate injection support in CPUID Fn8000_001F_EAX
+const CPUID_ALT_INJ: u32 = 1 << 13;
+
+/// APIC emulation needs alternate injection for the guest VMSA, so that the
+/// hypervisor no longer injects interrupts. Returns whether the platform
+/// supports it.
+fn apic_emulation_possible() -> bool {
+    // Interrupts for the guest reach the SVSM through the #HV doorbell page
+    this_cpu().hv_doorbell().is_some()
+        && cpuid_table(0x8000001f).is_some_and(|res| res.eax & CPUID_ALT_INJ != 0)
+}
+
+/// Switches alternate injection for the guest VMSA of the current CPU on or
+/// off and registers the VMSA with the new features at the hypervisor. The
+/// previous features are restored when the registration fails.
+fn set_alternate_injection(enable: bool) -> Result<(), SvsmReqError> {
+    let cpu = this_cpu();
+    let mut vmsa_ref = cpu.guest_vmsa_ref();
+    let vmsa_pa = vmsa_ref
+        .vmsa_phys()
+        .ok_or_else(SvsmReqError::invalid_request)?;
+    let vmsa = vmsa_ref.vmsa();
+
+    let old_features = vmsa.sev_features;
+    let alt_inj = SEVStatusFlags::ALT_INJ.as_sev_features();
+    if enable {
+        vmsa.sev_features |= alt_inj;
+    } else {
+        vmsa.sev_features &= !alt_inj;
+    }
+
+    current_ghcb()
+        .register_guest_vmsa(
+            vmsa_pa,
+            cpu.get_apic_id().into(),
+            GUEST_VMPL as u64,
+            vmsa.sev_features,
+        )
+        .inspect_err(|_| vmsa.sev_features = old_features)?;
+    Ok(())
+}
+
+fn apic_query_features(params: &mut RequestParams) -> Result<(), SvsmReqError> {
+    params.rcx = if apic_emulation_possible() {
+        APIC_FEATURE_EMULATION
+    } else {
+        0
+    };
+    Ok(())
+}
+
+fn apic_configure(params: &RequestParams) -> Result<(), SvsmReqError> {
+    let cpu = this_cpu();
+    match params.rcx {
+        APIC_CONFIGURE_DISABLE => {
+            // Keep emulating the APIC until the hypervisor injects
+            // interrupts into the guest again
+            set_alternate_injection(false)?;
+            cpu.shared.guest_apic().disable();
+        }
+        APIC_CONFIGURE_ENABLE => {
+            if !apic_emulation_possible() {
+                return Err(SvsmReqError::invalid_request());
+            }
+            set_alternate_injection(true)?;
+            cpu.shared.guest_apic().enable();
+        }
+        _ => return Err(SvsmReqError::invalid_parameter()),
+    }
+    Ok(())
+}
+
+fn apic_register(params: &RequestParams) -> Result<u32, SvsmReqError> {
+    u32::try_from(params.rcx).map_err(|_| SvsmReqError::invalid_parameter())
+}
+
+fn apic_read_register(params: &mut RequestParams) -> Result<(), SvsmReqError> {
+    let reg = apic_register(params)?;
+    let cpu = this_cpu();
+    let mut vmsa_ref = cpu.guest_vmsa_ref();
+    params.rdx = cpu
+        .shared
+        .guest_apic()
+        .read_register(reg, vmsa_ref.vmsa())?;
+    Ok(())
+}
+
+fn apic_write_register(params: &RequestParams) -> Result<(), SvsmReqError> {
+    let reg = apic_register(params)?;
+    let cpu = this_cpu();
+    let mut vmsa_ref = cpu.guest_vmsa_ref();
+    cpu.shared
+        .guest_apic()
+        .write_register(reg, params.rdx, vmsa_ref.vmsa())?;
+    Ok(())
+}
+
+pub fn apic_protocol_request(request: u32, params: &mut RequestParams) -> Result<(), SvsmReqError> {
+    match request {
+        SVSM_REQ_APIC_QUERY_FEATURES => apic_query_features(params),
+        SVSM_REQ_APIC_CONFIGURE => apic_configure(params),
+        SVSM_REQ_APIC_READ_REGISTER => apic_read_register(params),
+        SVSM_REQ_APIC_WRITE_REGISTER => apic_write_register(params),
+        _ => Err(SvsmReqError::unsupported_call()),
+    }
+}
@@ -4,6 +4,7 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
+use crate::cpu::guest_apic::ApicError;
 use crate::error::SvsmError;
 
 #[derive(Debug, Clone, Copy)]
@@ -75,6 +76,11 @@ impl From<SvsmError> for SvsmReqError {
             // to the guest as protocol-specific errors.
             SvsmError::SevSnp(e) => Self::protocol(e.ret()),
             SvsmError::InvalidAddress => Self::invalid_address(),
+            // Guest virtual addresses which can not be accessed
+            SvsmError::GuestPageTable(_) => Self::invalid_address(),
+            // Errors from emulating the guest APIC
+            SvsmError::Apic(ApicError::Disabled) => Self::invalid_request(),
+            SvsmError::Apic(_) => Self::invalid_parameter(),
             // Use a fatal error for now
             _ => Self::FatalError(err),
         }
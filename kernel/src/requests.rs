@@ -9,9 +9,12 @@ use crate::cpu::ghcb::current_ghcb;
 use crate::cpu::percpu::{process_requests, this_cpu, wait_for_requests};
 use crate::error::SvsmError;
 use crate::mm::GuestPtr;
+use crate::protocols::apic::{apic_protocol_request, APIC_PROTOCOL};
 use crate::protocols::core::core_protocol_request;
 use crate::protocols::errors::{SvsmReqError, SvsmResultCode};
 use crate::protocols::RequestParams;
+use crate::sev::hv_doorbell::process_hv_events;
+use crate::task::poll_timers;
 use crate::types::GUEST_VMPL;
 use crate::utils::halt;
 use cpuarch::vmsa::GuestVMExit;
@@ -60,6 +63,7 @@ fn request_loop_once(
 
     match protocol {
         0 => core_protocol_request(request, params).map(|_| true),
+        APIC_PROTOCOL => apic_protocol_request(request, params).map(|_| true),
         _ => Err(SvsmReqError::unsupported_protocol()),
     }
 }
@@ -79,17 +83,26 @@ fn check_requests() -> Result<bool, SvsmReqError> {
 
 pub fn request_loop() {
     loop {
+        // Let tasks whose timers expired run before the guest is entered
+        // again. Without timer interrupts this is where timers expire.
+        poll_timers();
+
+        // Interrupts for the guest are signalled through the #HV doorbell
+        // page when the guest uses APIC emulation.
+        process_hv_events();
+
         // Determine whether the guest is runnable.  If not, halt and wait for
         // the guest to execute.  When halting, assume that the hypervisor
         // will schedule the guest VMPL on its own.
         if update_mappings().is_ok() {
-            // Make VMSA runnable again by setting EFER.SVME.  This requires a
-            // separate scope so the CPU reference does not outlive the use of
-            // the VMSA reference.
+            // Inject pending guest interrupts and make VMSA runnable again by
+            // setting EFER.SVME.  This requires a separate scope so the CPU
+            // reference does not outlive the use of the VMSA reference.
             {
                 let cpu = this_cpu();
                 let mut vmsa_ref = cpu.guest_vmsa_ref();
                 let vmsa = vmsa_ref.vmsa();
+                cpu.shared.guest_apic().present_interrupts(vmsa);
                 vmsa.enable();
             }
 
@@ -119,6 +132,9 @@ pub fn request_loop() {
             // Clear EFER.SVME in guest VMSA
             vmsa.disable();
 
+            // Account for guest interrupts delivered while the guest ran
+            cpu.shared.guest_apic().check_delivered(vmsa);
+
             let rax = vmsa.rax;
 
             ((rax >> 32) as u32, (rax & 0xffff_ffff) as u32)
@@ -122,10 +122,12 @@ enum GHCBExitCode {}
 
 impl GHCBExitCode {
     pub const IOIO: u64 = 0x7b;
+    pub const MSR: u64 = 0x7c;
     pub const SNP_PSC: u64 = 0x8000_0010;
     pub const GUEST_REQUEST: u64 = 0x8000_0011;
     pub const GUEST_EXT_REQUEST: u64 = 0x8000_0012;
     pub const AP_CREATE: u64 = 0x80000013;
+    pub const HV_DOORBELL_PAGE: u64 = 0x80000014;
     pub const RUN_VMPL: u64 = 0x80000018;
 }
 
@@ -349,6 +351,27 @@ impl GHCB {
         Ok(())
     }
 
+    pub fn rdmsr(&mut self, msr: u32) -> Result<u64, SvsmError> {
+        self.clear();
+
+        self.set_rcx(msr as u64);
+        self.vmgexit(GHCBExitCode::MSR, 0, 0)?;
+        if !self.is_valid(OFF_RAX) || !self.is_valid(OFF_RDX) {
+            return Err(GhcbError::VmgexitInvalid.into());
+        }
+        Ok((self.rax & 0xffff_ffff) | (self.rdx << 32))
+    }
+
+    pub fn wrmsr(&mut self, msr: u32, value: u64) -> Result<(), SvsmError> {
+        self.clear();
+
+        self.set_rcx(msr as u64);
+        self.set_rax(value & 0xffff_ffff);
+        self.set_rdx(value >> 32);
+        self.vmgexit(GHCBExitCode::MSR, 1, 0)?;
+        Ok(())
+    }
+
     fn write_buffer<T>(&mut self, data: &T, offset: isize) -> Result<(), GhcbError>
     where
         T: Sized,
@@ -494,6 +517,13 @@ impl GHCB {
         Ok(())
     }
 
+    pub fn register_hv_doorbell(&mut self, paddr: PhysAddr) -> Result<(), SvsmError> {
+        self.clear();
+        // Sub-function 1 sets the doorbell page
+        self.vmgexit(GHCBExitCode::HV_DOORBELL_PAGE, 1, u64::from(paddr))?;
+        Ok(())
+    }
+
     pub fn guest_request(
         &mut self,
         req_page: VirtAddr,
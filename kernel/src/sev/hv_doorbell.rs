@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! #HV doorbell page.
+//!
+//! With restricted injection the hypervisor can not inject interrupts into
+//! the SVSM. It writes the pending vector to the #HV doorbell page of the CPU
+//! instead and raises #HV. When the guest VMSA uses alternate injection, the
+//! interrupts of the guest arrive the same way and are forwarded to its
+//! emulated APIC.
+
+use crate::cpu::apic::apic_eoi;
+use crate::cpu::idt::common::IPI_VECTOR;
+use crate::cpu::ipi::handle_ipi;
+use crate::cpu::percpu::this_cpu;
+use crate::cpu::IrqGuard;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// NMI pending bit in the flags byte of the doorbell page
+const HV_DOORBELL_NMI_PENDING: u8 = 1 << 6;
+/// #MC pending bit in the flags byte of the doorbell page
+const HV_DOORBELL_MC_PENDING: u8 = 1 << 7;
+
+/// Layout of the #HV doorbell page as defined by the GHCB specification.
+/// Only the first 64 bytes are used.
+#[repr(C)]
+#[derive(Debug)]
+pub struct HVDoorbell {
+    /// Pending interrupt vector, zero if none
+    vector: AtomicU8,
+    /// NMI and #MC pending bits
+    flags: AtomicU8,
+    /// Set by the hypervisor when the pending vector needs no EOI
+    no_eoi_required: AtomicU8,
+    reserved: [u8; 61],
+}
+
+impl HVDoorbell {
+    /// Takes the pending vector out of the doorbell page.
+    fn take_vector(&self) -> Option<u8> {
+        match self.vector.swap(0, Ordering::AcqRel) {
+            0 => None,
+            vector => Some(vector),
+        }
+    }
+
+    /// Returns whether the hypervisor expects an EOI for the vector taken
+    /// last, and resets the indication.
+    fn eoi_required(&self) -> bool {
+        self.no_eoi_required.swap(0, Ordering::AcqRel) & 1 == 0
+    }
+
+    /// Handles the events pending in the doorbell page. Interrupts for the
+    /// SVSM are handled right away, all others are posted to the emulated
+    /// APIC of the guest. Must be called from task context, as the GHCB is
+    /// needed to signal EOIs.
+    pub fn process_events(&self) {
+        // NMIs have no source the SVSM cares about
+        let flags = self
+            .flags
+            .fetch_and(!HV_DOORBELL_NMI_PENDING, Ordering::AcqRel);
+        if (flags & HV_DOORBELL_MC_PENDING) != 0 {
+            panic!("#MC signalled through the #HV doorbell page");
+        }
+
+        while let Some(vector) = self.take_vector() {
+            if usize::from(vector) == IPI_VECTOR {
+                let _irq_guard = IrqGuard::new();
+                handle_ipi();
+            } else {
+                this_cpu().shared.guest_apic().post_external(vector);
+            }
+
+            if self.eoi_required() {
+                apic_eoi();
+            }
+        }
+    }
+}
+
+/// Handles the events pending in the #HV doorbell page of the current CPU,
+/// if it has one.
+pub fn process_hv_events() {
+    let cpu = this_cpu();
+    if let Some(doorbell) = cpu.hv_doorbell() {
+        doorbell.process_events();
+    }
+}
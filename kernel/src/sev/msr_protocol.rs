@@ -5,6 +5,7 @@
 // Author: Joerg Roedel <jroedel@suse.de>
 
 use crate::address::{Address, PhysAddr};
+use crate::cpu::irq_state::IrqGuard;
 use crate::cpu::msr::{read_msr, write_msr, SEV_GHCB};
 use crate::error::SvsmError;
 use crate::utils::halt;
@@ -71,9 +72,11 @@ static GHCB_HV_FEATURES: ImmutAfterInitCell<GHCBHvFeatures> = ImmutAfterInitCell
 /// Check that we support the hypervisor's advertised GHCB versions.
 pub fn verify_ghcb_version() {
     // Request SEV information.
+    let irq_guard = IrqGuard::new();
     write_msr(SEV_GHCB, GHCBMsr::SEV_INFO_REQ);
     raw_vmgexit();
     let sev_info = read_msr(SEV_GHCB);
+    drop(irq_guard);
 
     // Parse the results.
 
@@ -99,9 +102,11 @@ pub fn hypervisor_ghcb_features() -> GHCBHvFeatures {
 }
 
 pub fn init_hypervisor_ghcb_features() -> Result<(), GhcbMsrError> {
+    let irq_guard = IrqGuard::new();
     write_msr(SEV_GHCB, GHCBMsr::SNP_HV_FEATURES_REQ);
     raw_vmgexit();
     let result = read_msr(SEV_GHCB);
+    drop(irq_guard);
     if (result & 0xFFF) == GHCBMsr::SNP_HV_FEATURES_RESP {
         let features = GHCBHvFeatures::from_bits_truncate(result >> 12);
 
@@ -133,9 +138,11 @@ pub fn register_ghcb_gpa_msr(addr: PhysAddr) -> Result<(), GhcbMsrError> {
     let mut info = addr.bits() as u64;
 
     info |= GHCBMsr::SNP_REG_GHCB_GPA_REQ;
+    let irq_guard = IrqGuard::new();
     write_msr(SEV_GHCB, info);
     raw_vmgexit();
     info = read_msr(SEV_GHCB);
+    drop(irq_guard);
 
     if (info & 0xfff) != GHCBMsr::SNP_REG_GHCB_GPA_RESP {
         return Err(GhcbMsrError::InfoMismatch);
@@ -158,9 +165,11 @@ fn set_page_valid_status_msr(addr: PhysAddr, valid: bool) -> Result<(), GhcbMsrE
     }
 
     info |= GHCBMsr::SNP_STATE_CHANGE_REQ;
+    let irq_guard = IrqGuard::new();
     write_msr(SEV_GHCB, info);
     raw_vmgexit();
     let response = read_msr(SEV_GHCB);
+    drop(irq_guard);
 
     if (response & 0xfff) != GHCBMsr::SNP_STATE_CHANGE_RESP {
         return Err(GhcbMsrError::InfoMismatch);
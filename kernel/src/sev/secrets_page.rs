@@ -101,6 +101,12 @@ impl SecretsPage {
     pub fn clear_vmpck(&mut self, idx: usize) {
         self.vmpck[idx].iter_mut().for_each(|e| *e = 0);
     }
+
+    /// Returns the TSC scaling factor used with Secure TSC, in units of
+    /// 1/100000 of the nominal TSC frequency.
+    pub fn tsc_factor(&self) -> u32 {
+        self.tsc_factor
+    }
 }
 
 static SECRETS_PAGE: RWLock<SecretsPage> = RWLock::new(SecretsPage::new());
@@ -184,4 +184,9 @@ impl SEVStatusFlags {
         let sev_features = self.bits();
         sev_features >> 2
     }
+
+    /// Converts the SEV features field of a VMSA back to status flags.
+    pub fn from_sev_features(sev_features: u64) -> Self {
+        Self::from_bits_truncate(sev_features << 2)
+    }
 }
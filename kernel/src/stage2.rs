@@ -23,11 +23,11 @@ use svsm::cpu::gdt;
 use svsm::cpu::ghcb::current_ghcb;
 use svsm::cpu::idt::stage2::{early_idt_init, early_idt_init_no_ghcb};
 use svsm::cpu::percpu::{this_cpu_mut, PerCpu};
+use svsm::cpu::rdrand::rdrand64;
 use svsm::elf;
 use svsm::fw_cfg::FwCfg;
 use svsm::igvm_params::IgvmParams;
 use svsm::mm::alloc::{memory_info, print_memory_info, root_mem_init};
-use svsm::mm::init_kernel_mapping_info;
 use svsm::mm::pagetable::{
     get_init_pgtable_locked, paging_init_early, set_init_pgtable, PTEntryFlags, PageTable,
     PageTableRef,
@@ -35,12 +35,13 @@ use svsm::mm::pagetable::{
 use svsm::mm::validate::{
     init_valid_bitmap_alloc, valid_bitmap_addr, valid_bitmap_set_valid_range,
 };
+use svsm::mm::{init_kernel_mapping_info, SVSM_SHARED_STACK_BASE};
 use svsm::serial::SerialPort;
 use svsm::sev::ghcb::PageStateChangeOp;
 use svsm::sev::msr_protocol::verify_ghcb_version;
 use svsm::sev::{pvalidate_range, sev_status_init, sev_status_verify, PvalidateOp};
 use svsm::svsm_console::SVSMIOPort;
-use svsm::types::{PageSize, PAGE_SIZE};
+use svsm::types::{PageSize, PAGE_SIZE, PAGE_SIZE_2M};
 use svsm::utils::immut_after_init::ImmutAfterInitCell;
 use svsm::utils::{halt, MemoryRegion};
 
@@ -149,6 +150,35 @@ fn map_and_validate(config: &SvsmConfig<'_>, vregion: MemoryRegion<VirtAddr>, pa
     valid_bitmap_set_valid_range(paddr, paddr + vregion.len());
 }
 
+/// Pick a random virtual base address for a relocatable kernel image.
+///
+/// The kernel image, the IGVM parameters and the kernel heap are mapped
+/// contiguously starting at the base, so the base is chosen such that all of
+/// them fit below the shared stack area. The base is aligned to at least 2M to
+/// keep the kernel mappable with large pages. Falls back to the link address
+/// if no random number is available.
+fn randomize_kernel_base(range: &elf::Elf64AddrRange, align: u64, region_len: usize) -> u64 {
+    let link_base = range.vaddr_begin;
+    let align = align.max(PAGE_SIZE_2M as u64);
+    let span = range.len() + region_len as u64;
+
+    let slots = u64::from(SVSM_SHARED_STACK_BASE)
+        .checked_sub(link_base)
+        .and_then(|space| space.checked_sub(span))
+        .map_or(0, |space| space / align);
+    if slots == 0 {
+        return link_base;
+    }
+
+    match rdrand64() {
+        Some(rand) => link_base + (rand % (slots + 1)) * align,
+        None => {
+            log::warn!("RDRAND not available, kernel base will not be randomized");
+            link_base
+        }
+    }
+}
+
 // Launch info from stage1, usually at the bottom of the stack
 // The layout has to match the order in which the parts are pushed to the stack
 // in stage1/stage1.S
@@ -196,8 +226,15 @@ pub extern "C" fn stage2_main(launch_info: &Stage1LaunchInfo) {
         Err(e) => panic!("error reading kernel ELF: {}", e),
     };
 
+    // Relocatable kernels are loaded at a random virtual base, everything
+    // else at its link address.
     let kernel_vaddr_alloc_info = kernel_elf.image_load_vaddr_alloc_info();
-    let kernel_vaddr_alloc_base = kernel_vaddr_alloc_info.range.vaddr_begin;
+    let kernel_vaddr_alloc_base = match kernel_vaddr_alloc_info.align {
+        Some(align) => randomize_kernel_base(&kernel_vaddr_alloc_info.range, align, r.len()),
+        None => kernel_vaddr_alloc_info.range.vaddr_begin,
+    };
+    let kernel_slide =
+        kernel_vaddr_alloc_base.wrapping_sub(kernel_vaddr_alloc_info.range.vaddr_begin);
 
     // Determine the starting physical address at which the kernel should be
     // relocated.  If IGVM parameters are present, then this will follow the
@@ -325,6 +362,7 @@ pub extern "C" fn stage2_main(launch_info: &Stage1LaunchInfo) {
         kernel_region_phys_end: u64::from(kernel_region_phys_end),
         heap_area_phys_start: u64::from(heap_area_phys_start),
         kernel_region_virt_start: u64::from(loaded_kernel_virt_start),
+        kernel_slide,
         heap_area_virt_start: u64::from(heap_area_virt_start),
         kernel_elf_stage2_virt_start: u64::from(kernel_elf_start),
         kernel_elf_stage2_virt_end: u64::from(kernel_elf_end),
@@ -354,6 +392,7 @@ pub extern "C" fn stage2_main(launch_info: &Stage1LaunchInfo) {
         "  kernel_virtual_base   = {:#018x}",
         loaded_kernel_virt_start
     );
+    log::info!("  kernel_slide          = {:#018x}", kernel_slide);
 
     let kernel_entry = kernel_elf.get_entry(kernel_vaddr_alloc_base);
     let valid_bitmap = valid_bitmap_addr();
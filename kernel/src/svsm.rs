@@ -22,6 +22,7 @@ use svsm::console::{init_console, install_console_logger, WRITER};
 use svsm::cpu::control_regs::{cr0_init, cr4_init};
 use svsm::cpu::cpuid::{dump_cpuid_table, register_cpuid_table};
 use svsm::cpu::efer::efer_init;
+use svsm::cpu::fpu::fpu_init;
 use svsm::cpu::gdt;
 use svsm::cpu::ghcb::current_ghcb;
 use svsm::cpu::idt::svsm::{early_idt_init, idt_init};
@@ -37,18 +38,23 @@ use svsm::fw_cfg::FwCfg;
 use svsm::greq::driver::guest_request_driver_init;
 use svsm::igvm_params::IgvmParams;
 use svsm::kernel_region::new_kernel_region;
-use svsm::mm::alloc::{memory_info, print_memory_info, root_mem_init};
+use svsm::mm::alloc::{memory_info, print_memory_info, root_mem_init, slab_caches_init};
 use svsm::mm::memory::init_memory_map;
 use svsm::mm::pagetable::paging_init;
 use svsm::mm::virtualrange::virt_log_usage;
-use svsm::mm::{init_kernel_mapping_info, PerCPUPageMappingGuard};
+use svsm::mm::vmalloc_heap::vmalloc_heap_init;
+use svsm::mm::{init_kernel_mapping_info, init_kernel_slide, PerCPUPageMappingGuard};
 use svsm::requests::{request_loop, request_processing_main, update_mappings};
 use svsm::serial::SerialPort;
 use svsm::sev::utils::{rmp_adjust, RMPFlags};
 use svsm::sev::{init_hypervisor_ghcb_features, secrets_page, secrets_page_mut, sev_status_init};
 use svsm::svsm_console::SVSMIOPort;
 use svsm::svsm_paging::{init_page_table, invalidate_early_boot_memory};
-use svsm::task::{create_kernel_task, schedule_init, TASK_FLAG_SHARE_PT};
+use svsm::task::{
+    create_kernel_task, enable_preemption, preempt_init, schedule_init, TASK_FLAG_REALTIME,
+    TASK_FLAG_SHARE_PT,
+};
+use svsm::time::clock_init;
 use svsm::types::{PageSize, GUEST_VMPL, PAGE_SIZE};
 use svsm::utils::{halt, immut_after_init::ImmutAfterInitCell, zero_mem_region};
 
@@ -257,6 +263,7 @@ fn mapping_info_init(launch_info: &KernelLaunchInfo) {
         VirtAddr::from(launch_info.heap_area_virt_end()),
         PhysAddr::from(launch_info.heap_area_phys_start),
     );
+    init_kernel_slide(launch_info.kernel_slide as usize);
 }
 
 #[no_mangle]
@@ -302,6 +309,7 @@ pub extern "C" fn svsm_start(li: &KernelLaunchInfo, vb_addr: usize) {
     cr4_init();
     efer_init();
     sev_status_init();
+    fpu_init();
 
     memory_init(&launch_info);
     migrate_valid_bitmap().expect("Failed to migrate valid-bitmap");
@@ -321,6 +329,7 @@ pub extern "C" fn svsm_start(li: &KernelLaunchInfo, vb_addr: usize) {
 
     paging_init();
     init_page_table(&launch_info, &kernel_elf).expect("Could not initialize the page table");
+    vmalloc_heap_init().expect("Failed to initialize the large heap allocation region");
 
     // SAFETY: this PerCpu has just been allocated and no other CPUs have been
     // brought up, thus it cannot be aliased and we can get a mutable
@@ -341,10 +350,19 @@ pub extern "C" fn svsm_start(li: &KernelLaunchInfo, vb_addr: usize) {
         .expect("Failed to run percpu.setup_on_cpu()");
     bsp_percpu.load();
 
+    // The per-CPU area is mapped now, start tracking the preempt count and
+    // serve heap allocations from the per-CPU slab caches
+    preempt_init();
+    slab_caches_init();
+    this_cpu()
+        .setup_hv_doorbell()
+        .expect("Failed to set up the #HV doorbell page");
+
     // Idle task must be allocated after PerCPU data is mapped
     bsp_percpu
         .setup_idle_task(svsm_main)
         .expect("Failed to allocate idle task for BSP");
+    bsp_percpu.set_online();
 
     idt_init();
 
@@ -366,6 +384,8 @@ pub extern "C" fn svsm_start(li: &KernelLaunchInfo, vb_addr: usize) {
 
     boot_stack_info();
 
+    clock_init();
+
     let bp = this_cpu().get_top_of_stack();
 
     log::info!("BSP Runtime stack starts @ {:#018x}", bp);
@@ -449,8 +469,15 @@ pub extern "C" fn svsm_main() {
         }
     }
 
-    create_kernel_task(request_processing_main, TASK_FLAG_SHARE_PT)
-        .expect("Failed to launch request processing task");
+    if let Err(e) = enable_preemption() {
+        log::warn!("Failed to enable preemption: {:?}", e);
+    }
+
+    create_kernel_task(
+        request_processing_main,
+        TASK_FLAG_SHARE_PT | TASK_FLAG_REALTIME,
+    )
+    .expect("Failed to launch request processing task");
 
     #[cfg(test)]
     crate::test_main();
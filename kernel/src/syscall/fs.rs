@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+extern crate alloc;
+
+use super::{SysCallArgs, SysCallError};
+use crate::address::VirtAddr;
+use crate::cpu::percpu::current_task;
+use crate::fs::open;
+use crate::mm::{copy_from_user, copy_to_user};
+use crate::types::PAGE_SIZE;
+use alloc::string::String;
+use alloc::vec;
+use core::cmp::min;
+use core::str;
+
+/// Maximum length of a path passed to open()
+const MAX_PATH_LEN: usize = 256;
+
+/// Directory of the SVSM file system which user tasks are confined to
+const USER_FS_ROOT: &str = "user";
+
+/// Translates a path passed by a user task into a path below
+/// [`USER_FS_ROOT`]. The path is interpreted relative to that directory and
+/// must not contain `.` or `..` components, so that user tasks can not reach
+/// any other part of the file system.
+///
+/// # Arguments
+///
+/// * `path` - Path as passed by the user task
+///
+/// # Returns
+///
+/// The path in the SVSM file system, or [`SysCallError::InvalidArgument`]
+/// if the path is empty or contains relative components.
+fn user_path(path: &str) -> Result<String, SysCallError> {
+    let mut result = String::from(USER_FS_ROOT);
+
+    for item in path.split('/').filter(|x| !x.is_empty()) {
+        if item == "." || item == ".." {
+            return Err(SysCallError::InvalidArgument);
+        }
+        result.push('/');
+        result.push_str(item);
+    }
+
+    if result.len() == USER_FS_ROOT.len() {
+        return Err(SysCallError::InvalidArgument);
+    }
+
+    Ok(result)
+}
+
+/// open(path, len) - Open the file at `path` below the user directory of the
+/// SVSM file system
+///
+/// Returns the file descriptor of the opened file.
+pub fn sys_open(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    let (path_addr, len) = (VirtAddr::from(args[0]), args[1]);
+    if len > MAX_PATH_LEN {
+        return Err(SysCallError::InvalidArgument);
+    }
+
+    let mut buf = [0u8; MAX_PATH_LEN];
+    copy_from_user(path_addr, &mut buf[..len])?;
+    let path = str::from_utf8(&buf[..len]).map_err(|_| SysCallError::InvalidArgument)?;
+
+    let file = open(&user_path(path)?)?;
+    current_task()
+        .add_file(file)
+        .ok_or(SysCallError::TooManyFiles)
+}
+
+/// close(fd) - Close an open file
+pub fn sys_close(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    current_task()
+        .remove_file(args[0])
+        .ok_or(SysCallError::BadHandle)?;
+    Ok(0)
+}
+
+/// read(fd, buf, len) - Read up to `len` bytes from an open file
+///
+/// Returns the number of bytes read.
+pub fn sys_read(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    let (fd, addr, len) = (args[0], VirtAddr::from(args[1]), args[2]);
+    let file = current_task().get_file(fd).ok_or(SysCallError::BadHandle)?;
+    let mut buf = vec![0u8; min(len, PAGE_SIZE)];
+    let mut done = 0;
+
+    while done < len {
+        let size = min(len - done, buf.len());
+        let count = file.read(&mut buf[..size])?;
+        copy_to_user(&buf[..count], addr + done)?;
+        done += count;
+        if count < size {
+            break;
+        }
+    }
+
+    Ok(done)
+}
+
+/// write(fd, buf, len) - Write `len` bytes to an open file
+///
+/// Returns the number of bytes written.
+pub fn sys_write(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    let (fd, addr, len) = (args[0], VirtAddr::from(args[1]), args[2]);
+    let file = current_task().get_file(fd).ok_or(SysCallError::BadHandle)?;
+    let mut buf = vec![0u8; min(len, PAGE_SIZE)];
+    let mut done = 0;
+
+    while done < len {
+        let size = min(len - done, buf.len());
+        copy_from_user(addr + done, &mut buf[..size])?;
+        let count = file.write(&buf[..size])?;
+        done += count;
+        if count < size {
+            break;
+        }
+    }
+
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_path_is_confined() {
+        assert_eq!(user_path("file").unwrap(), "user/file");
+        assert_eq!(user_path("/dir//file").unwrap(), "user/dir/file");
+    }
+
+    #[test]
+    fn user_path_rejects_escapes() {
+        assert_eq!(user_path(""), Err(SysCallError::InvalidArgument));
+        assert_eq!(user_path("/"), Err(SysCallError::InvalidArgument));
+        assert_eq!(user_path(".."), Err(SysCallError::InvalidArgument));
+        assert_eq!(
+            user_path("dir/../../file"),
+            Err(SysCallError::InvalidArgument)
+        );
+        assert_eq!(user_path("./file"), Err(SysCallError::InvalidArgument));
+    }
+}
@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+extern crate alloc;
+
+use super::{SysCallArgs, SysCallError};
+use crate::address::{Address, VirtAddr};
+use crate::cpu::percpu::current_task;
+use crate::mm::vm::{Mapping, VMDemandZero, VMFileMapping, VMFileMappingPermission};
+use crate::mm::{SIZE_LEVEL3, USER_MEM_START};
+use crate::types::PAGE_SIZE;
+use crate::utils::align_up;
+use alloc::sync::Arc;
+
+// Protection flags for mmap()
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const PROT_EXEC: usize = 1 << 2;
+
+// Mapping flags for mmap()
+pub const MAP_SHARED: usize = 1 << 0;
+
+/// File descriptor value to request an anonymous mapping from mmap()
+pub const MMAP_ANONYMOUS: usize = usize::MAX;
+
+/// mmap(fd, offset, len, prot, flags) - Map memory into the user-space
+/// address space of the current task
+///
+/// With `fd == MMAP_ANONYMOUS` zeroed memory is mapped read-write. Its pages
+/// are allocated when they are first accessed. Otherwise `len` bytes of the
+/// file starting at the page-aligned `offset` are mapped. Writable file
+/// mappings are private copy-on-write views of the file contents, unless
+/// `MAP_SHARED` is passed in `flags`. Shared mappings map the file pages
+/// themselves, so changes are visible in the file right away and never need
+/// to be written back.
+///
+/// Returns the address of the new mapping.
+pub fn sys_mmap(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    let (fd, offset, len, prot, flags) = (args[0], args[1], args[2], args[3], args[4]);
+    if len == 0 || len > SIZE_LEVEL3 {
+        return Err(SysCallError::InvalidArgument);
+    }
+    if (flags & !MAP_SHARED) != 0 {
+        return Err(SysCallError::InvalidArgument);
+    }
+    if (prot & PROT_WRITE) != 0 && (prot & PROT_EXEC) != 0 {
+        return Err(SysCallError::InvalidArgument);
+    }
+    let size = align_up(len, PAGE_SIZE);
+
+    let task = current_task();
+    let vm_range = task.user_vm_range().ok_or(SysCallError::Failed)?;
+
+    let mapping = if fd == MMAP_ANONYMOUS {
+        if (prot & PROT_EXEC) != 0 {
+            return Err(SysCallError::InvalidArgument);
+        }
+        VMDemandZero::new_mapping(size)
+    } else {
+        let file = task.get_file(fd).ok_or(SysCallError::BadHandle)?;
+        let permission = if (prot & PROT_WRITE) != 0 {
+            if (flags & MAP_SHARED) != 0 {
+                VMFileMappingPermission::Shared
+            } else {
+                VMFileMappingPermission::Write
+            }
+        } else if (prot & PROT_EXEC) != 0 {
+            VMFileMappingPermission::Execute
+        } else {
+            VMFileMappingPermission::Read
+        };
+        Mapping::new(VMFileMapping::new(file.reopen(), offset, size, permission)?)
+    };
+
+    let addr = vm_range.insert(Arc::new(mapping))?;
+    Ok(addr.bits())
+}
+
+/// munmap(addr) - Remove the mapping starting at `addr` from the user-space
+/// address space of the current task
+pub fn sys_munmap(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    let addr = VirtAddr::from(args[0]);
+    // The reserved NULL page can not be unmapped
+    if addr == USER_MEM_START {
+        return Err(SysCallError::InvalidArgument);
+    }
+
+    let task = current_task();
+    let vm_range = task.user_vm_range().ok_or(SysCallError::Failed)?;
+    vm_range.remove(addr)?;
+    Ok(0)
+}
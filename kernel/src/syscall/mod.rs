@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! System call interface for user-space tasks.
+//!
+//! User-space enters the kernel with the SYSCALL instruction. The system call
+//! number is passed in RAX and the arguments in RDI, RSI, RDX, R10, R8 and
+//! R9. The result is returned in RAX. Values which are negative when
+//! interpreted as a signed integer are negated [`SysCallError`] codes.
+
+mod fs;
+mod mm;
+mod task;
+
+use crate::cpu::X86ExceptionContext;
+use crate::error::SvsmError;
+use crate::fs::FsError;
+use crate::task::TaskError;
+
+pub use mm::{MAP_SHARED, MMAP_ANONYMOUS, PROT_EXEC, PROT_READ, PROT_WRITE};
+
+// System call numbers
+pub const SYS_EXIT: usize = 0;
+pub const SYS_YIELD: usize = 1;
+pub const SYS_OPEN: usize = 2;
+pub const SYS_CLOSE: usize = 3;
+pub const SYS_READ: usize = 4;
+pub const SYS_WRITE: usize = 5;
+pub const SYS_MMAP: usize = 6;
+pub const SYS_MUNMAP: usize = 7;
+pub const SYS_WAIT_REQUEST: usize = 8;
+
+/// Errors returned to user-space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SysCallError {
+    /// Unknown system call number
+    InvalidSyscall = 1,
+    /// Invalid system call argument
+    InvalidArgument = 2,
+    /// A user-space pointer is invalid or not accessible
+    InvalidAddress = 3,
+    /// File not found
+    NotFound = 4,
+    /// Out of memory or address space
+    NoMemory = 5,
+    /// Invalid file descriptor
+    BadHandle = 6,
+    /// The resource is already in use
+    Busy = 7,
+    /// Too many open files
+    TooManyFiles = 8,
+    /// Any other error
+    Failed = 9,
+}
+
+impl SysCallError {
+    fn to_ret(self) -> usize {
+        (self as usize).wrapping_neg()
+    }
+}
+
+impl From<SvsmError> for SysCallError {
+    fn from(e: SvsmError) -> Self {
+        match e {
+            SvsmError::InvalidAddress => Self::InvalidAddress,
+            SvsmError::Mem | SvsmError::Alloc(_) => Self::NoMemory,
+            SvsmError::FileSystem(FsError::FileNotFound) => Self::NotFound,
+            SvsmError::FileSystem(FsError::Inval) => Self::InvalidArgument,
+            SvsmError::Task(TaskError::Busy) => Self::Busy,
+            _ => Self::Failed,
+        }
+    }
+}
+
+/// Arguments of a system call in the order they are passed in registers
+type SysCallArgs = [usize; 6];
+
+type SysCallHandler = fn(&SysCallArgs) -> Result<usize, SysCallError>;
+
+/// System call table, indexed by the system call number
+static SYSCALL_TABLE: [SysCallHandler; 9] = [
+    task::sys_exit,
+    task::sys_yield,
+    fs::sys_open,
+    fs::sys_close,
+    fs::sys_read,
+    fs::sys_write,
+    mm::sys_mmap,
+    mm::sys_munmap,
+    task::sys_wait_request,
+];
+
+/// Dispatch a system call from the current user task. The result is stored
+/// in RAX of `ctx`.
+pub fn handle_syscall(ctx: &mut X86ExceptionContext) {
+    let nr = ctx.regs.rax;
+    let args = [
+        ctx.regs.rdi,
+        ctx.regs.rsi,
+        ctx.regs.rdx,
+        ctx.regs.r10,
+        ctx.regs.r8,
+        ctx.regs.r9,
+    ];
+
+    let result = match SYSCALL_TABLE.get(nr) {
+        Some(handler) => handler(&args),
+        None => Err(SysCallError::InvalidSyscall),
+    };
+
+    ctx.regs.rax = match result {
+        Ok(ret) => ret,
+        Err(e) => e.to_ret(),
+    };
+}
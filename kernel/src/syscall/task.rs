@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use super::{SysCallArgs, SysCallError};
+use crate::cpu::percpu::wait_for_user_requests;
+use crate::task::{exit_current_task, schedule};
+
+/// exit(code) - Terminate the current task with the given exit code
+pub fn sys_exit(args: &SysCallArgs) -> Result<usize, SysCallError> {
+    exit_current_task(args[0] as i32);
+}
+
+/// yield() - Give up the CPU to other runnable tasks
+pub fn sys_yield(_args: &SysCallArgs) -> Result<usize, SysCallError> {
+    schedule();
+    Ok(0)
+}
+
+/// wait_request() - Block until the next protocol request arrives on the
+/// current CPU
+pub fn sys_wait_request(_args: &SysCallArgs) -> Result<usize, SysCallError> {
+    wait_for_user_requests()?;
+    Ok(0)
+}
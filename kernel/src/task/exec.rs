@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Loader for user-space executables. The ELF image is read from the SVSM
+//! file system and mapped into a private [`VMR`] which covers the user-space
+//! part of the address space of a task.
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::ptr;
+
+use super::TaskError;
+use crate::address::{Address, VirtAddr};
+use crate::elf::{Elf64File, Elf64PhdrFlags, Elf64X86RelocProcessor};
+use crate::error::SvsmError;
+use crate::fs::open;
+use crate::mm::pagetable::PTEntryFlags;
+use crate::mm::vm::{Mapping, VMFileMapping, VMFileMappingPermission, VMReserved, VMalloc, VMR};
+use crate::mm::{
+    phys_to_virt, PAGE_SIZE, USER_IMAGE_BASE, USER_MEM_END, USER_MEM_START, USER_STACK_BASE,
+    USER_STACK_SIZE,
+};
+use crate::utils::{align_down, align_up};
+
+/// Space reserved at the top of the user stack. The stack is zero-filled,
+/// so the program finds `argc == 0` and empty argv, envp and auxv vectors
+/// there.
+const USER_STACK_RESERVED: usize = 8 * 8;
+
+/// A user-space image which is ready to be executed by a task
+#[derive(Debug)]
+pub struct UserImage {
+    /// Virtual memory range with the image and stack mappings
+    pub vm_range: VMR,
+    /// Entry point of the image
+    pub entry: VirtAddr,
+    /// Initial user-space stack pointer
+    pub stack: VirtAddr,
+}
+
+/// A writable segment, remembered to apply relocations to its contents
+struct WritableSegment {
+    start: VirtAddr,
+    end: VirtAddr,
+    mapping: Arc<Mapping>,
+}
+
+/// Copies `data` into the pages backing `mapping`, starting at `offset`.
+/// The pages must be allocated, which is the case for [`VMalloc`].
+fn copy_to_mapping(mapping: &Mapping, offset: usize, data: &[u8]) -> Result<(), SvsmError> {
+    let guard = mapping.get();
+    let mut done = 0;
+
+    while done < data.len() {
+        let off = offset + done;
+        let page_offset = off & (PAGE_SIZE - 1);
+        let len = min(PAGE_SIZE - page_offset, data.len() - done);
+        let paddr = guard.map(off - page_offset).ok_or(SvsmError::Mem)?;
+        let dst = phys_to_virt(paddr) + page_offset;
+
+        // SAFETY: dst points to a page owned by the mapping and the copy
+        // does not cross the page boundary.
+        unsafe {
+            ptr::copy_nonoverlapping(data[done..].as_ptr(), dst.as_mut_ptr::<u8>(), len);
+        }
+        done += len;
+    }
+
+    Ok(())
+}
+
+/// Determine the address to load an ELF image at and check that it fits
+/// into the user-space memory region below the stack.
+fn image_load_addr(elf: &Elf64File<'_>) -> Result<u64, SvsmError> {
+    let alloc_info = elf.image_load_vaddr_alloc_info();
+    let range = alloc_info.range;
+
+    let load_addr = match alloc_info.align {
+        Some(align) => align_up(USER_IMAGE_BASE.bits(), align as usize) as u64,
+        None => range.vaddr_begin,
+    };
+
+    let end = load_addr
+        .checked_add(range.len())
+        .ok_or(TaskError::InvalidImage)?;
+    if load_addr < (USER_MEM_START + PAGE_SIZE).bits() as u64 || end > USER_STACK_BASE.bits() as u64
+    {
+        return Err(TaskError::InvalidImage.into());
+    }
+
+    Ok(load_addr)
+}
+
+/// Loads the ELF executable at `path` into a new user-space [`VMR`].
+///
+/// Read-only and executable segments are mapped directly from the file.
+/// Writable segments are copied into newly allocated memory, which also
+/// receives the dynamic relocations of position independent executables.
+///
+/// # Arguments
+///
+/// * `path` - Path of the executable in the SVSM file system
+///
+/// # Returns
+///
+/// The loaded [`UserImage`] on success, or an [`SvsmError`] if the file
+/// could not be read, is not a valid ELF file or has an unsupported layout.
+pub fn load_user_image(path: &str) -> Result<UserImage, SvsmError> {
+    let file = open(path)?;
+    let mut buf = vec![0u8; file.size()];
+    if file.read(&mut buf)? != buf.len() {
+        return Err(TaskError::InvalidImage.into());
+    }
+
+    let elf = Elf64File::read(&buf)?;
+    let load_addr = image_load_addr(&elf)?;
+
+    let mut vm_range = VMR::new(USER_MEM_START, USER_MEM_END, PTEntryFlags::USER);
+    vm_range.initialize()?;
+
+    // Keep the NULL page unmapped
+    vm_range.insert_at(USER_MEM_START, Arc::new(VMReserved::new_mapping(PAGE_SIZE)))?;
+
+    let mut writable: Vec<WritableSegment> = Vec::new();
+
+    for segment in elf.image_load_segment_iter(load_addr) {
+        let vaddr_begin = segment.vaddr_range.vaddr_begin as usize;
+        let start = VirtAddr::from(align_down(vaddr_begin, PAGE_SIZE));
+        let end = VirtAddr::from(segment.vaddr_range.vaddr_end as usize).page_align_up();
+        let page_offset = vaddr_begin - start.bits();
+        let contents = segment.file_contents;
+        let flags = segment.flags;
+
+        if flags.contains(Elf64PhdrFlags::WRITE) {
+            // Writable and executable memory is not supported for user-space
+            if flags.contains(Elf64PhdrFlags::EXECUTE) {
+                return Err(TaskError::InvalidImage.into());
+            }
+
+            let mapping = Arc::new(VMalloc::new_mapping(end - start)?);
+            copy_to_mapping(&mapping, page_offset, contents)?;
+            vm_range.insert_at(start, mapping.clone())?;
+            writable.push(WritableSegment {
+                start,
+                end,
+                mapping,
+            });
+        } else {
+            // Map the segment from the file. This requires the segment to
+            // be fully backed by the file and to have the same page offset
+            // in the file and in memory.
+            let file_offset = contents.as_ptr() as usize - buf.as_ptr() as usize;
+            if contents.len() as u64 != segment.vaddr_range.len()
+                || (file_offset & (PAGE_SIZE - 1)) != page_offset
+            {
+                return Err(TaskError::InvalidImage.into());
+            }
+
+            let permission = if flags.contains(Elf64PhdrFlags::EXECUTE) {
+                VMFileMappingPermission::Execute
+            } else {
+                VMFileMappingPermission::Read
+            };
+            let mapping = VMFileMapping::new(
+                open(path)?,
+                file_offset - page_offset,
+                end - start,
+                permission,
+            )?;
+            vm_range.insert_at(start, Arc::new(Mapping::new(mapping)))?;
+        }
+    }
+
+    // Apply relocations, if any. Relocations are only supported in
+    // writable segments.
+    if let Some(relocs) = elf.apply_dyn_relas(Elf64X86RelocProcessor::new(), load_addr)? {
+        for reloc in relocs {
+            let Some(reloc) = reloc? else {
+                continue;
+            };
+            let dst = VirtAddr::from(reloc.dst);
+            let segment = writable
+                .iter()
+                .find(|s| dst >= s.start && dst + reloc.value_len <= s.end)
+                .ok_or(TaskError::InvalidImage)?;
+            copy_to_mapping(
+                &segment.mapping,
+                dst - segment.start,
+                &reloc.value[..reloc.value_len],
+            )?;
+        }
+    }
+
+    let stack = Arc::new(VMalloc::new_mapping(USER_STACK_SIZE)?);
+    vm_range.insert_at(USER_STACK_BASE, stack)?;
+
+    Ok(UserImage {
+        vm_range,
+        entry: VirtAddr::from(elf.get_entry(load_addr)),
+        stack: USER_MEM_END - USER_STACK_RESERVED,
+    })
+}
@@ -4,17 +4,33 @@
 //
 // Author: Roy Hopkins <rhopkins@suse.de>
 
+mod exec;
+mod preempt;
 mod schedule;
+mod sleep;
 mod tasks;
 mod waiting;
 
+pub use preempt::{
+    enable_preemption, preempt_disable, preempt_enable, preempt_init, preemptible, reschedule,
+    schedule_tick, PreemptState,
+};
+
 pub use schedule::{
-    create_kernel_task, is_current_task, schedule, schedule_init, schedule_task, RunQueue, TASKLIST,
+    create_kernel_task, create_kernel_task_affine, create_user_task, current_task_id,
+    exit_current_task, is_current_task, schedule, schedule_init, schedule_task, set_task_priority,
+    wake_task, RunQueue, TASKLIST, TIME_SLICE_TICKS,
 };
 
+#[cfg(test)]
+pub use schedule::run_blocked_task;
+
+pub use sleep::{poll_timers, schedule_timeout, sleep, sleep_until, TaskTimer};
+
 pub use tasks::{
-    Task, TaskContext, TaskError, TaskListAdapter, TaskPointer, TaskRunListAdapter, TaskState,
-    INITIAL_TASK_ID, TASK_FLAG_SHARE_PT,
+    is_user_addr, Task, TaskContext, TaskError, TaskListAdapter, TaskPointer, TaskPriority,
+    TaskRunListAdapter, TaskState, TaskWaitListAdapter, INITIAL_TASK_ID, NR_TASK_PRIORITIES,
+    TASK_EXIT_KILLED, TASK_FLAG_FPU, TASK_FLAG_REALTIME, TASK_FLAG_SHARE_PT, TASK_MAX_FILES,
 };
 
 pub use waiting::WaitQueue;
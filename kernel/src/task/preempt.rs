@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Preemption support for the scheduler.
+//!
+//! Every CPU keeps a preempt count in its [`PerCpu`](crate::cpu::percpu::PerCpu)
+//! structure. The count is raised while a [`SpinLock`](crate::locking::SpinLock)
+//! or [`RWLock`](crate::locking::RWLock) is held and while a reference to the
+//! per-CPU data is alive. The APIC timer interrupt only switches tasks when
+//! the count is zero. Ticks arriving in a non-preemptible section are
+//! remembered and accounted when the section ends.
+
+use super::schedule::{schedule, schedule_tick_pending};
+use super::sleep::run_timers;
+use crate::cpu::apic::{apic_timer_start, apic_timer_tick_count, x2apic_enable};
+use crate::cpu::irq_state::{irqs_enabled, raw_irqs_enable};
+use crate::cpu::percpu::{this_cpu_shared, this_cpu_unsafe};
+use crate::error::SvsmError;
+use crate::sev::status::{sev_flags, SEVStatusFlags};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Per-CPU preemption state
+#[derive(Debug, Default)]
+pub struct PreemptState {
+    /// Nesting level of non-preemptible sections on this CPU
+    count: AtomicU32,
+    /// Timer ticks which were not yet accounted to the current task
+    pending_ticks: AtomicU32,
+    /// Set when tasks were put on the run-queue which might preempt the
+    /// current task
+    pending_resched: AtomicBool,
+}
+
+impl PreemptState {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            pending_ticks: AtomicU32::new(0),
+            pending_resched: AtomicBool::new(false),
+        }
+    }
+
+    fn has_pending_work(&self) -> bool {
+        self.pending_ticks.load(Ordering::Relaxed) != 0
+            || self.pending_resched.load(Ordering::Relaxed)
+    }
+}
+
+/// Set once the per-CPU areas are mapped and the preempt count can be
+/// tracked. Before that (and in stage2 or unit tests) the preemption
+/// functions do nothing.
+static PREEMPT_READY: AtomicBool = AtomicBool::new(false);
+
+fn preempt_state() -> Option<&'static PreemptState> {
+    if !PREEMPT_READY.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    // SAFETY: The per-CPU area of the current CPU is mapped once
+    // PREEMPT_READY is set. Only the atomic preempt state is accessed, so
+    // this does not conflict with outstanding per-CPU references.
+    unsafe { Some(&*ptr::addr_of!((*this_cpu_unsafe()).preempt)) }
+}
+
+/// Start tracking the preempt count. Must be called on the BSP right after
+/// its per-CPU area has been loaded and while no locks are held.
+pub fn preempt_init() {
+    PREEMPT_READY.store(true, Ordering::Relaxed);
+}
+
+/// Enter a non-preemptible section on the current CPU. Sections can be
+/// nested and must be left with [`preempt_enable()`] on the same CPU.
+#[inline]
+pub fn preempt_disable() {
+    if let Some(state) = preempt_state() {
+        state.count.fetch_add(1, Ordering::Acquire);
+    }
+}
+
+/// Leave a non-preemptible section. If this was the outermost section and
+/// timer ticks or reschedule requests arrived in the meantime, the current
+/// task might be preempted.
+#[inline]
+pub fn preempt_enable() {
+    if let Some(state) = preempt_state() {
+        let prev = state.count.fetch_sub(1, Ordering::Release);
+        debug_assert!(prev != 0, "Unbalanced preempt_enable()");
+        if prev == 1 && state.has_pending_work() && irqs_enabled() {
+            preempt_check(state);
+        }
+    }
+}
+
+/// Query whether the current task can be preempted right now.
+pub fn preemptible() -> bool {
+    preempt_state().is_some_and(|state| state.count.load(Ordering::Relaxed) == 0)
+}
+
+fn preempt_check(state: &PreemptState) {
+    let ticks = state.pending_ticks.swap(0, Ordering::Relaxed);
+    let resched = state.pending_resched.swap(false, Ordering::Relaxed);
+    if ticks == 0 && !resched {
+        return;
+    }
+
+    if ticks != 0 {
+        run_timers();
+    }
+    if schedule_tick_pending(ticks) {
+        schedule();
+    }
+}
+
+/// Account a timer tick on the current CPU. Called from the APIC timer
+/// interrupt handler with interrupts disabled.
+pub fn schedule_tick() {
+    if let Some(state) = preempt_state() {
+        state.pending_ticks.fetch_add(1, Ordering::Relaxed);
+        if state.count.load(Ordering::Relaxed) == 0 {
+            preempt_check(state);
+        }
+    }
+}
+
+/// Makes the current CPU check at its next preemption point whether the
+/// current task should keep running.
+pub fn request_reschedule() {
+    if let Some(state) = preempt_state() {
+        state.pending_resched.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Check the run-queue of the current CPU for tasks which other CPUs put
+/// there. Called from the IPI handler with interrupts disabled.
+pub fn reschedule() {
+    if let Some(state) = preempt_state() {
+        state.pending_resched.store(true, Ordering::Relaxed);
+        if state.count.load(Ordering::Relaxed) == 0 {
+            preempt_check(state);
+        }
+    }
+}
+
+/// Start the APIC timer on the current CPU and enable interrupts, which
+/// turns on time-slice preemption for the tasks running on it. From then on
+/// the CPU also accepts IPIs.
+///
+/// # Returns
+///
+/// Ok(()) on success, or the [`SvsmError`] returned when programming the
+/// local APIC failed. In the latter case the CPU keeps scheduling
+/// cooperatively.
+pub fn enable_preemption() -> Result<(), SvsmError> {
+    // With restricted injection interrupts are only delivered through #HV,
+    // which is not supported yet.
+    if sev_flags().contains(SEVStatusFlags::REST_INJ) {
+        log::info!("Restricted injection enabled - using cooperative scheduling");
+        return Ok(());
+    }
+
+    x2apic_enable()?;
+    this_cpu_shared().ipi().enable();
+    apic_timer_start(apic_timer_tick_count())?;
+    raw_irqs_enable();
+
+    Ok(())
+}
@@ -8,44 +8,76 @@ extern crate alloc;
 
 use core::ptr::null_mut;
 
+use super::exec::load_user_image;
+use super::preempt::request_reschedule;
+use super::preempt::{preempt_disable, preempt_enable};
+use super::sleep::run_timers;
 use super::INITIAL_TASK_ID;
-use super::{Task, TaskListAdapter, TaskPointer, TaskRunListAdapter};
+use super::{
+    Task, TaskListAdapter, TaskPointer, TaskPriority, TaskRunListAdapter, NR_TASK_PRIORITIES,
+};
 use crate::address::Address;
-use crate::cpu::percpu::{this_cpu, this_cpu_mut};
+use crate::cpu::cpuset::CpuSet;
+use crate::cpu::ipi::smp_send_reschedule;
+use crate::cpu::irq_state::IrqGuard;
+use crate::cpu::percpu::{
+    current_task, this_cpu, this_cpu_mut, this_cpu_shared, PerCpuShared, PERCPU_AREAS,
+};
 use crate::error::SvsmError;
 use crate::locking::SpinLock;
 use alloc::sync::Arc;
 use core::arch::{asm, global_asm};
+use core::array::from_fn;
 use core::cell::OnceCell;
+use core::hint::spin_loop;
 use intrusive_collections::LinkedList;
 
+/// Number of timer ticks a task can run before it gets preempted
+pub const TIME_SLICE_TICKS: u32 = 10;
+
 /// Round-Robin scheduler implementation for COCONUT-SVSM
 ///
-/// This file implements a round-robin scheduler for cooperative multi-tasking.
+/// This file implements a round-robin scheduler for preemptive multi-tasking.
 /// It works by assigning a single owner for each struct [Task]. The owner
 /// depends on the state of the task:
 ///
 /// * `RUNNING` A task in running state is owned by the [RunQueue] and either
-///    stored in the `run_list` (when the task is not actively running) or in
+///    stored in a `run_list` (when the task is not actively running) or in
 ///    `current+task` when it is scheduled on the CPU.
 /// * `BLOCKED` A task in this state is waiting for an event to become runnable
 ///    again. It is owned by a wait object when in this state.
 /// * `TERMINATED` The task is about to be destroyed and owned by the RunQueue.
 ///
-/// The scheduler is cooperative. A task runs until it voluntarily calls the
-/// [schedule] function.
+/// Every task belongs to a scheduling class given by its [TaskPriority].
+/// Runnable tasks of a higher class always run before those of a lower
+/// class. Within a class tasks run round-robin: a task runs until it
+/// voluntarily calls the [schedule] function, until its time slice of
+/// [TIME_SLICE_TICKS] APIC timer ticks is used up while another task of the
+/// same class is runnable, or until a task of a higher class becomes
+/// runnable. Tasks are never preempted while they hold a lock or a reference
+/// to the per-CPU data, see the [preempt](super::preempt) module.
 ///
 /// Only when a task is in `RUNNING` or `TERMINATED` state it is assigned to a
 /// specific CPU. Tasks in the `BLOCKED` state have no CPU assigned and will run
-/// on the CPU where their event is triggered that makes them `RUNNING` again.
+/// on the CPU where their event is triggered that makes them `RUNNING` again,
+/// as long as the affinity of the task allows it. Otherwise the task is put on
+/// the least loaded CPU in its affinity set.
+///
+/// A CPU which has nothing to run takes runnable tasks from the run-queues of
+/// other CPUs, if their affinity allows it.
 
-/// A RunQueue implementation that uses an RBTree to efficiently sort the priority
-/// of tasks within the queue.
+/// A RunQueue implementation with one FIFO run list per scheduling class.
 
 #[derive(Debug, Default)]
 pub struct RunQueue {
-    /// Linked list with runable tasks
-    run_list: LinkedList<TaskRunListAdapter>,
+    /// APIC ID of the CPU owning the run-queue
+    apic_id: u32,
+
+    /// Linked lists with runable tasks, indexed by [TaskPriority::index()]
+    run_lists: [LinkedList<TaskRunListAdapter>; NR_TASK_PRIORITIES],
+
+    /// Number of tasks on the run_lists
+    nr_queued: usize,
 
     /// Pointer to currently running task
     current_task: Option<TaskPointer>,
@@ -53,25 +85,33 @@ pub struct RunQueue {
     /// Idle task - runs when there is no other runnable task
     idle_task: OnceCell<TaskPointer>,
 
-    /// Temporary storage for tasks which are about to be terminated
-    terminated_task: Option<TaskPointer>,
+    /// Task which was switched out by the last task switch. Kept until the
+    /// switch has completed in the context of the next task.
+    prev_task: Option<TaskPointer>,
+
+    /// Remaining timer ticks in the time slice of the current task
+    time_slice: u32,
 }
 
 impl RunQueue {
     /// Create a new runqueue for an id. The id would normally be set
     /// to the APIC ID of the CPU that owns the runqueue and is used to
     /// determine the affinity of tasks.
-    pub fn new() -> Self {
+    pub fn new(apic_id: u32) -> Self {
         Self {
-            run_list: LinkedList::new(TaskRunListAdapter::new()),
+            apic_id,
+            run_lists: from_fn(|_| LinkedList::new(TaskRunListAdapter::new())),
+            nr_queued: 0,
             current_task: None,
             idle_task: OnceCell::new(),
-            terminated_task: None,
+            prev_task: None,
+            time_slice: TIME_SLICE_TICKS,
         }
     }
 
     /// Find the next task to run, which is either the task at the front of the
-    /// run_list or the idle task, if the run_list is empty.
+    /// highest priority non-empty run list or the idle task, if all run lists
+    /// are empty.
     ///
     /// # Returns
     ///
@@ -82,21 +122,97 @@ impl RunQueue {
     /// Panics if there are no tasks to run and no idle task has been
     /// allocated via [`set_idle_task()`](Self::set_idle_task).
     fn get_next_task(&mut self) -> TaskPointer {
-        self.run_list
-            .pop_front()
-            .unwrap_or_else(|| self.idle_task.get().unwrap().clone())
+        for priority in TaskPriority::ALL {
+            if let Some(task) = self.run_lists[priority.index()].pop_front() {
+                task.mark_dequeued();
+                self.nr_queued -= 1;
+                return task;
+            }
+        }
+        self.idle_task.get().unwrap().clone()
+    }
+
+    /// Returns the priority of the highest priority runnable task, or
+    /// `None` if all run lists are empty.
+    fn highest_queued_priority(&self) -> Option<TaskPriority> {
+        TaskPriority::ALL
+            .into_iter()
+            .find(|priority| !self.run_lists[priority.index()].is_empty())
     }
 
     /// Update state before a task is scheduled out. Non-idle tasks in RUNNING
-    /// state will be put at the end of the run_list. Terminated tasks will be
-    /// stored in the terminated_task field of the RunQueue and be destroyed
-    /// after the task-switch.
-    fn handle_task(&mut self, task: TaskPointer) {
-        if task.is_running() && !task.is_idle_task() {
-            self.run_list.push_back(task);
-        } else if task.is_terminated() {
-            self.terminated_task = Some(task);
+    /// state will be put at the end of the run list for their priority,
+    /// unless their affinity does not allow them to run on this CPU.
+    ///
+    /// # Returns
+    ///
+    /// The task if it is runnable but not allowed to run on this CPU. The
+    /// caller has to move it to another CPU.
+    #[must_use]
+    fn handle_task(&mut self, task: TaskPointer) -> Option<TaskPointer> {
+        if !task.is_running() || task.is_idle_task() {
+            return None;
+        }
+        if !task.allowed_on_cpu(self.apic_id) {
+            return Some(task);
+        }
+
+        let priority = task.mark_queued(self.apic_id);
+        self.run_lists[priority.index()].push_back(task);
+        self.nr_queued += 1;
+        None
+    }
+
+    /// Moves a queued task to the run list for `priority`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the priority was changed, `false` if the task is not on this
+    /// run-queue.
+    fn requeue(&mut self, task: &TaskPointer, priority: TaskPriority) -> bool {
+        let Some(old) = task.set_queued_priority(self.apic_id, priority) else {
+            return false;
+        };
+
+        // SAFETY: The task is queued on this run-queue with priority `old`,
+        // so it is linked into the corresponding run list.
+        let mut cursor = unsafe { self.run_lists[old.index()].cursor_mut_from_ptr(task.as_ref()) };
+        let task = cursor.remove().expect("Queued task not on its run list");
+        self.run_lists[priority.index()].push_back(task);
+        true
+    }
+
+    /// Takes a runnable task which is allowed to run on the CPU with APIC ID
+    /// `apic_id`, starting at the back of the highest priority run list.
+    /// Tasks whose context is still live on this CPU are skipped.
+    fn steal_task(&mut self, apic_id: u32) -> Option<TaskPointer> {
+        for priority in TaskPriority::ALL {
+            let mut cursor = self.run_lists[priority.index()].back_mut();
+            while let Some(task) = cursor.get() {
+                if task.allowed_on_cpu(apic_id) && !task.is_on_cpu() {
+                    task.mark_dequeued();
+                    self.nr_queued -= 1;
+                    return cursor.remove();
+                }
+                cursor.move_prev();
+            }
         }
+        None
+    }
+
+    /// Returns the number of runnable tasks waiting for the CPU.
+    pub fn nr_queued(&self) -> usize {
+        self.nr_queued
+    }
+
+    /// Returns whether the CPU has nothing to run besides its idle task. A
+    /// current task which is about to block does not count as runnable.
+    fn is_idle(&self) -> bool {
+        self.nr_queued == 0
+            && self
+                .current_task
+                .as_ref()
+                .is_none_or(|task| task.is_idle_task() || !task.is_running())
     }
 
     /// Initialized the scheduler for this (RunQueue)[RunQueue]. This method is
@@ -109,6 +225,7 @@ impl RunQueue {
     pub fn schedule_init(&mut self) -> TaskPointer {
         let task = self.get_next_task();
         self.current_task = Some(task.clone());
+        self.time_slice = TIME_SLICE_TICKS;
         task
     }
 
@@ -130,20 +247,49 @@ impl RunQueue {
         // runnable. This is important to make sure the last runnable task
         // keeps running, even if it calls schedule()
         let current = self.current_task.take().unwrap();
-        self.handle_task(current.clone());
+        // A current task whose affinity excludes this CPU is moved to
+        // another CPU by schedule_tail() once its context is saved.
+        let _ = self.handle_task(current.clone());
 
         // Get next task and update current_task state
         let next = self.get_next_task();
         self.current_task = Some(next.clone());
+        self.time_slice = TIME_SLICE_TICKS;
 
         // Check if task switch is needed
         if current != next {
+            // Terminated tasks are destroyed after the task-switch
+            self.prev_task = Some(current.clone());
             Some((current, next))
         } else {
             None
         }
     }
 
+    /// Accounts timer ticks to the time slice of the current task.
+    ///
+    /// # Returns
+    ///
+    /// `true` when the current task needs to be preempted, because it is the
+    /// idle task and another task is runnable, because a task of a higher
+    /// priority is runnable, or because it used up its time slice and a task
+    /// of the same priority is runnable. `false` otherwise.
+    pub fn tick(&mut self, ticks: u32) -> bool {
+        self.time_slice = self.time_slice.saturating_sub(ticks);
+
+        let Some(queued) = self.highest_queued_priority() else {
+            return false;
+        };
+
+        match self.current_task {
+            Some(ref task) if !task.is_idle_task() => {
+                let current = task.priority();
+                queued > current || (queued == current && self.time_slice == 0)
+            }
+            _ => true,
+        }
+    }
+
     pub fn current_task_id(&self) -> u32 {
         self.current_task
             .as_ref()
@@ -223,18 +369,172 @@ impl TaskList {
 
 pub static TASKLIST: SpinLock<TaskList> = SpinLock::new(TaskList::new());
 
+/// Create a kernel task which is bound to the current CPU.
 pub fn create_kernel_task(entry: extern "C" fn(), flags: u16) -> Result<TaskPointer, SvsmError> {
     let mut cpu = this_cpu_mut();
     let task = Task::create(&mut cpu, entry, flags)?;
-    TASKLIST.lock().list().push_back(task.clone());
+    drop(cpu);
+
+    Ok(start_task(task))
+}
 
-    // Put task on the runqueue of this CPU
-    cpu.runqueue().lock_write().handle_task(task.clone());
+/// Create a kernel task which can run on any CPU in `affinity`. Background
+/// service tasks use [`CpuSet::all()`] so that they run on whichever CPU is
+/// idle.
+///
+/// # Returns
+///
+/// The new task on success, or an [`SvsmError`] if the task could not be
+/// created or `affinity` contains no online CPU.
+pub fn create_kernel_task_affine(
+    entry: extern "C" fn(),
+    flags: u16,
+    affinity: CpuSet,
+) -> Result<TaskPointer, SvsmError> {
+    let mut cpu = this_cpu_mut();
+    let task = Task::create(&mut cpu, entry, flags)?;
+    drop(cpu);
+
+    task.set_affinity(affinity)?;
+    Ok(start_task(task))
+}
+
+/// Create a task which runs the ELF executable at `path` in user-space.
+///
+/// # Arguments
+///
+/// * `path` - Path of the executable in the SVSM file system
+///
+/// # Returns
+///
+/// The new task on success, or an [`SvsmError`] if the executable could not
+/// be loaded.
+pub fn create_user_task(path: &str) -> Result<TaskPointer, SvsmError> {
+    let image = load_user_image(path)?;
+    let mut cpu = this_cpu_mut();
+    let task = Task::create_user(&mut cpu, image)?;
     drop(cpu);
 
+    Ok(start_task(task))
+}
+
+/// Adds a new task to the global task list and to the run-queue of a CPU
+/// in its affinity set, then gives it a chance to run.
+fn start_task(task: TaskPointer) -> TaskPointer {
+    TASKLIST.lock().list().push_back(task.clone());
+    enqueue_task(task.clone());
+
     schedule();
 
-    Ok(task)
+    task
+}
+
+/// Selects the CPU a runnable task is put on. This is the current CPU when
+/// the affinity of the task allows it, otherwise the online CPU from the
+/// affinity set with the fewest queued tasks.
+fn select_cpu(task: &TaskPointer) -> &'static PerCpuShared {
+    let this = this_cpu().shared;
+    if task.allowed_on_cpu(this.apic_id()) {
+        return this;
+    }
+
+    PERCPU_AREAS
+        .iter()
+        .filter(|cpu| cpu.is_online() && task.allowed_on_cpu(cpu.apic_id()))
+        .min_by_key(|cpu| cpu.runqueue().lock_read().nr_queued())
+        .expect("No online CPU in task affinity")
+}
+
+/// Asks the CPU with APIC ID `apic_id` to check whether a task on its
+/// run-queue should preempt the current one. A remote CPU is notified with
+/// an IPI, the current CPU checks at its next preemption point.
+fn kick_cpu(apic_id: u32) {
+    if apic_id == this_cpu_shared().apic_id() {
+        request_reschedule();
+    } else {
+        smp_send_reschedule(apic_id);
+    }
+}
+
+/// Puts a runnable task on the run-queue of a CPU it is allowed to run on
+/// and lets that CPU check whether the task should run right away.
+fn enqueue_task(mut task: TaskPointer) {
+    loop {
+        let cpu = select_cpu(&task);
+        let rejected = cpu.runqueue().lock_write().handle_task(task);
+        match rejected {
+            // The affinity of the task changed after the CPU was selected
+            Some(rejected) => task = rejected,
+            None => {
+                kick_cpu(cpu.apic_id());
+                return;
+            }
+        }
+    }
+}
+
+/// Changes the scheduling class of `task`. A runnable task is moved to the
+/// run list of its new class right away, and might preempt the task running
+/// on its CPU.
+pub fn set_task_priority(task: &TaskPointer, priority: TaskPriority) {
+    let apic_id = loop {
+        let Some(apic_id) = task.try_set_priority(priority) else {
+            // The task is not queued. If it is the current task, this CPU
+            // checks whether it is still the most important one to run.
+            break this_cpu_shared().apic_id();
+        };
+
+        let cpu = PERCPU_AREAS
+            .get(apic_id)
+            .expect("Task queued on unknown CPU");
+        if cpu.runqueue().lock_write().requeue(task, priority) {
+            break apic_id;
+        }
+        // The task was dequeued in the meantime, try again
+    };
+
+    kick_cpu(apic_id);
+}
+
+/// Moves a runnable task from the run-queue of another CPU to the current
+/// CPU. Only called when the current CPU has nothing else to run.
+///
+/// # Returns
+///
+/// `true` if a task was moved, `false` otherwise.
+fn pull_task() -> bool {
+    let this = this_cpu().shared;
+    let task = PERCPU_AREAS
+        .iter()
+        .filter(|cpu| cpu.apic_id() != this.apic_id() && cpu.is_online())
+        .find_map(|cpu| {
+            let mut rq = cpu.runqueue().lock_write();
+            if rq.nr_queued() > 0 {
+                rq.steal_task(this.apic_id())
+            } else {
+                None
+            }
+        });
+
+    let Some(task) = task else {
+        return false;
+    };
+
+    let rejected = this.runqueue().lock_write().handle_task(task);
+    match rejected {
+        // The affinity of the task changed after it was stolen
+        Some(task) => {
+            enqueue_task(task);
+            false
+        }
+        None => true,
+    }
+}
+
+/// Returns the id of the task scheduled on the current processor, or
+/// [`INITIAL_TASK_ID`] before the scheduler is initialized.
+pub fn current_task_id() -> u32 {
+    this_cpu().runqueue().lock_read().current_task_id()
 }
 
 /// Check to see if the task scheduled on the current processor has the given id
@@ -250,7 +550,7 @@ pub fn is_current_task(id: u32) -> bool {
 /// # Panics
 ///
 /// Panics if there is no current task.
-pub unsafe fn current_task_terminated() {
+unsafe fn current_task_terminated() {
     let cpu = this_cpu();
     let mut rq = cpu.runqueue().lock_write();
     let task_node = rq
@@ -260,6 +560,27 @@ pub unsafe fn current_task_terminated() {
     TASKLIST.lock().terminate(task_node.clone());
 }
 
+/// Terminates the current task and switches to the next one on the
+/// run-list. Tasks waiting in [`Task::join()`] are woken up and receive
+/// `exit_code`. The resources of the terminated task are released after the
+/// switch.
+///
+/// # Panics
+///
+/// Panics if there is no current task.
+pub fn exit_current_task(exit_code: i32) -> ! {
+    let joiners = current_task().set_exit_code(exit_code);
+    for task in joiners {
+        wake_task(task);
+    }
+
+    unsafe {
+        current_task_terminated();
+    }
+    schedule();
+    unreachable!("Terminated task was scheduled again");
+}
+
 // SAFETY: This function returns a raw pointer to a task. It is safe
 // because this function is only used in the task switch code, which also only
 // takes a single reference to the next and previous tasks. Also, this
@@ -288,9 +609,13 @@ unsafe fn switch_to(prev: *const Task, next: *const Task) {
 /// task and initialize the current_task field of the RunQueue. After this
 /// function has ran it is safe to call [schedule()] on the current CPU.
 pub fn schedule_init() {
+    // Balanced by schedule_tail() in the context of the first task
+    preempt_disable();
     unsafe {
-        let next = task_pointer(this_cpu_mut().schedule_init());
-        switch_to(null_mut(), next);
+        let next = this_cpu_mut().schedule_init();
+        next.set_on_cpu(true);
+        next.load_fpu_state();
+        switch_to(null_mut(), task_pointer(next));
     }
 }
 
@@ -298,6 +623,16 @@ pub fn schedule_init() {
 /// run-list. In case the current task is terminated, it will be destroyed after
 /// the switch to the next task.
 pub fn schedule() {
+    run_timers();
+
+    // The task switch must not be interrupted by another one. Preemption is
+    // enabled again in the context of the next task.
+    preempt_disable();
+
+    if this_cpu().runqueue().lock_read().is_idle() {
+        pull_task();
+    }
+
     let work = this_cpu_mut().schedule_prepare();
 
     // !!! Runqueue lock must be release here !!!
@@ -311,31 +646,140 @@ pub fn schedule() {
             this_cpu().populate_page_table(&mut pt);
         }
 
+        next.set_on_cpu(true);
+
         // Get task-pointers, consuming the Arcs and release their reference
         unsafe {
             let a = task_pointer(current);
             let b = task_pointer(next);
 
-            // Switch tasks
+            // Switch tasks. The task stack and page table must be switched
+            // with interrupts disabled.
+            let irq_guard = IrqGuard::new();
+            (*a).save_fpu_state();
+            (*b).load_fpu_state();
             switch_to(a, b);
+            drop(irq_guard);
         }
     }
 
+    schedule_tail();
+}
+
+/// Finishes a task switch in the context of the new task. This is called from
+/// [schedule()] and from the entry trampoline of newly created tasks.
+#[no_mangle]
+extern "C" fn schedule_tail() {
     // We're now in the context of the new task. If the previous task had terminated
-    // then we can release it's reference here.
-    let _ = this_cpu_mut()
-        .runqueue()
-        .lock_write()
-        .terminated_task
-        .take();
+    // then we can release it's resources and reference here.
+    let prev = this_cpu_mut().runqueue().lock_write().prev_task.take();
+    if let Some(task) = prev {
+        // The context of the previous task is saved, it can now be woken up
+        // on other CPUs.
+        task.set_on_cpu(false);
+        if task.is_terminated() {
+            reap_task(task);
+        } else if task.is_running()
+            && !task.is_idle_task()
+            && !task.allowed_on_cpu(this_cpu().get_apic_id())
+        {
+            // The affinity of the task changed, move it to another CPU
+            enqueue_task(task);
+        }
+    }
+
+    preempt_enable();
+}
+
+/// Reaper for terminated tasks. Frees the kernel stack, page-table and
+/// address space of the task right away, even when other references to the
+/// task (e.g. from a joining task) keep the [`Task`] itself alive.
+fn reap_task(task: TaskPointer) {
+    // SAFETY: The task has terminated and the CPU switched away from its
+    // stack and page-table, so nothing is using its resources anymore.
+    unsafe {
+        task.release_resources();
+    }
+}
+
+/// Accounts timer ticks which arrived on the current CPU to the current task.
+///
+/// # Returns
+///
+/// `true` if the current task needs to be preempted, `false` otherwise.
+pub(super) fn schedule_tick_pending(ticks: u32) -> bool {
+    let cpu = this_cpu();
+    let mut rq = cpu.runqueue().lock_write();
+    if rq.tick(ticks) {
+        return true;
+    }
+
+    // An idle CPU looks for work on other CPUs
+    let idle = rq.is_idle();
+    drop(rq);
+    idle && pull_task()
 }
 
 pub fn schedule_task(task: TaskPointer) {
-    task.set_task_running();
-    this_cpu().runqueue().lock_write().handle_task(task);
+    wake_task(task);
     schedule();
 }
 
+/// Returns whether `task` is the current task of this CPU.
+fn is_current_task_ptr(task: &TaskPointer) -> bool {
+    this_cpu()
+        .runqueue()
+        .lock_read()
+        .current_task
+        .as_ref()
+        .is_some_and(|current| Arc::ptr_eq(current, task))
+}
+
+/// Makes a blocked task runnable again by putting it on the run-queue of the
+/// current CPU, or of another CPU if its affinity requires it, without
+/// scheduling it right away. Nothing happens when the task is not blocked
+/// (anymore).
+///
+/// A task which blocked on another CPU might not have finished switching out
+/// yet. In this case the function waits until its context is saved, so it
+/// must not be called from interrupt context. A task which is still the
+/// current task of this CPU, because its timer expired before it called
+/// [`schedule()`], only needs its state to be updated. It puts itself back
+/// on the run-queue when it switches out.
+pub fn wake_task(task: TaskPointer) {
+    let is_current = is_current_task_ptr(&task);
+
+    if !is_current {
+        while task.is_on_cpu() {
+            spin_loop();
+        }
+    }
+
+    if task.wake_up() && !is_current {
+        enqueue_task(task);
+    }
+}
+
+/// Like [`wake_task()`], but does not wait for a task which is still
+/// switching out on another CPU. Can be called from interrupt context.
+///
+/// # Returns
+///
+/// `false` if the task is still switching out and was not woken up, `true`
+/// otherwise.
+pub(super) fn try_wake_task(task: &TaskPointer) -> bool {
+    let is_current = is_current_task_ptr(task);
+
+    if !is_current && task.is_on_cpu() {
+        return false;
+    }
+
+    if task.wake_up() && !is_current {
+        enqueue_task(task.clone());
+    }
+    true
+}
+
 global_asm!(
     r#"
         .text
@@ -394,6 +838,78 @@ global_asm!(
         popfq
 
         ret
+
+        // Entry point of newly created tasks. The stack layout is set up in
+        // Task::allocate_stack() and contains the task entry point followed
+        // by the task exit handler.
+        .globl  task_start
+    task_start:
+        call    schedule_tail
+        ret
     "#,
     options(att_syntax)
 );
+
+extern "C" {
+    pub(super) fn task_start();
+}
+
+/// Runs a new kernel task executing `entry` until it blocks, then calls
+/// `wake` to make it runnable again. Used to test blocking primitives.
+///
+/// # Returns
+///
+/// The exit code of the task.
+#[cfg(test)]
+pub fn run_blocked_task<F>(entry: extern "C" fn(), wake: F) -> i32
+where
+    F: FnOnce(&TaskPointer),
+{
+    let task = create_kernel_task(entry, 0).expect("Failed to create task");
+    while task.is_running() {
+        schedule();
+    }
+    wake(&task);
+    task.join()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::alloc::memory_info;
+
+    const TEST_EXIT_CODE: i32 = 42;
+
+    extern "C" fn test_task_return() {}
+
+    extern "C" fn test_task_exit() {
+        exit_current_task(TEST_EXIT_CODE);
+    }
+
+    fn create_and_join(count: usize) {
+        for i in 0..count {
+            let (entry, expected): (extern "C" fn(), i32) = if i % 2 == 0 {
+                (test_task_return, 0)
+            } else {
+                (test_task_exit, TEST_EXIT_CODE)
+            };
+            let task = create_kernel_task(entry, 0).expect("Failed to create task");
+            assert_eq!(task.join(), expected);
+            assert!(task.is_terminated());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(test_in_svsm), ignore = "Can only be run inside the SVSM")]
+    fn test_join_does_not_leak() {
+        // Warm up the heap so that slab pages allocated for the first tasks
+        // are not counted as leaked.
+        create_and_join(4);
+
+        let free_before = memory_info().free_pages_4k();
+        create_and_join(64);
+        let free_after = memory_info().free_pages_4k();
+
+        assert_eq!(free_before, free_after);
+    }
+}
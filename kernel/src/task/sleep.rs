@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Sleeping and timeouts for tasks.
+//!
+//! A task which needs to be woken up at a deadline arms a timer on the
+//! per-CPU [`TimerWheel`](crate::time::TimerWheel) of the CPU it runs on.
+//! Timers are not removed from the wheel when the task is woken up earlier,
+//! as the task may continue on another CPU by then. Instead the task
+//! increments its timer sequence number, which turns the timer stale, and
+//! stale timers are ignored when they expire. Timers only hold a weak
+//! reference to their task, so that stale timers do not keep exited tasks
+//! alive.
+
+extern crate alloc;
+
+use super::preempt::preemptible;
+use super::schedule::{schedule, try_wake_task};
+use super::tasks::Task;
+use crate::cpu::percpu::{current_task, this_cpu_mut};
+use crate::time::{Duration, Instant};
+use alloc::sync::{Arc, Weak};
+
+/// A timer armed by a task
+#[derive(Debug)]
+pub struct TaskTimer {
+    /// Task to wake up
+    task: Weak<Task>,
+    /// Timer sequence number of the task when the timer was armed
+    seq: u64,
+}
+
+/// Switches away from the current task, which must already be in BLOCKED
+/// state, and makes it runnable again at `deadline` at the latest.
+///
+/// # Returns
+///
+/// `true` if the deadline has passed, `false` if the task was woken up
+/// before.
+pub fn schedule_timeout(deadline: Instant) -> bool {
+    let task = current_task();
+    let seq = task.arm_timer();
+    this_cpu_mut().add_timer(
+        deadline,
+        TaskTimer {
+            task: Arc::downgrade(&task),
+            seq,
+        },
+    );
+
+    schedule();
+
+    task.cancel_timer();
+    Instant::now() >= deadline
+}
+
+/// Puts the current task to sleep until `deadline`.
+pub fn sleep_until(deadline: Instant) {
+    let task = current_task();
+    while Instant::now() < deadline {
+        task.set_task_blocked();
+        schedule_timeout(deadline);
+    }
+}
+
+/// Puts the current task to sleep for at least `duration`.
+pub fn sleep(duration: Duration) {
+    sleep_until(Instant::now() + duration);
+}
+
+/// Wakes up the tasks whose timers expired on the current CPU. Does nothing
+/// when called in a non-preemptible section, as waking up a task needs to
+/// take the run-queue lock.
+///
+/// This also runs from the timer interrupt, so it must not wait for tasks
+/// which are still switching out on another CPU. Their timers are retried
+/// on the next tick instead.
+///
+/// # Returns
+///
+/// `true` if any task was woken up, `false` otherwise.
+pub(super) fn run_timers() -> bool {
+    if !preemptible() {
+        return false;
+    }
+
+    let now = Instant::now();
+    let expired = this_cpu_mut().expire_timers(now);
+    let mut woken = false;
+    for timer in expired {
+        let Some(task) = timer.task.upgrade() else {
+            continue;
+        };
+        if !task.timer_armed(timer.seq) {
+            continue;
+        }
+        if try_wake_task(&task) {
+            woken = true;
+        } else {
+            this_cpu_mut().add_timer(now, timer);
+        }
+    }
+    woken
+}
+
+/// Runs the expired timers of the current CPU and switches to the tasks
+/// they woke up. Timers are normally driven by the APIC timer interrupt,
+/// which is not delivered with restricted injection. The request loop calls
+/// this whenever it gets control back from the guest, so that sleeping
+/// tasks wake up in this case as well.
+pub fn poll_timers() {
+    if run_timers() {
+        schedule();
+    }
+}
@@ -7,23 +7,35 @@
 extern crate alloc;
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
 use core::fmt;
-use core::mem::size_of;
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::mem::{replace, size_of};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 
 use crate::address::{Address, VirtAddr};
+use crate::cpu::cpuset::CpuSet;
+use crate::cpu::fpu::{fpu_disable_access, fpu_enable_access, XSaveArea};
+use crate::cpu::irq_state::EFLAGS_IF;
 use crate::cpu::msr::read_flags;
-use crate::cpu::percpu::PerCpu;
+use crate::cpu::percpu::{current_task, PerCpu, PERCPU_AREAS};
 use crate::cpu::X86GeneralRegs;
 use crate::error::SvsmError;
+use crate::fs::FileHandle;
 use crate::locking::{RWLock, SpinLock};
 use crate::mm::pagetable::{get_init_pgtable_locked, PTEntryFlags, PageTableRef};
 use crate::mm::vm::{Mapping, VMKernelStack, VMR};
-use crate::mm::{SVSM_PERTASK_BASE, SVSM_PERTASK_END, SVSM_PERTASK_STACK_BASE};
+use crate::mm::{
+    SVSM_PERTASK_BASE, SVSM_PERTASK_END, SVSM_PERTASK_STACK_BASE, USER_MEM_END, USER_MEM_START,
+};
+use crate::time::{Duration, Instant};
+use crate::types::{SVSM_USER_CS, SVSM_USER_DS};
 use crate::utils::MemoryRegion;
 use intrusive_collections::{intrusive_adapter, LinkedListAtomicLink};
 
-use super::schedule::{current_task_terminated, schedule};
+use super::exec::UserImage;
+use super::schedule::{exit_current_task, schedule, task_start};
+use super::sleep::schedule_timeout;
 
 pub const INITIAL_TASK_ID: u32 = 1;
 
@@ -41,6 +53,14 @@ pub enum TaskError {
     NotTerminated,
     // A closed task could not be removed from the task list
     CloseFailed,
+    // The executable of a user task has an unsupported memory layout
+    InvalidImage,
+    // Another task is already waiting on a single-waiter wait queue
+    Busy,
+    // The affinity mask of a task contains no online CPU
+    InvalidAffinity,
+    // A task which was not created with TASK_FLAG_FPU used SIMD instructions
+    FpuNotAllowed,
 }
 
 impl From<TaskError> for SvsmError {
@@ -50,6 +70,42 @@ impl From<TaskError> for SvsmError {
 }
 
 pub const TASK_FLAG_SHARE_PT: u16 = 0x01;
+/// Create the task in the [`TaskPriority::RealTime`] class
+pub const TASK_FLAG_REALTIME: u16 = 0x02;
+/// Allow the task to use FPU, SSE and AVX instructions. User tasks are
+/// always allowed to use them.
+pub const TASK_FLAG_FPU: u16 = 0x04;
+
+/// Scheduling class of a task. Runnable tasks of a higher class always run
+/// before tasks of a lower class, tasks of the same class share the CPU
+/// round-robin.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TaskPriority {
+    /// Background work
+    #[default]
+    Normal,
+    /// Latency sensitive work like guest request processing
+    RealTime,
+}
+
+/// Number of scheduling classes
+pub const NR_TASK_PRIORITIES: usize = 2;
+
+impl TaskPriority {
+    /// All scheduling classes, from the highest to the lowest
+    pub const ALL: [TaskPriority; NR_TASK_PRIORITIES] =
+        [TaskPriority::RealTime, TaskPriority::Normal];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Exit code of tasks killed by the kernel, e.g. after an unhandled exception
+pub const TASK_EXIT_KILLED: i32 = -1;
+
+/// Maximum number of files a task can have open at the same time
+pub const TASK_MAX_FILES: usize = 64;
 
 #[derive(Debug, Default)]
 struct TaskIDAllocator {
@@ -94,6 +150,15 @@ struct TaskSchedState {
 
     /// CPU this task is currently assigned to
     cpu: u32,
+
+    /// CPUs the task is allowed to run on
+    affinity: CpuSet,
+
+    /// Scheduling class of the task
+    priority: TaskPriority,
+
+    /// APIC ID of the CPU whose run-queue holds the task, if any
+    queued_on: Option<u32>,
 }
 
 impl TaskSchedState {
@@ -105,6 +170,17 @@ impl TaskSchedState {
     }
 }
 
+/// Exit state of a task, protected by a single lock so that joining tasks
+/// can not miss the termination of the task.
+#[derive(Debug, Default)]
+struct TaskExitState {
+    /// Exit code, set when the task terminates
+    exit_code: Option<i32>,
+
+    /// Tasks blocked in [`Task::join()`] on this task
+    joiners: Vec<TaskPointer>,
+}
+
 #[repr(C)]
 pub struct Task {
     pub rsp: u64,
@@ -117,9 +193,36 @@ pub struct Task {
     /// Task virtual memory range for use at CPL 0
     vm_kernel_range: VMR,
 
+    /// Task virtual memory range for use at CPL 3 - None for kernel tasks
+    vm_user_range: Option<VMR>,
+
+    /// User-space entry point and initial stack pointer of user tasks
+    user_entry: Option<(VirtAddr, VirtAddr)>,
+
+    /// Files opened by the task, indexed by their file descriptor
+    files: SpinLock<Vec<Option<Arc<FileHandle>>>>,
+
+    /// Whether the task may use SIMD instructions
+    fpu_allowed: bool,
+
+    /// Extended register state, allocated when the task first uses SIMD
+    /// instructions. Only accessed with interrupts disabled.
+    fpu_state: SpinLock<Option<XSaveArea>>,
+
     /// State relevant for scheduler
     sched_state: RWLock<TaskSchedState>,
 
+    /// Whether the context of the task is live on a CPU, set from the moment
+    /// a CPU switches to the task until its context is saved again
+    on_cpu: AtomicBool,
+
+    /// Sequence number of the current timer of the task. Timers armed with
+    /// an older sequence number are stale and ignored when they expire.
+    timer_seq: AtomicU64,
+
+    /// Exit code and joining tasks
+    exit_state: SpinLock<TaskExitState>,
+
     /// ID of the task
     id: u32,
 
@@ -128,6 +231,9 @@ pub struct Task {
 
     /// Link to scheduler run queue
     runlist_link: LinkedListAtomicLink,
+
+    /// Link to the wait queue the task is blocked on
+    waitlist_link: LinkedListAtomicLink,
 }
 
 // SAFETY: Send + Sync is required for Arc<Task> to implement Send. All members
@@ -140,6 +246,7 @@ pub type TaskPointer = Arc<Task>;
 
 intrusive_adapter!(pub TaskRunListAdapter = TaskPointer: Task { runlist_link: LinkedListAtomicLink });
 intrusive_adapter!(pub TaskListAdapter = TaskPointer: Task { list_link: LinkedListAtomicLink });
+intrusive_adapter!(pub TaskWaitListAdapter = TaskPointer: Task { waitlist_link: LinkedListAtomicLink });
 
 impl PartialEq for Task {
     fn eq(&self, other: &Self) -> bool {
@@ -162,6 +269,21 @@ impl Task {
         cpu: &mut PerCpu,
         entry: extern "C" fn(),
         flags: u16,
+    ) -> Result<TaskPointer, SvsmError> {
+        Self::create_task(cpu, entry, flags, None)
+    }
+
+    /// Create a task which runs the given [`UserImage`] at CPL 3. The task
+    /// gets a private page table with the user-space range of the image.
+    pub fn create_user(cpu: &mut PerCpu, image: UserImage) -> Result<TaskPointer, SvsmError> {
+        Self::create_task(cpu, user_task_entry, 0, Some(image))
+    }
+
+    fn create_task(
+        cpu: &mut PerCpu,
+        entry: extern "C" fn(),
+        flags: u16,
+        user_image: Option<UserImage>,
     ) -> Result<TaskPointer, SvsmError> {
         let mut pgtable = if (flags & TASK_FLAG_SHARE_PT) != 0 {
             cpu.get_pgtable().clone_shared()?
@@ -180,6 +302,16 @@ impl Task {
 
         vm_kernel_range.populate(&mut pgtable);
 
+        let fpu_allowed = (flags & TASK_FLAG_FPU) != 0 || user_image.is_some();
+
+        let (vm_user_range, user_entry) = match user_image {
+            Some(image) => {
+                image.vm_range.populate(&mut pgtable);
+                (Some(image.vm_range), Some((image.entry, image.stack)))
+            }
+            None => (None, None),
+        };
+
         // Remap at the per-task offset
         let bounds = MemoryRegion::new(
             SVSM_PERTASK_STACK_BASE + raw_bounds.start().into(),
@@ -195,14 +327,30 @@ impl Task {
             stack_bounds: bounds,
             page_table: SpinLock::new(pgtable),
             vm_kernel_range,
+            vm_user_range,
+            user_entry,
+            files: SpinLock::new(Vec::new()),
+            fpu_allowed,
+            fpu_state: SpinLock::new(None),
             sched_state: RWLock::new(TaskSchedState {
                 idle_task: false,
                 state: TaskState::RUNNING,
                 cpu: cpu.get_apic_id(),
+                affinity: CpuSet::single(cpu.get_apic_id()),
+                priority: if (flags & TASK_FLAG_REALTIME) != 0 {
+                    TaskPriority::RealTime
+                } else {
+                    TaskPriority::Normal
+                },
+                queued_on: None,
             }),
+            on_cpu: AtomicBool::new(false),
+            timer_seq: AtomicU64::new(0),
+            exit_state: SpinLock::new(TaskExitState::default()),
             id: TASK_ID_ALLOCATOR.next_id(),
             list_link: LinkedListAtomicLink::default(),
             runlist_link: LinkedListAtomicLink::default(),
+            waitlist_link: LinkedListAtomicLink::default(),
         }))
     }
 
@@ -214,6 +362,22 @@ impl Task {
         self.id
     }
 
+    /// Puts a BLOCKED task back into RUNNING state.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the task was blocked, `false` if it was already woken up
+    /// or terminated.
+    pub fn wake_up(&self) -> bool {
+        let mut state = self.sched_state.lock_write();
+        if state.state == TaskState::BLOCKED {
+            state.state = TaskState::RUNNING;
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn set_task_running(&self) {
         self.sched_state.lock_write().state = TaskState::RUNNING;
     }
@@ -255,10 +419,328 @@ impl Task {
         old_cpu
     }
 
+    /// Returns the set of CPUs the task is allowed to run on.
+    pub fn affinity(&self) -> CpuSet {
+        self.sched_state.lock_read().affinity
+    }
+
+    /// Sets the CPUs the task is allowed to run on. A runnable task which is
+    /// not allowed on its current CPU anymore is moved to another CPU the
+    /// next time it is switched out.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or `Err(SvsmError::Task(TaskError::InvalidAffinity))`
+    /// if `affinity` contains no online CPU.
+    pub fn set_affinity(&self, affinity: CpuSet) -> Result<(), SvsmError> {
+        if !PERCPU_AREAS
+            .iter()
+            .any(|cpu| cpu.is_online() && affinity.contains(cpu.apic_id()))
+        {
+            return Err(TaskError::InvalidAffinity.into());
+        }
+
+        self.sched_state
+            .lock_write()
+            .panic_on_idle("Trying to change affinity of idle task")
+            .affinity = affinity;
+        Ok(())
+    }
+
+    pub fn priority(&self) -> TaskPriority {
+        self.sched_state.lock_read().priority
+    }
+
+    /// Records that the task was put on the run-queue of the CPU with APIC
+    /// ID `apic_id`. Must be called with that run-queue locked.
+    ///
+    /// # Returns
+    ///
+    /// The priority the task needs to be queued with.
+    pub(super) fn mark_queued(&self, apic_id: u32) -> TaskPriority {
+        let mut state = self.sched_state.lock_write();
+        state.queued_on = Some(apic_id);
+        state.priority
+    }
+
+    /// Records that the task was taken off a run-queue. Must be called with
+    /// that run-queue locked.
+    pub(super) fn mark_dequeued(&self) {
+        self.sched_state.lock_write().queued_on = None;
+    }
+
+    /// Changes the priority of a task which is not on any run-queue.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the priority was changed, or the APIC ID of the CPU whose
+    /// run-queue holds the task. The priority needs to be changed with
+    /// [`Task::set_queued_priority()`] under the run-queue lock then.
+    pub(super) fn try_set_priority(&self, priority: TaskPriority) -> Option<u32> {
+        let mut state = self.sched_state.lock_write();
+        if state.queued_on.is_none() {
+            state.priority = priority;
+        }
+        state.queued_on
+    }
+
+    /// Changes the priority of a task on the run-queue of the CPU with APIC
+    /// ID `apic_id`. Must be called with that run-queue locked.
+    ///
+    /// # Returns
+    ///
+    /// The previous priority, or `None` if the task is not on that run-queue
+    /// (anymore).
+    pub(super) fn set_queued_priority(
+        &self,
+        apic_id: u32,
+        priority: TaskPriority,
+    ) -> Option<TaskPriority> {
+        let mut state = self.sched_state.lock_write();
+        if state.queued_on != Some(apic_id) {
+            return None;
+        }
+        Some(replace(&mut state.priority, priority))
+    }
+
+    /// Returns whether the task is allowed to run on the CPU with APIC ID
+    /// `apic_id`.
+    pub fn allowed_on_cpu(&self, apic_id: u32) -> bool {
+        self.sched_state.lock_read().affinity.contains(apic_id)
+    }
+
+    pub fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
+    pub fn is_on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    /// Returns the sequence number for a new timer of the task. Any
+    /// previously armed timer becomes stale.
+    pub fn arm_timer(&self) -> u64 {
+        self.timer_seq.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Makes the currently armed timer of the task stale.
+    pub fn cancel_timer(&self) {
+        self.timer_seq.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns whether the timer with sequence number `seq` is still armed.
+    pub fn timer_armed(&self, seq: u64) -> bool {
+        self.timer_seq.load(Ordering::Relaxed) == seq
+    }
+
+    /// Saves the extended register state of the task before the CPU switches
+    /// away from it. Must be called with interrupts disabled.
+    pub(super) fn save_fpu_state(&self) {
+        if let Some(area) = self.fpu_state.lock().as_mut() {
+            area.save();
+        }
+    }
+
+    /// Loads the extended register state of the task when the CPU switches
+    /// to it. SIMD instructions trap with #NM if the task has no state yet.
+    /// Must be called with interrupts disabled.
+    pub(super) fn load_fpu_state(&self) {
+        match self.fpu_state.lock().as_ref() {
+            Some(area) => {
+                fpu_enable_access();
+                area.restore();
+            }
+            None => fpu_disable_access(),
+        }
+    }
+
+    /// Handles the first SIMD instruction of the task, which raised #NM.
+    /// Allocates the extended register state of the task and makes it
+    /// available on the current CPU.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the task can continue, or an [`SvsmError`] if it is not
+    /// allowed to use SIMD instructions or the state could not be allocated.
+    pub fn handle_fpu_trap(&self) -> Result<(), SvsmError> {
+        if !self.fpu_allowed {
+            return Err(TaskError::FpuNotAllowed.into());
+        }
+
+        let mut state = self.fpu_state.lock();
+        if state.is_none() {
+            *state = Some(XSaveArea::new()?);
+        }
+        fpu_enable_access();
+        if let Some(area) = state.as_ref() {
+            area.restore();
+        }
+        Ok(())
+    }
+
+    /// Returns whether the task is linked into a [`WaitQueue`](super::WaitQueue).
+    pub fn is_waiting(&self) -> bool {
+        self.waitlist_link.is_linked()
+    }
+
+    /// Records the exit code of the task and returns the tasks which joined
+    /// it, so that they can be woken up.
+    pub fn set_exit_code(&self, exit_code: i32) -> Vec<TaskPointer> {
+        let mut exit_state = self.exit_state.lock();
+        exit_state.exit_code = Some(exit_code);
+        core::mem::take(&mut exit_state.joiners)
+    }
+
+    /// Returns the exit code of the task, or `None` if it did not terminate
+    /// yet.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_state.lock().exit_code
+    }
+
+    /// Waits for the task to terminate. The current task is blocked until
+    /// then.
+    ///
+    /// # Returns
+    ///
+    /// The exit code of the task.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called for the current task or when there is no current
+    /// task.
+    pub fn join(&self) -> i32 {
+        let current = current_task();
+        assert!(*current != *self, "Task tried to join itself");
+
+        loop {
+            let mut exit_state = self.exit_state.lock();
+            if let Some(exit_code) = exit_state.exit_code {
+                return exit_code;
+            }
+            current.set_task_blocked();
+            exit_state.joiners.push(current.clone());
+            drop(exit_state);
+
+            schedule();
+        }
+    }
+
+    /// Waits for the task to terminate, but at most for `timeout`.
+    ///
+    /// # Returns
+    ///
+    /// The exit code of the task, or `None` if it did not terminate in time.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called for the current task or when there is no current
+    /// task.
+    pub fn join_timeout(&self, timeout: Duration) -> Option<i32> {
+        let deadline = Instant::now() + timeout;
+        let current = current_task();
+        assert!(*current != *self, "Task tried to join itself");
+
+        loop {
+            let mut exit_state = self.exit_state.lock();
+            if let Some(exit_code) = exit_state.exit_code {
+                return Some(exit_code);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            current.set_task_blocked();
+            exit_state.joiners.push(current.clone());
+            drop(exit_state);
+
+            schedule_timeout(deadline);
+
+            // Drop the joiner entry in case the timer woke the task up
+            self.exit_state
+                .lock()
+                .joiners
+                .retain(|task| !Arc::ptr_eq(task, &current));
+        }
+    }
+
+    /// Releases the kernel stack, page-table, address space and open files
+    /// of a terminated task. The task structure itself stays around as long
+    /// as there are references to it, so that the exit code can still be
+    /// retrieved.
+    ///
+    /// # Safety
+    ///
+    /// The task must be terminated and must not be running on any CPU
+    /// anymore.
+    pub unsafe fn release_resources(&self) {
+        debug_assert!(self.is_terminated());
+
+        self.files.lock().clear();
+        *self.fpu_state.lock() = None;
+        if let Some(vmr) = self.vm_user_range.as_ref() {
+            vmr.clear();
+        }
+        self.vm_kernel_range.clear();
+        // SAFETY: The task is not running anymore, so its page-table is not
+        // loaded on any CPU.
+        unsafe {
+            self.page_table.lock().free();
+        }
+    }
+
     pub fn handle_pf(&self, vaddr: VirtAddr, write: bool) -> Result<(), SvsmError> {
         self.vm_kernel_range.handle_page_fault(vaddr, write)
     }
 
+    /// Returns whether this task runs code at CPL 3
+    pub fn is_user_task(&self) -> bool {
+        self.vm_user_range.is_some()
+    }
+
+    /// Returns the user-space [`VMR`] of the task, or `None` for kernel tasks
+    pub fn user_vm_range(&self) -> Option<&VMR> {
+        self.vm_user_range.as_ref()
+    }
+
+    /// Adds an open file to the file table of the task.
+    ///
+    /// # Returns
+    ///
+    /// The file descriptor for the file, or `None` if the task already has
+    /// [`TASK_MAX_FILES`] files open.
+    pub fn add_file(&self, file: FileHandle) -> Option<usize> {
+        let mut files = self.files.lock();
+        let fd = match files.iter().position(|f| f.is_none()) {
+            Some(fd) => fd,
+            None if files.len() < TASK_MAX_FILES => {
+                files.push(None);
+                files.len() - 1
+            }
+            None => return None,
+        };
+        files[fd] = Some(Arc::new(file));
+        Some(fd)
+    }
+
+    /// Looks up an open file by its file descriptor
+    pub fn get_file(&self, fd: usize) -> Option<Arc<FileHandle>> {
+        self.files.lock().get(fd)?.clone()
+    }
+
+    /// Removes a file from the file table of the task. The file is closed
+    /// when the last reference to it is dropped.
+    pub fn remove_file(&self, fd: usize) -> Option<Arc<FileHandle>> {
+        self.files.lock().get_mut(fd)?.take()
+    }
+
+    /// Handle a page fault on an address in the user-space range of the
+    /// task. Fails for kernel tasks.
+    pub fn handle_user_pf(&self, vaddr: VirtAddr, write: bool) -> Result<(), SvsmError> {
+        self.vm_user_range
+            .as_ref()
+            .ok_or(SvsmError::Mem)?
+            .handle_page_fault(vaddr, write)
+    }
+
     fn allocate_stack(
         cpu: &mut PerCpu,
         entry: extern "C" fn(),
@@ -276,14 +758,20 @@ impl Task {
         // 'Push' the task frame onto the stack
         unsafe {
             // flags
-            stack_ptr.offset(-3).write(read_flags());
-            // ret_addr
+            stack_ptr.offset(-4).write(read_flags());
+            // ret_addr - finishes the task switch before calling entry
+            stack_ptr.offset(-3).write(task_start as *const () as u64);
+            // Task entry point
             stack_ptr.offset(-2).write(entry as *const () as u64);
             // Task termination handler for when entry point returns
             stack_ptr.offset(-1).write(task_exit as *const () as u64);
         }
 
-        Ok((mapping, bounds, size_of::<TaskContext>() + size_of::<u64>()))
+        Ok((
+            mapping,
+            bounds,
+            size_of::<TaskContext>() + 2 * size_of::<u64>(),
+        ))
     }
 
     fn allocate_page_table() -> Result<PageTableRef, SvsmError> {
@@ -294,9 +782,58 @@ impl Task {
     }
 }
 
+/// Returns whether `vaddr` is in the user-space part of the address space
+pub fn is_user_addr(vaddr: VirtAddr) -> bool {
+    vaddr >= USER_MEM_START && vaddr < USER_MEM_END
+}
+
 extern "C" fn task_exit() {
+    exit_current_task(0);
+}
+
+/// Kernel entry point of user tasks, which drops to CPL 3 and starts
+/// executing the user-space image.
+extern "C" fn user_task_entry() {
+    let (entry, stack) = current_task()
+        .user_entry
+        .expect("User task entry called for a kernel task");
+    // Keep the interrupt state of the kernel side of the task
+    let flags = (read_flags() & EFLAGS_IF) | 0x2;
+
+    // SAFETY: The user-space range of the task is mapped in the current page
+    // table and the segment selectors are valid GDT entries with DPL 3.
+    // Registers are cleared to not leak kernel data into user-space.
     unsafe {
-        current_task_terminated();
+        asm!(
+            r#"
+            pushq   {ss}
+            pushq   {rsp}
+            pushq   {flags}
+            pushq   {cs}
+            pushq   {rip}
+            xorl    %eax, %eax
+            xorl    %ebx, %ebx
+            xorl    %ecx, %ecx
+            xorl    %edx, %edx
+            xorl    %esi, %esi
+            xorl    %edi, %edi
+            xorl    %ebp, %ebp
+            xorl    %r8d, %r8d
+            xorl    %r9d, %r9d
+            xorl    %r10d, %r10d
+            xorl    %r11d, %r11d
+            xorl    %r12d, %r12d
+            xorl    %r13d, %r13d
+            xorl    %r14d, %r14d
+            xorl    %r15d, %r15d
+            iretq
+            "#,
+            ss = in(reg) u64::from(SVSM_USER_DS | 3),
+            rsp = in(reg) stack.bits() as u64,
+            flags = in(reg) flags,
+            cs = in(reg) u64::from(SVSM_USER_CS | 3),
+            rip = in(reg) entry.bits() as u64,
+            options(att_syntax, noreturn)
+        );
     }
-    schedule();
 }
@@ -4,26 +4,69 @@
 //
 // Author: Joerg Roedel <jroedel@suse.de>
 
-use super::tasks::TaskPointer;
+use super::tasks::{TaskPointer, TaskWaitListAdapter};
+use intrusive_collections::LinkedList;
 
+/// Queue of tasks blocked on an event. Tasks are woken up in the order they
+/// started waiting.
+///
+/// A task can wait on at most one queue at a time. Tasks waiting with a
+/// timeout need to [`remove`](WaitQueue::remove) themselves from the queue
+/// after they were woken up, as their timer might have fired first.
 #[derive(Debug)]
 pub struct WaitQueue {
-    waiter: Option<TaskPointer>,
+    waiters: Option<LinkedList<TaskWaitListAdapter>>,
 }
 
 impl WaitQueue {
     pub const fn new() -> Self {
-        Self { waiter: None }
+        Self { waiters: None }
     }
 
-    pub fn wait_for_event(&mut self, current_task: TaskPointer) {
-        assert!(self.waiter.is_none());
+    fn waiters(&mut self) -> &mut LinkedList<TaskWaitListAdapter> {
+        self.waiters
+            .get_or_insert_with(|| LinkedList::new(TaskWaitListAdapter::new()))
+    }
 
+    /// Puts `current_task` into BLOCKED state and adds it to the queue. The
+    /// caller needs to call [`schedule()`](super::schedule) afterwards,
+    /// without holding the lock protecting the queue.
+    pub fn wait_for_event(&mut self, current_task: TaskPointer) {
         current_task.set_task_blocked();
-        self.waiter = Some(current_task);
+        self.waiters().push_back(current_task);
     }
 
+    pub fn has_waiter(&self) -> bool {
+        self.waiters.as_ref().is_some_and(|list| !list.is_empty())
+    }
+
+    /// Removes the longest waiting task from the queue. The caller needs to
+    /// make it runnable via [`wake_task()`](super::wake_task) after
+    /// releasing the lock protecting the queue.
     pub fn wakeup(&mut self) -> Option<TaskPointer> {
-        self.waiter.take()
+        self.waiters.as_mut()?.pop_front()
+    }
+
+    /// Removes `task` from the queue.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the task was still waiting on the queue, `false` if it was
+    /// already woken up.
+    pub fn remove(&mut self, task: &TaskPointer) -> bool {
+        if !task.is_waiting() {
+            return false;
+        }
+
+        // SAFETY: A task waits on at most one queue, so a linked task is
+        // linked into this queue.
+        let mut cursor = unsafe { self.waiters().cursor_mut_from_ptr(task.as_ref()) };
+        cursor.remove().is_some()
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -29,6 +29,9 @@ pub fn svsm_test_runner(test_cases: &[&test::TestDescAndFn]) {
 
     info!("All tests passed!");
 
+    #[cfg(feature = "heap-debug")]
+    crate::mm::heap_debug::print_heap_leaks();
+
     exit();
 }
 
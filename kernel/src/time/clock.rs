@@ -0,0 +1,313 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+use crate::cpu::cpuid::{cpuid_table, CpuidResult};
+use crate::cpu::msr::{rdtsc, read_msr, MSR_GUEST_TSC_FREQ};
+use crate::error::SvsmError;
+use crate::io::IOPort;
+use crate::sev::secrets_page::secrets_page;
+use crate::sev::status::{sev_flags, SEVStatusFlags};
+use crate::svsm_console::SVSMIOPort;
+use crate::utils::immut_after_init::ImmutAfterInitCell;
+use core::hint::spin_loop;
+use core::ops::{Add, Sub};
+use core::time::Duration;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+const NSEC_PER_MSEC: u64 = 1_000_000;
+
+/// The TSC frequency in MHz is reported in bits 17:0 of the guest TSC
+/// frequency MSR.
+const GUEST_TSC_FREQ_MASK: u64 = (1 << 18) - 1;
+
+// PIT ports and input clock frequency
+const PIT_CH2_PORT: u16 = 0x42;
+const PIT_CMD_PORT: u16 = 0x43;
+const PIT_PORT_B: u16 = 0x61;
+const PIT_FREQ_HZ: u64 = 1_193_182;
+
+// Port B bits to control and monitor PIT channel 2
+const PORT_B_CH2_GATE: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_CH2_OUT: u8 = 1 << 5;
+
+/// PIT command: channel 2, low/high byte access, mode 0, binary counter
+const PIT_CMD_CH2_ONESHOT: u8 = 0xb0;
+
+/// Duration of the TSC calibration against the PIT in milliseconds
+const CALIBRATE_MS: u64 = 10;
+
+/// Lowest plausible TSC frequency, used to detect a missing PIT
+const MIN_TSC_KHZ: u64 = 100_000;
+
+/// Highest plausible TSC frequency, bounds the wait for the PIT
+const MAX_TSC_KHZ: u64 = 10_000_000;
+
+/// CPUID leaf reporting the ratio of the TSC and core crystal clock
+const CPUID_TSC_CRYSTAL: u32 = 0x15;
+
+/// CPUID leaf reporting the processor base frequency in MHz
+const CPUID_PROC_FREQ: u32 = 0x16;
+
+/// Hypervisor CPUID leaf with the highest supported hypervisor leaf in EAX
+const CPUID_HV_MAX: u32 = 0x4000_0000;
+
+/// Hypervisor CPUID leaf reporting the TSC frequency in kHz in EAX
+const CPUID_HV_TIMING: u32 = 0x4000_0010;
+
+/// TSC frequency assumed when no source reports a usable value
+const FALLBACK_TSC_KHZ: u64 = 1_000_000;
+
+#[derive(Clone, Copy, Debug)]
+struct ClockSource {
+    /// TSC frequency in kHz, zero until the clock is initialized
+    tsc_khz: u64,
+    /// TSC value at clock initialization, which is time zero
+    tsc_base: u64,
+}
+
+static CLOCK: ImmutAfterInitCell<ClockSource> = ImmutAfterInitCell::new(ClockSource {
+    tsc_khz: 0,
+    tsc_base: 0,
+});
+
+/// Returns the TSC frequency in kHz reported by the Secure TSC interface.
+fn secure_tsc_khz() -> u64 {
+    let mhz = read_msr(MSR_GUEST_TSC_FREQ) & GUEST_TSC_FREQ_MASK;
+    let khz = mhz * 1000;
+    let factor = u64::from(secrets_page().tsc_factor());
+    khz - (khz * factor) / 100_000
+}
+
+/// Measures the TSC frequency in kHz by counting TSC ticks while PIT
+/// channel 2 counts down for [`CALIBRATE_MS`] milliseconds.
+fn pit_calibrate_tsc_khz(io: &dyn IOPort) -> Result<u64, SvsmError> {
+    let latch = PIT_FREQ_HZ * CALIBRATE_MS / 1000;
+
+    // Enable the channel 2 gate with the speaker turned off
+    let port_b = io.inb(PIT_PORT_B);
+    io.outb(PIT_PORT_B, (port_b & !PORT_B_SPEAKER) | PORT_B_CH2_GATE);
+
+    io.outb(PIT_CMD_PORT, PIT_CMD_CH2_ONESHOT);
+    io.outb(PIT_CH2_PORT, latch as u8);
+    io.outb(PIT_CH2_PORT, (latch >> 8) as u8);
+
+    // Give up when the PIT output does not change in time, which is the
+    // case when there is no PIT at all.
+    let timeout = MAX_TSC_KHZ * CALIBRATE_MS;
+    let start = rdtsc();
+    let mut end = start;
+    while (io.inb(PIT_PORT_B) & PORT_B_CH2_OUT) == 0 && end - start < timeout {
+        spin_loop();
+        end = rdtsc();
+    }
+
+    io.outb(PIT_PORT_B, port_b);
+
+    let khz = (end - start) / CALIBRATE_MS;
+    if !(MIN_TSC_KHZ..MAX_TSC_KHZ).contains(&khz) {
+        log::warn!("TSC calibration against the PIT failed: {} kHz", khz);
+        return Err(SvsmError::Clock);
+    }
+    Ok(khz)
+}
+
+/// Derives the TSC frequency in kHz from the CPUID leaves reporting the TSC
+/// to crystal clock ratio and the processor base frequency.
+///
+/// # Arguments
+///
+/// * `crystal` - Output of CPUID leaf 0x15, if available
+/// * `base` - Output of CPUID leaf 0x16, if available
+///
+/// # Returns
+///
+/// The TSC frequency in kHz, or `None` if the leaves do not report it.
+fn cpuid_leaf_tsc_khz(crystal: Option<CpuidResult>, base: Option<CpuidResult>) -> Option<u64> {
+    // EBX/EAX is the TSC to crystal clock ratio, ECX the crystal frequency
+    // in Hz
+    let from_crystal = crystal
+        .filter(|res| res.eax != 0 && res.ebx != 0 && res.ecx != 0)
+        .map(|res| u64::from(res.ecx) * u64::from(res.ebx) / u64::from(res.eax) / 1000);
+
+    // The TSC runs at the base frequency, reported in MHz in EAX[15:0]
+    from_crystal
+        .or_else(|| base.map(|res| u64::from(res.eax & 0xffff) * 1000))
+        .filter(|khz| (MIN_TSC_KHZ..MAX_TSC_KHZ).contains(khz))
+}
+
+/// Returns the TSC frequency in kHz reported by the hypervisor timing leaf
+/// of the CPUID table, if present.
+fn hypervisor_tsc_khz() -> Option<u64> {
+    cpuid_table(CPUID_HV_MAX)
+        .filter(|res| res.eax >= CPUID_HV_TIMING)
+        .and_then(|_| cpuid_table(CPUID_HV_TIMING))
+        .map(|res| u64::from(res.eax))
+        .filter(|khz| (MIN_TSC_KHZ..MAX_TSC_KHZ).contains(khz))
+}
+
+/// Determines the TSC frequency when it can not be calibrated against the
+/// PIT. Falls back to [`FALLBACK_TSC_KHZ`] when neither CPUID nor the
+/// hypervisor report the frequency, in which case time runs inaccurately.
+fn fallback_tsc_khz() -> (u64, &'static str) {
+    if let Some(khz) =
+        cpuid_leaf_tsc_khz(cpuid_table(CPUID_TSC_CRYSTAL), cpuid_table(CPUID_PROC_FREQ))
+    {
+        (khz, "CPUID")
+    } else if let Some(khz) = hypervisor_tsc_khz() {
+        (khz, "hypervisor")
+    } else {
+        log::warn!("TSC frequency unknown - time keeping will be inaccurate");
+        (FALLBACK_TSC_KHZ, "default")
+    }
+}
+
+/// Determines the TSC frequency and starts the monotonic clock. Must be
+/// called once on the BSP before other CPUs are started. Until then,
+/// [`Instant::now()`] always returns time zero.
+///
+/// Without Secure TSC the frequency is calibrated against the PIT. When
+/// that fails, it is taken from CPUID or the hypervisor instead.
+pub fn clock_init() {
+    let (tsc_khz, source) = if sev_flags().contains(SEVStatusFlags::SECURE_TSC) {
+        (secure_tsc_khz(), "Secure TSC")
+    } else {
+        match pit_calibrate_tsc_khz(&SVSMIOPort::new()) {
+            Ok(khz) => (khz, "PIT"),
+            Err(_) => fallback_tsc_khz(),
+        }
+    };
+
+    CLOCK.reinit(&ClockSource {
+        tsc_khz,
+        tsc_base: rdtsc(),
+    });
+
+    log::info!(
+        "TSC frequency: {}.{:03} MHz ({})",
+        tsc_khz / 1000,
+        tsc_khz % 1000,
+        source
+    );
+}
+
+/// Returns the TSC frequency in kHz, or zero if the clock is not initialized
+/// yet.
+pub fn tsc_khz() -> u64 {
+    CLOCK.tsc_khz
+}
+
+/// A point in time of the monotonic clock, with nanosecond resolution.
+/// Time zero is the initialization of the clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// Returns the current time.
+    pub fn now() -> Self {
+        let clock = *CLOCK;
+        if clock.tsc_khz == 0 {
+            return Self(0);
+        }
+
+        let ticks = rdtsc().saturating_sub(clock.tsc_base);
+        let nsecs = u128::from(ticks) * u128::from(NSEC_PER_MSEC) / u128::from(clock.tsc_khz);
+        Self(nsecs.try_into().unwrap_or(u64::MAX))
+    }
+
+    /// Creates an instant `nsecs` nanoseconds after time zero.
+    pub const fn from_nanos(nsecs: u64) -> Self {
+        Self(nsecs)
+    }
+
+    /// Returns the nanoseconds passed between time zero and this instant.
+    pub const fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns the time passed since `earlier`, or a zero duration if
+    /// `earlier` is later than this instant.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time passed since this instant.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+/// Adding a duration saturates at the end of time, so that very long
+/// timeouts can be expressed with [`Duration::MAX`].
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        let nsecs = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        Instant(self.0.saturating_add(nsecs))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Busy-waits for `duration`. Returns immediately if the clock is not
+/// initialized.
+pub fn delay(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while tsc_khz() != 0 && Instant::now() < deadline {
+        spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instant_arithmetic() {
+        let t = Instant::from_nanos(NSEC_PER_SEC);
+        assert_eq!(
+            (t + Duration::from_millis(5)).as_nanos(),
+            NSEC_PER_SEC + 5 * NSEC_PER_MSEC
+        );
+        assert_eq!((t + Duration::from_millis(5)) - t, Duration::from_millis(5));
+        assert_eq!(Instant::from_nanos(0) - t, Duration::ZERO);
+        assert_eq!((t + Duration::MAX).as_nanos(), u64::MAX);
+    }
+
+    fn cpuid_result(eax: u32, ebx: u32, ecx: u32) -> Option<CpuidResult> {
+        Some(CpuidResult {
+            eax,
+            ebx,
+            ecx,
+            edx: 0,
+        })
+    }
+
+    #[test]
+    fn test_cpuid_tsc_khz() {
+        // 25 MHz crystal with a ratio of 88/2
+        assert_eq!(
+            cpuid_leaf_tsc_khz(cpuid_result(2, 88, 25_000_000), None),
+            Some(1_100_000)
+        );
+        // Crystal frequency not reported, use the base frequency
+        assert_eq!(
+            cpuid_leaf_tsc_khz(cpuid_result(2, 88, 0), cpuid_result(2100, 0, 0)),
+            Some(2_100_000)
+        );
+        assert_eq!(
+            cpuid_leaf_tsc_khz(None, cpuid_result(2400, 0, 0)),
+            Some(2_400_000)
+        );
+        assert_eq!(cpuid_leaf_tsc_khz(None, cpuid_result(0, 0, 0)), None);
+        assert_eq!(cpuid_leaf_tsc_khz(None, None), None);
+    }
+}
@@ -0,0 +1,18 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+//! Time keeping for the SVSM kernel.
+//!
+//! The monotonic clock is based on the TSC. Its frequency is taken from the
+//! Secure TSC interface when the guest runs with SEV-SNP Secure TSC, and
+//! calibrated against the PIT otherwise. Without a PIT it is taken from
+//! CPUID or the hypervisor. Timers are kept in per-CPU [`TimerWheel`]s with
+//! a resolution of [`TIMER_TICK_NS`].
+
+mod clock;
+mod timer;
+
+pub use clock::{clock_init, delay, tsc_khz, Instant};
+pub use core::time::Duration;
+pub use timer::{TimerWheel, TIMER_TICK_NS};
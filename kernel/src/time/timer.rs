@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2026 The COCONUT-SVSM Authors
+
+extern crate alloc;
+
+use super::Instant;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+
+/// Resolution of timers in nanoseconds
+pub const TIMER_TICK_NS: u64 = 1_000_000;
+
+/// Number of slots in a [`TimerWheel`]. Timers further in the future than
+/// the number of slots wrap around and stay in their slot until they expire.
+const WHEEL_SLOTS: usize = 256;
+
+#[derive(Debug)]
+struct TimerEntry<T> {
+    /// Tick at which the timer expires
+    expires: u64,
+    /// Data handed back when the timer expires
+    data: T,
+}
+
+/// A hashed timer wheel. Timers are sorted into slots by their expiry tick,
+/// so that expiring timers only requires looking at the slots of the ticks
+/// which passed since the last call to [`TimerWheel::expire()`].
+///
+/// Timers never expire early, but can expire up to one tick late.
+#[derive(Debug)]
+pub struct TimerWheel<T> {
+    /// Slots with pending timers, allocated when the first timer is added
+    slots: Vec<Vec<TimerEntry<T>>>,
+    /// Last tick for which expired timers were collected
+    last_tick: u64,
+    /// Number of pending timers
+    pending: usize,
+}
+
+impl<T> TimerWheel<T> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            last_tick: 0,
+            pending: 0,
+        }
+    }
+
+    /// Adds a timer which expires at `deadline`.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Time at which the timer expires
+    /// * `data` - Data returned from [`TimerWheel::expire()`] when the
+    ///   timer expired
+    pub fn add(&mut self, deadline: Instant, data: T) {
+        if self.slots.is_empty() {
+            self.slots.resize_with(WHEEL_SLOTS, Vec::new);
+        }
+
+        // Round up so that the timer does not expire early. Timers in the
+        // past expire on the next call to expire().
+        let tick = max(
+            deadline.as_nanos().div_ceil(TIMER_TICK_NS),
+            self.last_tick + 1,
+        );
+        let slot = (tick % WHEEL_SLOTS as u64) as usize;
+        self.slots[slot].push(TimerEntry {
+            expires: tick,
+            data,
+        });
+        self.pending += 1;
+    }
+
+    /// Returns the number of pending timers.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Removes all timers which expired at time `now` from the wheel.
+    ///
+    /// # Returns
+    ///
+    /// The data of the expired timers.
+    pub fn expire(&mut self, now: Instant) -> Vec<T> {
+        let now_tick = now.as_nanos() / TIMER_TICK_NS;
+        let mut expired = Vec::new();
+        if now_tick <= self.last_tick {
+            return expired;
+        }
+
+        if self.pending != 0 {
+            // Every slot needs to be looked at only once, even when more
+            // ticks passed than there are slots.
+            let ticks = min(now_tick - self.last_tick, WHEEL_SLOTS as u64);
+            for tick in self.last_tick + 1..=self.last_tick + ticks {
+                let slot = &mut self.slots[(tick % WHEEL_SLOTS as u64) as usize];
+                let mut idx = 0;
+                while idx < slot.len() {
+                    if slot[idx].expires <= now_tick {
+                        expired.push(slot.swap_remove(idx).data);
+                    } else {
+                        idx += 1;
+                    }
+                }
+            }
+            self.pending -= expired.len();
+        }
+
+        self.last_tick = now_tick;
+        expired
+    }
+}
+
+impl<T> Default for TimerWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_ms(ms: u64) -> Instant {
+        Instant::from_nanos(ms * TIMER_TICK_NS)
+    }
+
+    #[test]
+    fn test_timer_wheel_expire() {
+        let mut wheel = TimerWheel::new();
+        wheel.add(at_ms(10), 1);
+        wheel.add(at_ms(20), 2);
+        wheel.add(at_ms(10 + WHEEL_SLOTS as u64), 3);
+        assert_eq!(wheel.pending(), 3);
+
+        assert!(wheel.expire(at_ms(9)).is_empty());
+        assert_eq!(wheel.expire(at_ms(10)), [1]);
+        assert_eq!(wheel.expire(at_ms(25)), [2]);
+        assert_eq!(wheel.pending(), 1);
+
+        // Long gaps between calls still expire every timer
+        assert_eq!(wheel.expire(at_ms(10_000)), [3]);
+        assert_eq!(wheel.pending(), 0);
+    }
+
+    #[test]
+    fn test_timer_wheel_past_deadline() {
+        let mut wheel = TimerWheel::new();
+        assert!(wheel.expire(at_ms(100)).is_empty());
+
+        // Timers in the past expire on the next tick
+        wheel.add(at_ms(50), 1);
+        assert!(wheel.expire(at_ms(100)).is_empty());
+        assert_eq!(wheel.expire(at_ms(101)), [1]);
+    }
+}
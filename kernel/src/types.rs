@@ -10,6 +10,7 @@ pub const PAGE_SHIFT: usize = 12;
 pub const PAGE_SHIFT_2M: usize = 21;
 pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
 pub const PAGE_SIZE_2M: usize = PAGE_SIZE * 512;
+pub const PAGE_SIZE_1G: usize = PAGE_SIZE_2M * 512;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PageSize {
@@ -29,8 +30,9 @@ impl From<PageSize> for usize {
 #[allow(clippy::identity_op)]
 pub const SVSM_CS: u16 = 1 * 8;
 pub const SVSM_DS: u16 = 2 * 8;
-pub const SVSM_USER_CS: u16 = 3 * 8;
-pub const SVSM_USER_DS: u16 = 4 * 8;
+// SYSRET expects the user data segment right before the user code segment
+pub const SVSM_USER_DS: u16 = 3 * 8;
+pub const SVSM_USER_CS: u16 = 4 * 8;
 pub const SVSM_TSS: u16 = 6 * 8;
 
 pub const SVSM_CS_FLAGS: u16 = 0x29b;
@@ -47,6 +47,30 @@ pub fn zero_mem_region(start: VirtAddr, end: VirtAddr) {
     unsafe { start.as_mut_ptr::<u8>().write_bytes(0, size) }
 }
 
+/// Zeroes a region of whole pages with `rep stosq`. This is considerably
+/// faster than [`zero_mem_region()`] for large regions.
+///
+/// # Panics
+///
+/// Panics if the region is not page aligned or starts at NULL.
+pub fn zero_mem_pages(start: VirtAddr, end: VirtAddr) {
+    assert!(start.is_page_aligned() && end.is_page_aligned());
+    if start.is_null() {
+        panic!("Attempted to zero out a NULL pointer");
+    }
+
+    let count = (end - start) / 8;
+    // SAFETY: the caller owns the region, which is page aligned so that the
+    // stores never go beyond it.
+    unsafe {
+        asm!("rep stosq",
+             inout("rcx") count => _,
+             inout("rdi") start.bits() => _,
+             in("rax") 0u64,
+             options(att_syntax, nostack, preserves_flags));
+    }
+}
+
 /// Obtain bit for a given position
 #[macro_export]
 macro_rules! BIT {
@@ -96,4 +120,18 @@ mod tests {
             assert_eq!(*byte, 0);
         }
     }
+
+    #[test]
+    fn test_zero_mem_pages() {
+        #[repr(C, align(4096))]
+        struct Pages([u8; 3 * PAGE_SIZE]);
+
+        let mut pages = Pages([0xaa; 3 * PAGE_SIZE]);
+        let start = VirtAddr::from(pages.0.as_mut_ptr());
+
+        zero_mem_pages(start, start + 2 * PAGE_SIZE);
+
+        assert!(pages.0[..2 * PAGE_SIZE].iter().all(|b| *b == 0));
+        assert!(pages.0[2 * PAGE_SIZE..].iter().all(|b| *b == 0xaa));
+    }
 }